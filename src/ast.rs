@@ -1,68 +1,264 @@
-use crate::ty::Type;
+use crate::{
+    ty::Type,
+    visit::{
+        walk_arm, walk_block, walk_definition, walk_expr, walk_fn_def, walk_global, walk_static,
+        Visitor,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write};
 
-#[derive(Debug)]
+/// constructing ASTs directly, for tests that would rather not go through the parser,
+/// e.g. `build::fn_def("main").ret(Type::I64).block(vec![b.number(42)])`
+#[cfg(test)]
+pub mod build;
+/// comparing and hashing ASTs as trees, which the derived impls on nodes don't,
+/// as they compare children by id
+pub mod structural;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Ast {
+    /// every expression of the program
+    pub arena: Arena<Expr>,
     pub node: Global,
+    /// comments before each statement that has any, by the statement
+    pub comments: BTreeMap<NodeId, Vec<Comment>>,
+    /// source span of each expression that was parsed rather than generated,
+    /// only meaningful with the `SourceMap` it was parsed from
+    #[serde(skip)]
+    pub spans: BTreeMap<NodeId, (usize, usize)>,
+}
+
+/// index of a node in its `Arena`, identifying it for as long as the arena lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct NodeId(usize);
+
+/// owns nodes allocated one after another, which refer to each other by `NodeId`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Arena<T> {
+    nodes: Vec<T>,
 }
 
-#[derive(Debug)]
+impl<T> Arena<T> {
+    pub const fn new() -> Self {
+        Self { nodes: vec![] }
+    }
+
+    pub fn alloc(&mut self, node: T) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// the id the next node allocated will get
+    pub fn next_id(&self) -> NodeId {
+        NodeId(self.nodes.len())
+    }
+
+    /// ids of the nodes allocated since `start` was the next id, in order
+    pub fn ids_since(&self, start: NodeId) -> impl Iterator<Item = NodeId> {
+        (start.0..self.nodes.len()).map(NodeId)
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> std::ops::Index<NodeId> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId) -> &T {
+        &self.nodes[id.0]
+    }
+}
+
+impl<T> std::ops::IndexMut<NodeId> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId) -> &mut T {
+        &mut self.nodes[id.0]
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Global {
-    pub definitions: Vec<FnDef>,
+    pub definitions: Vec<Definition>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Definition {
+    Fn(FnDef),
+    Static(Static),
+    Extern(Extern),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FnDef {
+    /// comments before the fn, or before its attributes
+    pub comments: Vec<Comment>,
+    pub attrs: Vec<Attribute>,
     pub name: String,
     pub args: Vec<Local>,
     pub fn_type: Type,
     pub body: Block,
 }
 
-#[derive(Debug)]
+/// `// ..`, `/* .. */` or `/// ..` as written, except for `///` itself
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Comment {
+    pub text: String,
+    pub doc: bool,
+}
+
+/// `#[name]` or `#[name(arg, ..)]` before an item, kept even if unknown
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// lives for the whole program, at a fixed address instead of on the frame
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Static {
+    pub name: String,
+    pub ty: Type,
+    /// must be a constant, it is written into the binary
+    pub init: NodeId,
+}
+
+/// `extern fn name(a: T, ...) -> U;`, defined outside and called with the C ABI
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Extern {
+    pub name: String,
+    /// takes any number of arguments after the fixed ones, like `printf`
+    pub variadic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Expr {
     Stmt(Stmt),
     Block(Block),
     Return(Return),
     Loop(Loop),
+    While(While),
+    For(For),
+    Break(Break),
+    Continue(Continue),
+    Defer(Defer),
     IfElse(IfElse),
+    Match(Match),
     FnCall(FnCall),
+    Call(Call),
+    Closure(Closure),
     Init(Init),
     Assign(Assign),
     BinOp(BinOp),
     UnOp(UnOp),
     Enclosed(Enclosed),
+    Cast(Cast),
+    Index(Index),
+    Field(Field),
+    Array(Array),
+    Tuple(Tuple),
+    Struct(Struct),
+    Variant(Variant),
     Bool(Bool),
     Local(Local),
     Number(Number),
+    Float(Float),
+    Char(Char),
+    Str(Str),
 }
 
 impl Expr {
-    pub fn ty(&self) -> Type {
-        match self {
-            Expr::Stmt(_) => Type::Void,
-            Expr::Block(Block { exprs }) => {
-                exprs.last().map_or(Type::Void, |last_expr| last_expr.ty())
-            }
+    /// type of the expression, its children looked up in `arena`
+    pub fn ty(&self, arena: &Arena<Expr>) -> Type {
+        self.checked_ty(arena).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// type of the expression, or why it or any of its children is ill-typed
+    pub fn checked_ty(&self, arena: &Arena<Expr>) -> Result<Type, String> {
+        let error = std::cell::RefCell::new(None);
+        let res = self.try_ty(&|id| {
+            arena[*id].checked_ty(arena).unwrap_or_else(|e| {
+                error.borrow_mut().get_or_insert(e);
+                Type::Unknown
+            })
+        });
+        match error.into_inner() {
+            Some(e) => Err(e),
+            None => res,
+        }
+    }
+
+    /// type of the expression if it can be told from its children alone,
+    /// `Unknown` if that is left to `typeck`
+    pub fn infer(&self, arena: &Arena<Expr>) -> Type {
+        self.try_ty(&|id| arena[*id].infer(arena))
+            .unwrap_or(Type::Unknown)
+    }
+
+    /// type of the expression given those of its children, or why it is ill-typed
+    pub fn try_ty(&self, ty_of: &dyn Fn(&NodeId) -> Type) -> Result<Type, String> {
+        let block_ty = |block: &Block| block.exprs.last().map_or(Type::Void, ty_of);
+        let res = match self {
+            // `return x;` still diverges
+            Expr::Stmt(Stmt { expr }) => match ty_of(expr) {
+                Type::Never => Type::Never,
+                _ => Type::Void,
+            },
+            Expr::Block(block) => block_ty(block),
             Expr::Return(_) => Type::Never,
-            Expr::Loop(_) => Type::Never,
+            Expr::Loop(Loop { ty, .. }) => ty.clone(),
+            Expr::While(_) => Type::Void,
+            Expr::For(_) => Type::Void,
+            Expr::Break(_) | Expr::Continue(_) => Type::Never,
+            Expr::Defer(_) => Type::Void,
             Expr::IfElse(IfElse {
-                cond: _,
-                if_body,
-                else_body: _,
-            }) => if_body
-                .exprs
-                .last()
-                .map_or(Type::Void, |last_expr| last_expr.ty()),
-            Expr::FnCall(FnCall { fn_type, .. }) => {
-                if let Type::Fn { ret, .. } = fn_type {
-                    *ret.clone()
-                } else {
-                    panic!("function's type must be Fn")
+                if_body, else_body, ..
+            }) => match else_body {
+                Some(else_body) => match (block_ty(if_body), block_ty(else_body)) {
+                    (Type::Never, ty) | (ty, Type::Never) => ty,
+                    (a, b) if a == b => a,
+                    (a, b) => {
+                        return Err(format!(
+                            "`if` and `else` have incompatible types {:?} and {:?}",
+                            a, b
+                        ))
+                    }
+                },
+                // evaluates to nothing when the condition does not hold
+                None => Type::Void,
+            },
+            Expr::Match(Match { arms, .. }) => {
+                let mut res = Type::Never;
+                for ty in arms.iter().map(|x| ty_of(&x.body)) {
+                    match (&res, &ty) {
+                        (_, Type::Never) => {}
+                        (Type::Never, _) => res = ty,
+                        (a, b) if a == b => {}
+                        (a, b) => {
+                            return Err(format!(
+                                "`match` arms have incompatible types {:?} and {:?}",
+                                a, b
+                            ))
+                        }
+                    }
                 }
+                res
             }
+            Expr::FnCall(FnCall { fn_type, name, .. }) => match fn_type {
+                Type::Fn { ret, .. } => *ret.clone(),
+                _ => return Err(format!("cannot find function `{}`", name)),
+            },
+            Expr::Call(Call { callee, .. }) => match ty_of(callee) {
+                Type::Fn { ret, .. } => *ret,
+                ty => return Err(format!("cannot call {:?}", ty)),
+            },
+            Expr::Closure(Closure { ty, .. }) => ty.clone(),
             Expr::Init(_) => Type::Void,
-            Expr::Assign(_) => Type::Void,
-            Expr::BinOp(BinOp { op, lhs, rhs }) => match (op, lhs.ty(), rhs.ty()) {
+            Expr::Assign(Assign { lhs, .. }) => ty_of(lhs),
+            Expr::BinOp(BinOp { op, lhs, rhs }) => match (op, ty_of(lhs), ty_of(rhs)) {
                 (
                     BinOpKind::Add
                     | BinOpKind::Sub
@@ -71,10 +267,12 @@ impl Expr {
                     | BinOpKind::Rem
                     | BinOpKind::BitAnd
                     | BinOpKind::BitOr
-                    | BinOpKind::BitXor,
-                    Type::I64,
-                    Type::I64,
-                ) => Type::I64,
+                    | BinOpKind::BitXor
+                    | BinOpKind::Shl
+                    | BinOpKind::Shr,
+                    a,
+                    b,
+                ) if a == b && a.is_integer() => a,
                 (
                     BinOpKind::LeEq
                     | BinOpKind::Le
@@ -86,85 +284,268 @@ impl Expr {
                     b,
                 ) if a == b => Type::Bool,
                 (BinOpKind::LogAnd | BinOpKind::LogOr, Type::Bool, Type::Bool) => Type::Bool,
+                (
+                    BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul | BinOpKind::Div,
+                    Type::F64,
+                    Type::F64,
+                ) => Type::F64,
+                // evaluate both sides, unlike `&&` and `||`
+                (
+                    BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor,
+                    Type::Bool,
+                    Type::Bool,
+                ) => Type::Bool,
                 (BinOpKind::Add | BinOpKind::Sub, Type::Ptr { to }, Type::I64) => Type::Ptr { to },
                 (BinOpKind::Add, Type::Array { element, .. }, Type::I64) => {
                     Type::Ptr { to: element }
                 }
-                _ => panic!("{:?} is not defined between {:?} and {:?}", op, lhs, rhs),
+                (op, lhs, rhs) => {
+                    return Err(format!(
+                        "{:?} is not defined between {:?} and {:?}",
+                        op, lhs, rhs
+                    ))
+                }
             },
             Expr::UnOp(UnOp { kind, expr }) => match kind {
-                UnOpKind::Neg => expr.ty(),
+                UnOpKind::Neg => match ty_of(expr) {
+                    ty if ty.is_signed() || ty == Type::F64 => ty,
+                    ty => return Err(format!("cannot apply `-` to {:?}", ty)),
+                },
+                UnOpKind::Not => match ty_of(expr) {
+                    Type::Bool => Type::Bool,
+                    ty => return Err(format!("cannot apply `!` to {:?}", ty)),
+                },
+                UnOpKind::BitNot => match ty_of(expr) {
+                    ty if ty.is_integer() => ty,
+                    ty => return Err(format!("cannot apply `~` to {:?}", ty)),
+                },
                 UnOpKind::Ref => Type::Ptr {
-                    to: Box::new(expr.ty()),
+                    to: Box::new(ty_of(expr)),
                 },
-                UnOpKind::Deref => match expr.ty() {
+                UnOpKind::Deref => match ty_of(expr) {
                     Type::Ptr { to } => *to,
                     Type::Array { element, .. } => *element,
-                    _ => panic!("only pointer type can be dereferenced"),
+                    _ => return Err("only pointer type can be dereferenced".to_string()),
                 },
             },
-            Expr::Enclosed(Enclosed { expr }) => expr.ty(),
+            Expr::Enclosed(Enclosed { expr }) => ty_of(expr),
+            Expr::Cast(Cast { expr, ty }) => match (ty_of(expr), ty) {
+                (from, to) if from == *to => from,
+                // truncated or extended to the size of `to`
+                (from, to) if from.is_integer() && to.is_integer() => to.clone(),
+                (Type::Bool | Type::Char | Type::Ptr { .. }, to) if to.is_integer() => to.clone(),
+                (from, Type::Char) if from.is_integer() => Type::Char,
+                // rounded to the nearest, or truncated toward zero
+                (from, Type::F64) if from.is_integer() => Type::F64,
+                (Type::F64, to) if to.is_integer() => to.clone(),
+                // the tag of a C-like enum
+                (from @ Type::Enum { .. }, to) if !from.is_aggregate() && to.is_integer() => {
+                    to.clone()
+                }
+                (Type::I64 | Type::U64 | Type::Ptr { .. }, Type::Ptr { .. }) => ty.clone(),
+                (from, to) => return Err(format!("cannot cast {:?} as {:?}", from, to)),
+            },
+            Expr::Index(Index { base, index }) => {
+                if !ty_of(index).is_integer() {
+                    return Err("index must be an integer".to_string());
+                }
+                match ty_of(base) {
+                    Type::Array { element, .. } => *element,
+                    Type::Ptr { to } => *to,
+                    Type::Str => Type::Char,
+                    ty => return Err(format!("cannot index into {:?}", ty)),
+                }
+            }
+            Expr::Field(Field { base, name }) => ty_of(base).field(name)?.1,
+            Expr::Tuple(Tuple { elements }) => Type::Tuple {
+                elements: elements.iter().map(ty_of).collect(),
+            },
+            Expr::Struct(Struct { ty, .. }) => ty.clone(),
+            Expr::Variant(Variant { ty, .. }) => ty.clone(),
+            Expr::Array(Array { elements }) => {
+                let Some(first) = elements.first() else {
+                    return Err("cannot infer the type of an empty array".to_string());
+                };
+                let element = ty_of(first);
+                if elements.iter().any(|x| ty_of(x) != element) {
+                    return Err("mismatched types of array elements".to_string());
+                }
+                Type::Array {
+                    element: Box::new(element),
+                    len: elements.len(),
+                }
+            }
             Expr::Bool(..) => Type::Bool,
             Expr::Local(Local { ty, .. }) => ty.clone(),
             Expr::Number(..) => Type::I64,
-        }
+            Expr::Float(..) => Type::F64,
+            Expr::Char(..) => Type::Char,
+            Expr::Str(_) => Type::Str,
+        };
+        Ok(res)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Stmt {
-    pub expr: Box<Expr>,
+    pub expr: NodeId,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Block {
-    pub exprs: Vec<Expr>,
+    pub exprs: Vec<NodeId>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Return {
-    pub expr: Box<Expr>,
+    pub expr: NodeId,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Loop {
+    pub label: Option<String>,
+    pub body: Block,
+    /// type of the values broken out with, `Never` if it is never broken out of
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct While {
+    pub label: Option<String>,
+    pub cond: NodeId,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct For {
+    pub label: Option<String>,
+    /// induction variable, bound for the body only
+    pub var: Local,
+    pub range: Range,
     pub body: Block,
 }
 
-#[derive(Debug)]
+/// `start..end`, end exclusive
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Range {
+    pub start: NodeId,
+    pub end: NodeId,
+}
+
+/// jumps out of the innermost loop or the one labeled `label`,
+/// which evaluates to `expr` if any
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Break {
+    pub label: Option<String>,
+    pub expr: Option<NodeId>,
+}
+
+/// jumps to the next iteration of the innermost loop or the one labeled `label`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Continue {
+    pub label: Option<String>,
+}
+
+/// `defer expr`, running `expr` when the enclosing block is left,
+/// rewritten away before codegen
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Defer {
+    pub expr: NodeId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IfElse {
-    pub cond: Box<Expr>,
+    pub cond: NodeId,
     pub if_body: Block,
     pub else_body: Option<Block>,
 }
 
-#[derive(Debug)]
+/// `match scrutinee { pat => body, .. }`, arms are tried in order
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Match {
+    pub scrutinee: NodeId,
+    pub arms: Vec<Arm>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Arm {
+    pub pat: Pattern,
+    pub body: NodeId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// a name binding the whole value
+    Binding(Local),
+    /// integer literal, possibly negative
+    Number(i64),
+    Bool(bool),
+    /// `Enum::Name`, or `Enum::Name(x)` binding the payload
+    Variant {
+        index: usize,
+        binding: Option<Local>,
+    },
+}
+
+impl Pattern {
+    /// local bound in the arm, if any
+    pub fn binding(&self) -> Option<&Local> {
+        match self {
+            Pattern::Variant { binding, .. } => binding.as_ref(),
+            Pattern::Binding(local) => Some(local),
+            Pattern::Wildcard | Pattern::Number(_) | Pattern::Bool(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FnCall {
     pub name: String,
-    pub args: Vec<Expr>,
+    pub args: Vec<NodeId>,
     pub fn_type: Type,
 }
 
-#[derive(Debug)]
+/// call through a value of fn type, passing the environment it points to first
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Call {
+    pub callee: NodeId,
+    pub args: Vec<NodeId>,
+}
+
+/// `|x| body`, evaluating to the address of its environment on the frame:
+/// the code of the lifted fn `name`, followed by the captured values in order
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Closure {
+    pub name: String,
+    pub captures: Vec<NodeId>,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Init {
-    pub name: Box<Expr>,
-    pub value: Option<Box<Expr>>,
+    pub name: NodeId,
+    pub value: Option<NodeId>,
+    /// `let mut`, which can be assigned to again
+    pub mutable: bool,
 }
 
-#[derive(Debug)]
+/// evaluates to the value stored, so that `a = b = c` assigns both
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Assign {
-    pub lhs: Box<Expr>,
-    pub rhs: Box<Expr>,
+    pub lhs: NodeId,
+    pub rhs: NodeId,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BinOp {
     pub op: BinOpKind,
-    pub lhs: Box<Expr>,
-    pub rhs: Box<Expr>,
+    pub lhs: NodeId,
+    pub rhs: NodeId,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BinOpKind {
     Eq,
     Neq,
@@ -185,42 +566,241 @@ pub enum BinOpKind {
     BitOr,
     BitXor,
 
+    Shl,
+    /// arithmetic, keeps the sign
+    Shr,
+
     LogAnd,
     LogOr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct UnOp {
     pub kind: UnOpKind,
-    pub expr: Box<Expr>,
+    pub expr: NodeId,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum UnOpKind {
     Neg,
+    /// logical, on bool only
+    Not,
+    /// bitwise, on i64 only
+    BitNot,
     Ref,
     Deref,
 }
 
-#[derive(Debug)]
-
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Enclosed {
-    pub expr: Box<Expr>,
+    pub expr: NodeId,
+}
+
+/// `expr as ty`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cast {
+    pub expr: NodeId,
+    pub ty: Type,
+}
+
+/// `base[index]`, on arrays and pointers
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Index {
+    pub base: NodeId,
+    pub index: NodeId,
+}
+
+/// `base.name`, or `base.0` on tuples
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Field {
+    pub base: NodeId,
+    pub name: String,
+}
+
+/// `[a, b, c]`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Array {
+    pub elements: Vec<NodeId>,
+}
+
+/// `(a, b)`, or `(a,)` with a single element
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tuple {
+    pub elements: Vec<NodeId>,
+}
+
+/// `Name { field: value, .. }`, every field given exactly once
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Struct {
+    pub ty: Type,
+    pub fields: Vec<(String, NodeId)>,
+}
+
+/// `Enum::Name`, or `Enum::Name(payload)`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Variant {
+    pub ty: Type,
+    pub index: usize,
+    pub payload: Option<NodeId>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Bool {
     True,
     False,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Local {
     pub name: String,
     pub ty: Type,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Number {
     pub value: String,
 }
+
+/// a byte, as only ASCII literals fit in it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Char {
+    pub value: u8,
+}
+
+/// kept as written, so that it compares and hashes like the other nodes
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Float {
+    pub value: String,
+}
+
+/// `"..."`, a pointer to its first byte, NUL-terminated in .rodata
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Str {
+    pub value: String,
+}
+
+/// the tree as a Graphviz digraph, each node labeled with its kind and name or value if any
+pub fn to_dot(ast: &Ast) -> String {
+    let mut dot = Dot {
+        out: String::from("digraph ast {\n    node [shape=box];\n"),
+        parents: vec![],
+        count: 0,
+    };
+    dot.visit_ast(ast);
+    dot.out += "}\n";
+    dot.out
+}
+
+struct Dot {
+    out: String,
+    /// ids of the nodes entered so far, innermost last
+    parents: Vec<usize>,
+    count: usize,
+}
+
+impl Dot {
+    /// a node under the current one, entered while `walk` visits its children
+    fn node(&mut self, label: String, walk: impl FnOnce(&mut Self)) {
+        let id = self.count;
+        self.count += 1;
+        writeln!(self.out, "    n{} [label={:?}];", id, label).unwrap();
+        if let Some(parent) = self.parents.last() {
+            writeln!(self.out, "    n{} -> n{};", parent, id).unwrap();
+        }
+
+        self.parents.push(id);
+        walk(self);
+        self.parents.pop();
+    }
+}
+
+impl Visitor for Dot {
+    fn visit_global(&mut self, arena: &Arena<Expr>, global: &Global) {
+        self.node("Global".to_string(), |x| walk_global(x, arena, global));
+    }
+
+    fn visit_definition(&mut self, arena: &Arena<Expr>, definition: &Definition) {
+        match definition {
+            Definition::Extern(Extern { name, .. }) => {
+                self.node(format!("Extern {}", name), |_| {})
+            }
+            _ => walk_definition(self, arena, definition),
+        }
+    }
+
+    fn visit_fn_def(&mut self, arena: &Arena<Expr>, f: &FnDef) {
+        self.node(format!("Fn {}", f.name), |x| walk_fn_def(x, arena, f));
+    }
+
+    fn visit_static(&mut self, arena: &Arena<Expr>, s: &Static) {
+        self.node(format!("Static {}", s.name), |x| walk_static(x, arena, s));
+    }
+
+    fn visit_block(&mut self, arena: &Arena<Expr>, block: &Block) {
+        self.node("Block".to_string(), |x| walk_block(x, arena, block));
+    }
+
+    fn visit_arm(&mut self, arena: &Arena<Expr>, arm: &Arm) {
+        let label = match &arm.pat {
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Binding(local) => local.name.clone(),
+            Pattern::Number(value) => value.to_string(),
+            Pattern::Bool(value) => value.to_string(),
+            Pattern::Variant { index, .. } => format!("variant {}", index),
+        };
+        self.node(format!("Arm {}", label), |x| walk_arm(x, arena, arm));
+    }
+
+    fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        let label = match &arena[id] {
+            // already nodes of their own
+            Expr::Block(block) => return self.visit_block(arena, block),
+            Expr::Local(local) => return self.visit_local(local),
+            Expr::Stmt(_) => "Stmt".to_string(),
+            Expr::Return(_) => "Return".to_string(),
+            Expr::Loop(Loop { label, .. }) => labeled("Loop", label),
+            Expr::While(While { label, .. }) => labeled("While", label),
+            Expr::For(For { label, .. }) => labeled("For", label),
+            Expr::Break(Break { label, .. }) => labeled("Break", label),
+            Expr::Continue(Continue { label }) => labeled("Continue", label),
+            Expr::Defer(_) => "Defer".to_string(),
+            Expr::IfElse(_) => "If".to_string(),
+            Expr::Match(_) => "Match".to_string(),
+            Expr::FnCall(FnCall { name, .. }) => format!("Call {}", name),
+            Expr::Call(_) => "Call".to_string(),
+            Expr::Closure(Closure { name, .. }) => format!("Closure {}", name),
+            Expr::Init(_) => "Let".to_string(),
+            Expr::Assign(_) => "Assign".to_string(),
+            Expr::BinOp(BinOp { op, .. }) => format!("{:?}", op),
+            Expr::UnOp(UnOp { kind, .. }) => format!("{:?}", kind),
+            Expr::Enclosed(_) => "Paren".to_string(),
+            Expr::Cast(Cast { ty, .. }) => format!("as {:?}", ty),
+            Expr::Index(_) => "Index".to_string(),
+            Expr::Field(Field { name, .. }) => format!(".{}", name),
+            Expr::Array(_) => "Array".to_string(),
+            Expr::Tuple(_) => "Tuple".to_string(),
+            Expr::Struct(Struct { fields, .. }) => {
+                let names: Vec<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+                format!("Struct {{ {} }}", names.join(", "))
+            }
+            Expr::Variant(Variant { index, .. }) => format!("Variant {}", index),
+            Expr::Bool(Bool::True) => "true".to_string(),
+            Expr::Bool(Bool::False) => "false".to_string(),
+            Expr::Number(Number { value }) | Expr::Float(Float { value }) => value.clone(),
+            Expr::Char(Char { value }) => format!("{:?}", *value as char),
+            Expr::Str(Str { value }) => format!("{:?}", value),
+        };
+        self.node(label, |x| walk_expr(x, arena, id));
+    }
+
+    fn visit_local(&mut self, local: &Local) {
+        self.node(local.name.clone(), |_| {});
+    }
+}
+
+fn labeled(kind: &str, label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("{} '{}", kind, label),
+        None => kind.to_string(),
+    }
+}
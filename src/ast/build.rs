@@ -0,0 +1,145 @@
+use super::{
+    Arena, Assign, Ast, BinOp, BinOpKind, Block, Bool, Definition, Expr, FnCall, FnDef, Global,
+    IfElse, Init, Local, NodeId, Number, Return, Stmt,
+};
+use crate::ty::Type;
+use std::collections::BTreeMap;
+
+/// owns the arena the expressions built are allocated in
+#[derive(Debug, Default)]
+pub struct Builder {
+    arena: Arena<Expr>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self, definitions: Vec<FnDef>) -> Ast {
+        Ast {
+            arena: self.arena,
+            node: Global {
+                definitions: definitions.into_iter().map(Definition::Fn).collect(),
+            },
+            comments: BTreeMap::new(),
+            spans: BTreeMap::new(),
+        }
+    }
+
+    pub fn expr(&mut self, expr: Expr) -> NodeId {
+        self.arena.alloc(expr)
+    }
+
+    pub fn number(&mut self, value: i64) -> NodeId {
+        self.expr(Expr::Number(Number {
+            value: value.to_string(),
+        }))
+    }
+
+    pub fn bool(&mut self, value: bool) -> NodeId {
+        self.expr(Expr::Bool(if value { Bool::True } else { Bool::False }))
+    }
+
+    pub fn local(&mut self, name: &str, ty: Type) -> NodeId {
+        self.expr(Expr::Local(Local {
+            name: name.to_string(),
+            ty,
+        }))
+    }
+
+    /// `let name = value`
+    pub fn init(&mut self, name: &str, value: NodeId) -> NodeId {
+        let ty = self.arena[value].ty(&self.arena);
+        let name = self.local(name, ty);
+        self.expr(Expr::Init(Init {
+            name,
+            value: Some(value),
+            mutable: false,
+        }))
+    }
+
+    pub fn assign(&mut self, lhs: NodeId, rhs: NodeId) -> NodeId {
+        self.expr(Expr::Assign(Assign { lhs, rhs }))
+    }
+
+    pub fn binop(&mut self, op: BinOpKind, lhs: NodeId, rhs: NodeId) -> NodeId {
+        self.expr(Expr::BinOp(BinOp { op, lhs, rhs }))
+    }
+
+    /// call to the fn `name` of `fn_type`
+    pub fn call(&mut self, name: &str, fn_type: Type, args: Vec<NodeId>) -> NodeId {
+        self.expr(Expr::FnCall(FnCall {
+            name: name.to_string(),
+            args,
+            fn_type,
+        }))
+    }
+
+    pub fn if_else(
+        &mut self,
+        cond: NodeId,
+        if_body: Vec<NodeId>,
+        else_body: Vec<NodeId>,
+    ) -> NodeId {
+        self.expr(Expr::IfElse(IfElse {
+            cond,
+            if_body: Block { exprs: if_body },
+            else_body: Some(Block { exprs: else_body }),
+        }))
+    }
+
+    pub fn ret(&mut self, expr: NodeId) -> NodeId {
+        self.expr(Expr::Return(Return { expr }))
+    }
+
+    /// `expr;`
+    pub fn stmt(&mut self, expr: NodeId) -> NodeId {
+        self.expr(Expr::Stmt(Stmt { expr }))
+    }
+}
+
+/// the fn `name`, taking no args and returning void until told otherwise
+pub fn fn_def(name: &str) -> FnDefBuilder {
+    FnDefBuilder {
+        name: name.to_string(),
+        args: vec![],
+        ret: Type::Void,
+    }
+}
+
+#[derive(Debug)]
+pub struct FnDefBuilder {
+    name: String,
+    args: Vec<Local>,
+    ret: Type,
+}
+
+impl FnDefBuilder {
+    pub fn arg(mut self, name: &str, ty: Type) -> Self {
+        self.args.push(Local {
+            name: name.to_string(),
+            ty,
+        });
+        self
+    }
+
+    pub fn ret(mut self, ty: Type) -> Self {
+        self.ret = ty;
+        self
+    }
+
+    pub fn block(self, exprs: Vec<NodeId>) -> FnDef {
+        FnDef {
+            comments: vec![],
+            attrs: vec![],
+            fn_type: Type::Fn {
+                args: self.args.iter().map(|x| x.ty.clone()).collect(),
+                ret: Box::new(self.ret),
+            },
+            name: self.name,
+            args: self.args,
+            body: Block { exprs },
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use super::{Arena, Ast, Expr, FnDef, Global, NodeId};
+use crate::fold::{self, Fold};
+use std::hash::{Hash, Hasher};
+
+/// the expression `id` compared and hashed as a tree, wherever its nodes are allocated,
+/// e.g. as the key of a map from subtrees
+#[derive(Debug, Clone, Copy)]
+pub struct ExprRef<'a> {
+    pub arena: &'a Arena<Expr>,
+    pub id: NodeId,
+}
+
+impl PartialEq for ExprRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, a_children) = shell(self.arena, self.id);
+        let (b, b_children) = shell(other.arena, other.id);
+        a == b && children_eq(self.arena, &a_children, other.arena, &b_children)
+    }
+}
+
+impl Eq for ExprRef<'_> {}
+
+impl Hash for ExprRef<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (shell, children) = shell(self.arena, self.id);
+        shell.hash(state);
+        for id in children {
+            ExprRef {
+                arena: self.arena,
+                id,
+            }
+            .hash(state);
+        }
+    }
+}
+
+/// the same program, whatever the comments and wherever its nodes are allocated
+impl PartialEq for Ast {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, a_children) = global_shell(self);
+        let (b, b_children) = global_shell(other);
+        a == b && children_eq(&self.arena, &a_children, &other.arena, &b_children)
+    }
+}
+
+impl Eq for Ast {}
+
+impl Hash for Ast {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (shell, children) = global_shell(self);
+        shell.hash(state);
+        for id in children {
+            ExprRef {
+                arena: &self.arena,
+                id,
+            }
+            .hash(state);
+        }
+    }
+}
+
+fn children_eq(a_arena: &Arena<Expr>, a: &[NodeId], b_arena: &Arena<Expr>, b: &[NodeId]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(&a, &b)| {
+            ExprRef {
+                arena: a_arena,
+                id: a,
+            } == ExprRef {
+                arena: b_arena,
+                id: b,
+            }
+        })
+}
+
+/// the expression `id` with its children replaced by a placeholder, and the children in order
+fn shell(arena: &Arena<Expr>, id: NodeId) -> (Expr, Vec<NodeId>) {
+    let mut shell = Shell { children: vec![] };
+    // the children are only collected, so never looked up in here
+    let mut scratch = Arena::new();
+    let root = scratch.alloc(arena[id].clone());
+    fold::walk_expr(&mut shell, &mut scratch, root);
+    (fold::take(&mut scratch, root), shell.children)
+}
+
+fn global_shell(ast: &Ast) -> (Global, Vec<NodeId>) {
+    let mut shell = Shell { children: vec![] };
+    let global = shell.fold_global(&mut Arena::new(), ast.node.clone());
+    (global, shell.children)
+}
+
+struct Shell {
+    children: Vec<NodeId>,
+}
+
+impl Fold for Shell {
+    fn fold_fn_def(&mut self, arena: &mut Arena<Expr>, f: FnDef) -> FnDef {
+        fold::walk_fn_def(
+            self,
+            arena,
+            FnDef {
+                comments: vec![],
+                ..f
+            },
+        )
+    }
+
+    fn fold_expr(&mut self, _arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+        self.children.push(id);
+        NodeId(usize::MAX)
+    }
+}
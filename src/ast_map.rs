@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Arena, Ast, Definition, Expr, FnDef, Global, NodeId},
+    visit::{walk_expr, Visitor},
+};
+
+/// what an expression is directly part of
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parent {
+    /// the item at this index of `Global::definitions`
+    Item(usize),
+    Expr(NodeId),
+}
+
+/// lookup of the nodes of an `Ast` and their parents,
+/// built once the AST no longer changes
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct AstMap<'a> {
+    ast: &'a Ast,
+    parents: HashMap<NodeId, Parent>,
+}
+
+#[allow(dead_code)]
+impl<'a> AstMap<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        let mut builder = Builder {
+            parents: HashMap::new(),
+            current: None,
+        };
+        builder.visit_ast(ast);
+        Self {
+            ast,
+            parents: builder.parents,
+        }
+    }
+
+    pub fn get(&self, id: NodeId) -> &'a Expr {
+        &self.ast.arena[id]
+    }
+
+    pub fn item(&self, index: usize) -> &'a Definition {
+        &self.ast.node.definitions[index]
+    }
+
+    /// none for nodes no longer in the tree, like those replaced by a pass
+    pub fn parent(&self, id: NodeId) -> Option<Parent> {
+        self.parents.get(&id).copied()
+    }
+
+    /// the fn whose body `id` is in, closures being fns of their own
+    pub fn enclosing_fn(&self, mut id: NodeId) -> Option<&'a FnDef> {
+        loop {
+            match self.parent(id)? {
+                Parent::Expr(parent) => id = parent,
+                Parent::Item(index) => {
+                    return match self.item(index) {
+                        Definition::Fn(f) => Some(f),
+                        _ => None,
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Builder {
+    parents: HashMap<NodeId, Parent>,
+    /// parent of the expressions visited next
+    current: Option<Parent>,
+}
+
+impl Visitor for Builder {
+    fn visit_global(&mut self, arena: &Arena<Expr>, global: &Global) {
+        for (i, definition) in global.definitions.iter().enumerate() {
+            self.current = Some(Parent::Item(i));
+            self.visit_definition(arena, definition);
+        }
+    }
+
+    fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        self.parents.insert(id, self.current.unwrap());
+        let parent = self.current.replace(Parent::Expr(id));
+        walk_expr(self, arena, id);
+        self.current = parent;
+    }
+}
@@ -1,6 +1,13 @@
+use std::path::{Path, PathBuf};
+
+use crate::manifest;
+
 #[derive(Debug, clap::Parser)]
 #[clap(author, version, about)]
 pub struct SofaC {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// read input from console
     #[clap(short, long, group = "input_type")]
     pub console: Option<String>,
@@ -13,7 +20,131 @@ pub struct SofaC {
     #[clap(short, long, group = "output_type")]
     pub out: Option<String>,
 
+    /// directory to write outputs into, created if missing
+    #[clap(long, conflicts_with = "stdout")]
+    pub out_dir: Option<String>,
+
     /// output to stdout
     #[clap(short, long, group = "output_type")]
     pub stdout: bool,
+
+    /// kind of output to produce, obj and exe are built with gcc,
+    /// ast is the parsed program as JSON and dot as a Graphviz digraph
+    #[clap(long, value_enum, default_value = "asm", conflicts_with = "stdout")]
+    pub emit: Emit,
+
+    /// codegen option, only `link-arg=<ARG>` to pass ARG to the linker for now
+    #[clap(short = 'C', value_name = "OPT", value_parser = codegen_option)]
+    pub codegen: Vec<CodegenOption>,
+
+    /// link a native library, e.g. `-l m`
+    #[clap(short = 'l', long, value_name = "LIB")]
+    pub link_lib: Vec<String>,
+
+    /// emit `.loc` directives mapping the assembly back to the source
+    #[clap(short = 'g')]
+    pub debug_info: bool,
+
+    /// make assignments evaluate to unit instead of the value stored
+    #[clap(long)]
+    pub unit_assign: bool,
+
+    /// report per-function code statistics to stderr
+    #[clap(long, value_enum, min_values = 0, default_missing_value = "text")]
+    pub stats: Option<StatsFormat>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// create a new project
+    Init {
+        /// directory to create the project in
+        path: String,
+    },
+    /// build the project around the current directory and run it
+    Run,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Emit {
+    Asm,
+    Obj,
+    Exe,
+    Ast,
+    Dot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenOption {
+    LinkArg(String),
+}
+
+/// `-C` as `<NAME>=<VALUE>`
+fn codegen_option(s: &str) -> Result<CodegenOption, String> {
+    match s.split_once('=') {
+        Some(("link-arg", arg)) => Ok(CodegenOption::LinkArg(arg.to_string())),
+        _ => Err(format!(
+            "unknown codegen option `{}`, expected `link-arg=<ARG>`",
+            s
+        )),
+    }
+}
+
+impl SofaC {
+    /// arguments forwarded to the link step,
+    /// those of the `[link]` of the project's `sofa.toml` first if there is one
+    pub fn link_args(&self) -> Result<Vec<String>, String> {
+        let mut res = vec![];
+        // console input belongs to the project being worked in
+        let dir = match self.file.as_deref().and_then(|x| Path::new(x).parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => std::env::current_dir()
+                .map_err(|e| format!("cannot read the current directory: {}", e))?,
+        };
+        if let Some(path) = manifest::find(&dir) {
+            let link = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|x| manifest::link(&x))
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            res.extend(link.args);
+            res.extend(link.libs.iter().map(|x| format!("-l{}", x)));
+        }
+        for opt in self.codegen.iter() {
+            match opt {
+                CodegenOption::LinkArg(arg) => res.push(arg.clone()),
+            }
+        }
+        for lib in self.link_lib.iter() {
+            res.push(format!("-l{}", lib));
+        }
+        Ok(res)
+    }
+
+    /// path of an output with extension `ext` (empty for executables),
+    /// derived from the input file unless `--out` is given, e.g. `foo.sofa` -> `foo.s`
+    pub fn output_path(&self, ext: &str) -> PathBuf {
+        let name = match &self.out {
+            Some(out) => PathBuf::from(out),
+            None => {
+                // console input has no name to derive from
+                let stem = self
+                    .file
+                    .as_deref()
+                    .and_then(|x| Path::new(x).file_stem())
+                    .unwrap_or_else(|| "tmp".as_ref());
+                PathBuf::from(stem).with_extension(ext)
+            }
+        };
+
+        match &self.out_dir {
+            Some(dir) => Path::new(dir).join(name),
+            None => name,
+        }
+    }
 }
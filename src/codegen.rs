@@ -1,37 +1,131 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     io::{BufWriter, Write},
 };
 
 use crate::{
     ast::{
-        Assign, Ast, BinOp, BinOpKind, Block, Enclosed, Expr, FnCall, FnDef, Global, IfElse, Init,
-        Loop, Number, Return, Stmt, UnOp, UnOpKind,
+        Arena, Arm, Array, Assign, Ast, BinOp, BinOpKind, Block, Bool, Break, Call, Cast, Char,
+        Closure, Continue, Definition, Enclosed, Expr, Extern, Field, Float, FnCall, FnDef, Global,
+        IfElse, Index, Init, Local, Loop, Match, NodeId, Number, Pattern, Return, Static, Stmt,
+        Str, Struct, Tuple, UnOp, UnOpKind, Variant, While,
     },
+    const_eval,
+    source_map::SourceMap,
     ty::Type,
+    typeck::Types,
 };
 
-const MAX_STACK_SIZE: usize = 8 * 256;
-const ARG_REGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+pub const ARG_REGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+pub const FLOAT_ARG_REGS: [&str; 8] = [
+    "xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7",
+];
+
+/// where the value of an operand lives,
+/// so that trivial operands are used in place instead of going through the stack
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    /// fits in a sign-extended imm32
+    Imm(i64),
+    /// local stored at `rbp - offset`
+    Local(usize),
+    /// pushed onto the stack
+    Stack,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Imm(value) => write!(f, "{}", value),
+            Operand::Local(offset) => write!(f, "QWORD PTR [rbp-{}]", offset),
+            Operand::Stack => panic!("stack operand must be popped first"),
+        }
+    }
+}
 
 #[derive(Debug)]
-pub struct SofaGenerater<W: Write> {
+pub struct SofaGenerater<'a, W: Write> {
     writer: BufWriter<W>,
+    /// expressions of the AST being generated
+    arena: &'a Arena<Expr>,
+    /// their types, as checked by `typeck`
+    types: Types,
+    /// their source spans
+    spans: &'a BTreeMap<NodeId, (usize, usize)>,
+    /// the spans are resolved against, if `.loc` directives are emitted
+    sources: Option<&'a SourceMap>,
+    /// files named by `.file` so far, numbered from 1
+    files: Vec<String>,
     label_id: usize,
+    /// bytes of the frame in use, by the locals in scope and temporaries
     offset: usize,
+    /// the most `offset` has been in the fn, which its frame is allocated with
+    frame_size: usize,
     offset_table: HashMap<String, usize>,
+    /// names of statics, referred to by their labels
+    statics: HashSet<String>,
+    /// extern fns by name, with whether they are variadic
+    externs: HashMap<String, bool>,
+    /// declared return type of the function being generated
+    ret_type: Type,
+    /// enclosing loops, innermost last
+    loops: Vec<LoopCtx>,
+    /// string literals so far, written to .rodata at the end as `.LS{index}`
+    strings: Vec<String>,
+}
+
+/// calling convention of a callee
+#[derive(Debug, Clone, Copy)]
+enum Abi {
+    /// fns written in sofa, which don't need rsp aligned
+    Sofa,
+    /// System V, with rsp aligned to 16 bytes and al set for variadic ones
+    C { variadic: bool },
+}
+
+/// jump targets of an enclosing loop
+#[derive(Debug)]
+struct LoopCtx {
+    /// `'name` given in the source, if any
+    name: Option<String>,
+    continue_label: String,
+    break_label: String,
+    /// offset of the slot holding rsp at loop entry, restored before jumping
+    rsp_offset: usize,
+    /// whether `break` carries a value in rax, only `loop` does
+    with_value: bool,
 }
 
-impl<W: Write> SofaGenerater<W> {
+impl<'a, W: Write> SofaGenerater<'a, W> {
     pub fn new(writer: W) -> Self {
+        static EMPTY: Arena<Expr> = Arena::new();
+        static NO_SPANS: BTreeMap<NodeId, (usize, usize)> = BTreeMap::new();
         Self {
             writer: BufWriter::new(writer),
+            arena: &EMPTY,
+            types: Types::new(),
+            spans: &NO_SPANS,
+            sources: None,
+            files: vec![],
             label_id: 0,
             offset: 0,
+            frame_size: 0,
             offset_table: HashMap::new(),
+            statics: HashSet::new(),
+            externs: HashMap::new(),
+            ret_type: Type::Void,
+            loops: vec![],
+            strings: vec![],
         }
     }
 
+    /// emit a `.loc` before each statement, so debuggers can step through the source
+    pub fn with_debug_info(mut self, sources: &'a SourceMap) -> Self {
+        self.sources = Some(sources);
+        self
+    }
+
     fn gen_header(&mut self) {
         let entry_point = "main";
 
@@ -40,53 +134,167 @@ impl<W: Write> SofaGenerater<W> {
         writeln!(self.writer).unwrap();
     }
 
-    pub fn gen(&mut self, ast: &Ast) {
+    pub fn gen(&mut self, ast: &'a Ast, types: Types) {
+        self.arena = &ast.arena;
+        self.types = types;
+        self.spans = &ast.spans;
         self.gen_header();
 
         self.gen_global(&ast.node);
+        self.gen_strings();
 
         writeln!(self.writer).unwrap();
     }
 
     fn gen_global(&mut self, global: &Global) {
-        for f in global.definitions.iter() {
-            self.gen_fn(f);
-            self.offset = 0;
-            self.offset_table.clear();
+        for definition in global.definitions.iter() {
+            match definition {
+                Definition::Fn(f) => {
+                    self.gen_fn(f);
+                    self.offset = 0;
+                    self.frame_size = 0;
+                    self.offset_table.clear();
+                }
+                Definition::Static(s) => self.gen_static(s),
+                Definition::Extern(Extern { name, variadic, .. }) => {
+                    self.externs.insert(name.clone(), *variadic);
+                }
+            }
         }
     }
 
-    fn gen_fn(&mut self, f: &FnDef) {
-        // stack_size should be a multiple of 16;
-        let (name, stack_size) = if f.name == "main" {
-            ("main".to_string(), MAX_STACK_SIZE)
+    fn gen_strings(&mut self) {
+        if self.strings.is_empty() {
+            return;
+        }
+        writeln!(self.writer, ".section .rodata").unwrap();
+        for (i, s) in self.strings.iter().enumerate() {
+            writeln!(self.writer, ".LS{}:", i).unwrap();
+            writeln!(self.writer, "    .string \"{}\"", escape(s)).unwrap();
+        }
+        writeln!(self.writer, ".text").unwrap();
+    }
+
+    /// zero-initialized statics go to .bss and take no space in the binary
+    fn gen_static(&mut self, Static { name, ty, init }: &Static) {
+        let mut values = vec![];
+        static_values(self.arena, &self.types, *init, &mut values);
+        assert_eq!(
+            values.iter().map(|(size, _)| size).sum::<usize>(),
+            ty.size()
+        );
+
+        if values.iter().all(|&(_, x)| x == 0) {
+            writeln!(self.writer, ".bss").unwrap();
+            writeln!(self.writer, "    .align {}", ty.align()).unwrap();
+            writeln!(self.writer, "{}:", symbol(name)).unwrap();
+            writeln!(self.writer, "    .zero {}", ty.size()).unwrap();
         } else {
-            (f.name.clone(), (f.args.len() + 1) / 2 * 2 * 8)
-        };
+            writeln!(self.writer, ".data").unwrap();
+            writeln!(self.writer, "    .align {}", ty.align()).unwrap();
+            writeln!(self.writer, "{}:", symbol(name)).unwrap();
+            for (size, value) in values {
+                match size {
+                    1 => writeln!(self.writer, "    .byte {}", value).unwrap(),
+                    2 => writeln!(self.writer, "    .short {}", value).unwrap(),
+                    4 => writeln!(self.writer, "    .long {}", value).unwrap(),
+                    8 => writeln!(self.writer, "    .quad {}", value).unwrap(),
+                    // padding
+                    _ => writeln!(self.writer, "    .zero {}", size).unwrap(),
+                }
+            }
+        }
+        writeln!(self.writer, ".text").unwrap();
+        writeln!(self.writer).unwrap();
 
-        self.gen_prologue(&name, stack_size);
+        self.statics.insert(name.clone());
+    }
+
+    fn gen_fn(&mut self, f: &FnDef) {
+        let name = symbol(&f.name);
 
-        if !f.args.is_empty() {
-            writeln!(self.writer, "    mov rax, rbp").unwrap();
+        if let Type::Fn { ret, .. } = &f.fn_type {
+            self.ret_type = *ret.clone();
+        } else {
+            panic!("function's type must be Fn")
         }
-        for (arg, reg) in f.args.iter().zip(ARG_REGS) {
-            let size = arg.ty.size();
-            self.offset += size;
-            self.offset_table.insert(arg.name.clone(), self.offset);
 
-            writeln!(self.writer, "    sub rax, {}", size).unwrap();
-            writeln!(self.writer, "    mov [rax], {}", reg).unwrap();
+        // also labeled with the plain name, to be called from outside
+        let export = f
+            .attrs
+            .iter()
+            .find_map(|x| match (x.name.as_str(), &x.args[..]) {
+                ("no_mangle", []) => Some(f.name.rsplit("::").next().unwrap()),
+                ("export_name", [name]) => Some(name.as_str()),
+                _ => None,
+            });
+        if let Some(export) = export {
+            writeln!(self.writer, ".global {}", export).unwrap();
+            if export != name {
+                writeln!(self.writer, "{}:", export).unwrap();
+            }
+        }
+        self.gen_prologue(&name);
+
+        let tys: Vec<_> = f.args.iter().map(|x| x.ty.clone()).collect();
+        for (arg, reg) in f.args.iter().zip(arg_regs(&tys)) {
+            let offset = self.alloc(&arg.ty);
+            self.offset_table.insert(arg.name.clone(), offset);
+
+            let instr = if arg.ty == Type::F64 { "movq" } else { "mov" };
+            writeln!(
+                self.writer,
+                "    {} QWORD PTR [rbp-{}], {}",
+                instr, offset, reg
+            )
+            .unwrap();
+        }
+        // aggregates are passed by the address of the caller's copy,
+        // copied only now as copying clobbers the argument registers
+        for arg in f.args.iter().filter(|x| x.ty.is_aggregate()) {
+            let offset = self.offset_table[&arg.name];
+            writeln!(self.writer, "    mov rsi, QWORD PTR [rbp-{}]", offset).unwrap();
+            writeln!(self.writer, "    lea rdi, [rbp-{}]", offset).unwrap();
+            self.gen_copy(&arg.ty);
         }
 
+        // the tail value is returned like `return`
         self.gen_block(&f.body);
+        writeln!(self.writer, "    pop rax").unwrap();
+        self.gen_return_value();
         self.gen_epilogue();
+
+        // the frame size is known only after every local is allocated,
+        // stack_size should be a multiple of 16
+        writeln!(
+            self.writer,
+            ".set .L{}_frame, {}",
+            name,
+            self.frame_size.next_multiple_of(16)
+        )
+        .unwrap();
     }
 
-    fn gen_prologue(&mut self, name: &str, stack_size: usize) {
+    fn gen_prologue(&mut self, name: &str) {
         writeln!(self.writer, "{}:", name).unwrap();
         writeln!(self.writer, "    push rbp").unwrap();
         writeln!(self.writer, "    mov rbp, rsp").unwrap();
-        writeln!(self.writer, "    sub rsp, {}", stack_size).unwrap();
+        writeln!(self.writer, "    sub rsp, OFFSET .L{}_frame", name).unwrap();
+    }
+
+    /// move the value in rax to where the caller expects it,
+    /// small aggregates are returned in rax and rdx instead of by the address
+    fn gen_return_value(&mut self) {
+        if self.ret_type == Type::F64 {
+            writeln!(self.writer, "    movq xmm0, rax").unwrap();
+        } else if self.ret_type.is_aggregate() {
+            let size = self.ret_type.size();
+            assert!(size <= 16, "cannot return aggregates larger than 16 bytes");
+            if size > 8 {
+                writeln!(self.writer, "    mov rdx, [rax+8]").unwrap();
+            }
+            writeln!(self.writer, "    mov rax, [rax]").unwrap();
+        }
     }
 
     fn gen_epilogue(&mut self) {
@@ -94,38 +302,190 @@ impl<W: Write> SofaGenerater<W> {
         writeln!(self.writer, "    ret").unwrap();
     }
 
+    /// allocate a slot for a value of `ty` on the frame, returning its offset.
+    /// rounded up to a whole word, so that a scalar can be written to it from a full register
+    fn alloc(&mut self, ty: &Type) -> usize {
+        // rbp is 16 byte aligned, so the slot is as aligned as its offset
+        self.offset = (self.offset + ty.size()).next_multiple_of(ty.align().max(8));
+        self.frame_size = self.frame_size.max(self.offset);
+        self.offset
+    }
+
+    /// allocate a slot holding the current rsp, returning its offset
+    fn save_rsp(&mut self) -> usize {
+        let offset = self.alloc(&Type::I64);
+        writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rsp", offset).unwrap();
+        offset
+    }
+
+    /// the body of a loop, falling through to its continue label with the stack as on entry
+    fn gen_loop_body(&mut self, body: &Block, ctx: LoopCtx) {
+        let rsp_offset = ctx.rsp_offset;
+        self.loops.push(ctx);
+        self.gen_block(body);
+        self.loops.pop();
+
+        // drop what the body left, so the stack does not grow every iteration
+        writeln!(self.writer, "    mov rsp, QWORD PTR [rbp-{}]", rsp_offset).unwrap();
+    }
+
+    /// loop jumped to by `break` or `continue`, the innermost unless labeled
+    fn target_loop(&self, label: &Option<String>) -> &LoopCtx {
+        match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|x| x.name.as_ref() == Some(label))
+                .unwrap_or_else(|| panic!("use of undeclared label `'{}`", label)),
+            None => self
+                .loops
+                .last()
+                .expect("`break` or `continue` outside of a loop"),
+        }
+    }
+
+    /// type of the expression `id`
+    fn ty(&self, id: NodeId) -> Type {
+        self.types[&id].clone()
+    }
+
+    /// push exactly one value, that of the last expression
+    /// the locals declared in the block go out of scope at its end, freeing their slots
     fn gen_block(&mut self, block: &Block) {
-        for expr in block.exprs.iter() {
-            self.gen_expr(expr);
+        let Some((last, init)) = block.exprs.split_last() else {
+            writeln!(self.writer, "    push 0").unwrap(); // void
+            return;
+        };
+        let (offset, offset_table) = (self.offset, self.offset_table.clone());
+        for expr in init {
+            self.gen_loc(*expr);
+            self.gen_expr(*expr);
+            writeln!(self.writer, "    pop rax").unwrap();
+        }
+        self.gen_loc(*last);
+        self.gen_expr(*last);
+
+        self.offset_table = offset_table;
+        // an aggregate value is left as the address of its slot, which has to stay
+        if !self.ty(*last).is_aggregate() {
+            self.offset = offset;
         }
     }
 
-    fn gen_expr(&mut self, expr: &Expr) {
-        match expr {
+    /// where the code of `id` comes from, naming its file first if it is new
+    fn gen_loc(&mut self, id: NodeId) {
+        let (Some(sources), Some(&(pos, _))) = (self.sources, self.spans.get(&id)) else {
+            return;
+        };
+        let loc = sources.lookup(pos);
+        let file = match self.files.iter().position(|x| *x == loc.file.name) {
+            Some(i) => i + 1,
+            None => {
+                self.files.push(loc.file.name.clone());
+                writeln!(
+                    self.writer,
+                    ".file {} {:?}",
+                    self.files.len(),
+                    loc.file.name
+                )
+                .unwrap();
+                self.files.len()
+            }
+        };
+        writeln!(self.writer, ".loc {} {} {}", file, loc.line, loc.col).unwrap();
+    }
+
+    fn gen_expr(&mut self, id: NodeId) {
+        let arena = self.arena;
+        match &arena[id] {
             Expr::Stmt(Stmt { expr }) => {
-                self.gen_expr(expr);
+                self.gen_expr(*expr);
                 writeln!(self.writer, "    pop rax").unwrap();
-                writeln!(self.writer, "    push 0").unwrap(); // unit
+                writeln!(self.writer, "    push 0").unwrap(); // `()`, whatever was discarded
                 writeln!(self.writer).unwrap();
             }
             Expr::Block(block) => self.gen_block(block),
             Expr::Return(Return { expr }) => {
-                self.gen_expr(expr);
-                writeln!(self.writer, "    pop rax").unwrap();
+                let operand = self.gen_operand(*expr, true);
+                self.load("rax", operand);
+                self.gen_return_value();
                 self.gen_epilogue();
             }
-            Expr::Loop(Loop { body }) => {
+            Expr::Loop(Loop {
+                label: name, body, ..
+            }) => {
                 let label = format!(".L{}_loop", self.label_id);
+                let label_end = format!(".L{}_loop_end", self.label_id);
                 self.label_id += 1;
 
+                let rsp_offset = self.save_rsp();
                 writeln!(self.writer, "{}:", label).unwrap();
+                let ctx = LoopCtx {
+                    name: name.clone(),
+                    continue_label: label.clone(),
+                    break_label: label_end.clone(),
+                    rsp_offset,
+                    with_value: true,
+                };
+                self.gen_loop_body(body, ctx);
+                writeln!(self.writer, "    jmp {}", label).unwrap();
 
-                self.gen_block(body);
+                writeln!(self.writer, "{}:", label_end).unwrap();
+                writeln!(self.writer, "    push rax").unwrap(); // value of `break`
+            }
+            Expr::While(While {
+                label: name,
+                cond,
+                body,
+            }) => {
+                let label = format!(".L{}_while", self.label_id);
+                let label_end = format!(".L{}_while_end", self.label_id);
+                self.label_id += 1;
 
-                writeln!(self.writer, "    jmp {}", label).unwrap();
+                let rsp_offset = self.save_rsp();
+                writeln!(self.writer, "{}:", label).unwrap();
+                self.gen_expr(*cond);
                 writeln!(self.writer, "    pop rax").unwrap();
-                writeln!(self.writer, "    push 1").unwrap(); // never
+                writeln!(self.writer, "    cmp rax, 0").unwrap();
+                writeln!(self.writer, "    je {}", label_end).unwrap();
+
+                let ctx = LoopCtx {
+                    name: name.clone(),
+                    continue_label: label.clone(),
+                    break_label: label_end.clone(),
+                    rsp_offset,
+                    with_value: false,
+                };
+                self.gen_loop_body(body, ctx);
+                writeln!(self.writer, "    jmp {}", label).unwrap();
+
+                writeln!(self.writer, "{}:", label_end).unwrap();
+                writeln!(self.writer, "    push 0").unwrap(); // void
+            }
+            Expr::Break(Break { label, expr }) => {
+                let ctx = self.target_loop(label);
+                let (label, rsp_offset) = (ctx.break_label.clone(), ctx.rsp_offset);
+                if ctx.with_value {
+                    let operand = match expr {
+                        Some(expr) => self.gen_operand(*expr, true),
+                        None => Operand::Imm(0), // void
+                    };
+                    self.load("rax", operand);
+                } else {
+                    assert!(expr.is_none(), "`break` with value from a `while` or `for`");
+                }
+                writeln!(self.writer, "    mov rsp, QWORD PTR [rbp-{}]", rsp_offset).unwrap();
+                writeln!(self.writer, "    jmp {}", label).unwrap();
+            }
+            Expr::Continue(Continue { label }) => {
+                let ctx = self.target_loop(label);
+                let (label, rsp_offset) = (ctx.continue_label.clone(), ctx.rsp_offset);
+                writeln!(self.writer, "    mov rsp, QWORD PTR [rbp-{}]", rsp_offset).unwrap();
+                writeln!(self.writer, "    jmp {}", label).unwrap();
             }
+            Expr::Defer(_) => panic!("`defer` must be a statement of a block"),
+            Expr::For(_) => panic!("`for` must be desugared first"),
             Expr::IfElse(IfElse {
                 cond,
                 if_body,
@@ -137,8 +497,7 @@ impl<W: Write> SofaGenerater<W> {
                     let label_end = format!(".L{}_end", self.label_id);
                     self.label_id += 1;
 
-                    assert_eq!(cond.ty(), Type::Bool);
-                    self.gen_expr(cond);
+                    self.gen_expr(*cond);
 
                     writeln!(self.writer, "    pop rax").unwrap();
                     writeln!(self.writer, "    cmp rax, 0").unwrap();
@@ -154,141 +513,409 @@ impl<W: Write> SofaGenerater<W> {
                     let label_end = format!(".L{}_end", self.label_id);
                     self.label_id += 1;
 
-                    self.gen_expr(cond);
+                    self.gen_expr(*cond);
                     writeln!(self.writer, "    pop rax").unwrap();
                     writeln!(self.writer, "    cmp rax, 0").unwrap();
                     writeln!(self.writer, "    je {}", label_end).unwrap();
                     self.gen_block(if_body);
-                    writeln!(self.writer, "{}:", label_end).unwrap();
-                }
-            }
-            Expr::FnCall(FnCall { name, args, .. }) => {
-                for (expr, reg) in args.iter().zip(ARG_REGS) {
-                    self.gen_expr(expr);
                     writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    mov {}, rax", reg).unwrap();
+
+                    writeln!(self.writer, "{}:", label_end).unwrap();
+                    writeln!(self.writer, "    push 0").unwrap(); // void
                 }
-                writeln!(self.writer, "    call {}", name).unwrap();
-                writeln!(self.writer, "    push rax").unwrap();
             }
-            Expr::Init(Init { name, value }) => {
-                if let Expr::Local(local) = &**name {
-                    let size = local.ty.size();
-                    self.offset += size;
-                    self.offset_table.insert(local.name.clone(), self.offset);
+            Expr::Match(Match { scrutinee, arms }) => {
+                let label_end = format!(".L{}_match_end", self.label_id);
+                self.label_id += 1;
 
-                    writeln!(self.writer, "    mov rax, rbp").unwrap(); // retrieve rbp into rax
-                    writeln!(self.writer, "    sub rax, {}", self.offset).unwrap(); // local stored at offset from rbp
-                    writeln!(self.writer, "    push rax").unwrap(); // return local's address
+                // kept in a slot, since every arm looks at it
+                let aggregate = self.ty(*scrutinee).is_aggregate();
+                let operand = self.gen_operand(*scrutinee, true);
+                self.load("rax", operand);
+                let slot = self.alloc(&Type::I64);
+                writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rax", slot).unwrap();
+
+                for Arm { pat, body } in arms.iter() {
+                    let label_next = format!(".L{}_arm", self.label_id);
+                    self.label_id += 1;
 
+                    let value = match pat {
+                        Pattern::Wildcard | Pattern::Binding(_) => None,
+                        Pattern::Number(value) => Some(*value),
+                        Pattern::Bool(value) => Some(*value as i64),
+                        Pattern::Variant { index, .. } => Some(*index as i64),
+                    };
                     if let Some(value) = value {
-                        assert_eq!(local.ty, value.ty());
+                        writeln!(self.writer, "    mov rax, QWORD PTR [rbp-{}]", slot).unwrap();
+                        if aggregate {
+                            writeln!(self.writer, "    mov rax, [rax]").unwrap();
+                            // tag
+                        }
+                        if i32::try_from(value).is_ok() {
+                            writeln!(self.writer, "    cmp rax, {}", value).unwrap();
+                        } else {
+                            // cmp only takes a sign-extended imm32
+                            writeln!(self.writer, "    mov rdi, {}", value).unwrap();
+                            writeln!(self.writer, "    cmp rax, rdi").unwrap();
+                        }
+                        writeln!(self.writer, "    jne {}", label_next).unwrap();
+                    }
 
-                        self.gen_expr(value);
+                    let shadowed = match pat {
+                        Pattern::Binding(local) => {
+                            let reg = if aggregate { "rsi" } else { "rax" };
+                            writeln!(self.writer, "    mov {}, QWORD PTR [rbp-{}]", reg, slot)
+                                .unwrap();
+                            Some((local, self.gen_bind(local)))
+                        }
+                        Pattern::Variant {
+                            binding: Some(local),
+                            ..
+                        } => {
+                            writeln!(self.writer, "    mov rsi, QWORD PTR [rbp-{}]", slot).unwrap();
+                            writeln!(self.writer, "    add rsi, 8").unwrap(); // payload
+                            if !local.ty.is_aggregate() {
+                                self.gen_load("rsi", &local.ty);
+                            }
+                            Some((local, self.gen_bind(local)))
+                        }
+                        _ => None,
+                    };
+
+                    self.gen_expr(*body);
+                    writeln!(self.writer, "    jmp {}", label_end).unwrap();
+                    writeln!(self.writer, "{}:", label_next).unwrap();
 
-                        writeln!(self.writer, "    pop rdi").unwrap();
-                        writeln!(self.writer, "    pop rax").unwrap();
-                        writeln!(self.writer, "    mov [rax], rdi").unwrap();
-                        writeln!(self.writer, "    push 0").unwrap(); // void
-                    } else {
-                        writeln!(self.writer, "    pop rax").unwrap();
-                        writeln!(self.writer, "    push 0").unwrap(); // void
+                    if let Some((local, shadowed)) = shadowed {
+                        self.unbind(&local.name, shadowed);
                     }
+                }
+                // patterns are exhaustive
+                writeln!(self.writer, "    ud2").unwrap();
+
+                writeln!(self.writer, "{}:", label_end).unwrap();
+            }
+            Expr::FnCall(FnCall {
+                name,
+                args,
+                fn_type,
+            }) => {
+                let Type::Fn { ret, .. } = fn_type else {
+                    panic!("function's type must be Fn")
+                };
+                let abi = match self.externs.get(name) {
+                    Some(&variadic) => Abi::C { variadic },
+                    None => Abi::Sofa,
+                };
+                self.gen_call(&symbol(name), args, ret, abi);
+            }
+            Expr::Call(Call { callee, args }) => {
+                let Type::Fn { ret, .. } = self.ty(*callee) else {
+                    panic!("cannot call {:?}", self.ty(*callee))
+                };
+                // the environment starts with the code
+                let args: Vec<_> = std::iter::once(callee).chain(args).copied().collect();
+                self.gen_call("QWORD PTR [rdi]", &args, &ret, Abi::Sofa);
+            }
+            Expr::Closure(Closure { name, captures, .. }) => {
+                let env = Type::closure_env(captures.iter().map(|x| self.ty(*x)));
+                let offset = self.alloc(&env);
+                writeln!(self.writer, "    lea rax, [rip+{}]", symbol(name)).unwrap();
+                writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rax", offset).unwrap();
+
+                // copied by value, so later changes outside are not seen
+                for (i, capture) in captures.iter().enumerate() {
+                    let field = env.offset_of(&(i + 1).to_string());
+                    self.gen_store(*capture, offset - field);
+                }
+                writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Init(Init { name, value, .. }) => {
+                if let Expr::Local(local) = &arena[*name] {
+                    let offset = self.alloc(&self.ty(*name));
+
+                    if let Some(value) = value {
+                        // evaluated before the local comes into scope
+                        self.gen_store(*value, offset);
+                    }
+                    self.offset_table.insert(local.name.clone(), offset);
+                    writeln!(self.writer, "    push 0").unwrap(); // void
                 } else {
                     panic!("lhs must be addressable")
                 }
             }
+            Expr::Assign(Assign { lhs, rhs }) if self.ty(*lhs).is_aggregate() => {
+                self.gen_address(*lhs);
+                self.gen_expr(*rhs);
+
+                writeln!(self.writer, "    pop rsi").unwrap();
+                writeln!(self.writer, "    pop rdi").unwrap();
+                writeln!(self.writer, "    push rdi").unwrap(); // the lhs, as assigned
+                self.gen_copy(&self.ty(*lhs));
+            }
             Expr::Assign(Assign { lhs, rhs }) => {
-                match &**lhs {
+                match &arena[*lhs] {
                     Expr::UnOp(UnOp {
                         kind: UnOpKind::Deref,
                         expr,
                     }) => {
-                        self.gen_expr(expr);
+                        self.gen_expr(*expr);
                     }
                     _ => {
-                        assert_eq!(lhs.ty(), rhs.ty());
-                        self.gen_address(lhs);
+                        self.gen_address(*lhs);
                     }
                 }
-                self.gen_expr(rhs);
+                self.gen_expr(*rhs);
 
                 writeln!(self.writer, "    pop rdi").unwrap();
                 writeln!(self.writer, "    pop rax").unwrap();
-                writeln!(self.writer, "    mov [rax], rdi").unwrap();
-                writeln!(self.writer, "    push 0").unwrap(); // void
+                self.gen_store_rdi("rax", &self.ty(*lhs));
+                writeln!(self.writer, "    push rdi").unwrap();
             }
             Expr::BinOp(binop) => {
                 self.gen_binop(binop);
             }
             Expr::UnOp(UnOp { kind, expr }) => match kind {
                 UnOpKind::Neg => {
-                    self.gen_expr(expr);
+                    self.gen_expr(*expr);
                     writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    neg rax").unwrap();
+                    if self.ty(id) == Type::F64 {
+                        writeln!(self.writer, "    btc rax, 63").unwrap(); // sign bit
+                    } else {
+                        writeln!(self.writer, "    neg rax").unwrap();
+                    }
+                    self.gen_extend(&self.ty(id));
+                    writeln!(self.writer, "    push rax").unwrap();
+                }
+                UnOpKind::Not => {
+                    self.gen_expr(*expr);
+                    writeln!(self.writer, "    pop rax").unwrap();
+                    writeln!(self.writer, "    cmp rax, 0").unwrap();
+                    writeln!(self.writer, "    sete al").unwrap();
+                    writeln!(self.writer, "    movzb rax, al").unwrap();
+                    writeln!(self.writer, "    push rax").unwrap();
+                }
+                UnOpKind::BitNot => {
+                    self.gen_expr(*expr);
+                    writeln!(self.writer, "    pop rax").unwrap();
+                    writeln!(self.writer, "    not rax").unwrap();
+                    self.gen_extend(&self.ty(id));
                     writeln!(self.writer, "    push rax").unwrap();
                 }
                 UnOpKind::Ref => {
                     // TODO:
                     // take reference of imm is not yet supported,
                     // like, &10 or &&a
-                    self.gen_address(expr);
+                    self.gen_address(*expr);
                 }
                 UnOpKind::Deref => {
-                    self.gen_expr(expr);
+                    self.gen_expr(*expr);
+                    // a pointer to an aggregate is already its value
+                    if !matches!(self.ty(*expr), Type::Ptr { to } if to.is_aggregate()) {
+                        writeln!(self.writer, "    pop rax").unwrap();
+                        self.gen_load("rax", &self.ty(id));
+                        writeln!(self.writer, "    push rax").unwrap();
+                    }
+                }
+            },
+            Expr::Enclosed(Enclosed { expr }) => self.gen_expr(*expr),
+            Expr::Cast(Cast { expr, ty }) => {
+                self.gen_expr(*expr);
+                match (self.ty(*expr), ty) {
+                    (from, to) if from == *to => {}
+                    (Type::F64, to) => {
+                        writeln!(self.writer, "    pop rax").unwrap();
+                        writeln!(self.writer, "    movq xmm0, rax").unwrap();
+                        writeln!(self.writer, "    cvttsd2si rax, xmm0").unwrap();
+                        self.gen_extend(to);
+                        writeln!(self.writer, "    push rax").unwrap();
+                    }
+                    (_, Type::F64) => {
+                        writeln!(self.writer, "    pop rax").unwrap();
+                        writeln!(self.writer, "    cvtsi2sd xmm0, rax").unwrap();
+                        writeln!(self.writer, "    movq rax, xmm0").unwrap();
+                        writeln!(self.writer, "    push rax").unwrap();
+                    }
+                    (_, to) if to.is_integer() || *to == Type::Char => {
+                        writeln!(self.writer, "    pop rax").unwrap();
+                        self.gen_extend(to);
+                        writeln!(self.writer, "    push rax").unwrap();
+                    }
+                    _ => {}
+                }
+            }
+            Expr::Index(_) | Expr::Field(_) => {
+                self.gen_address(id);
+                // aggregates are passed around by their address
+                if !self.ty(id).is_aggregate() {
                     writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    mov rax, [rax]").unwrap();
+                    self.gen_load("rax", &self.ty(id));
                     writeln!(self.writer, "    push rax").unwrap();
                 }
-            },
-            Expr::Enclosed(Enclosed { expr }) => self.gen_expr(expr),
+            }
+            Expr::Tuple(Tuple { elements }) => {
+                // a temporary on the frame, left as its address
+                let ty = self.ty(id);
+                let offset = self.alloc(&ty);
+
+                for (i, element) in elements.iter().enumerate() {
+                    let field_offset = ty.offset_of(&i.to_string());
+                    self.gen_store(*element, offset - field_offset);
+                }
+                writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Struct(Struct { ty, fields }) => {
+                // a temporary on the frame, left as its address
+                let offset = self.alloc(ty);
+
+                for (name, value) in fields.iter() {
+                    let field_offset = ty.offset_of(name);
+                    self.gen_store(*value, offset - field_offset);
+                }
+                writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Array(Array { elements }) => {
+                // a temporary on the frame, left as its address
+                let offset = self.alloc(&self.ty(id));
+
+                let mut element_offset = offset;
+                for element in elements.iter() {
+                    self.gen_store(*element, element_offset);
+                    element_offset -= self.ty(*element).size();
+                }
+                writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Variant(Variant { ty, index, payload }) => {
+                if ty.is_aggregate() {
+                    // a temporary on the frame, left as its address
+                    let offset = self.alloc(ty);
+
+                    writeln!(self.writer, "    mov QWORD PTR [rbp-{}], {}", offset, index).unwrap();
+                    if let Some(payload) = payload {
+                        self.gen_store(*payload, offset - 8);
+                    }
+                    writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+                    writeln!(self.writer, "    push rax").unwrap();
+                } else {
+                    writeln!(self.writer, "    push {}", index).unwrap();
+                }
+            }
             Expr::Bool(boolean) => match boolean {
-                crate::ast::Bool::True => writeln!(self.writer, "    push 1").unwrap(),
-                crate::ast::Bool::False => writeln!(self.writer, "    push 0").unwrap(),
+                Bool::True => writeln!(self.writer, "    push 1").unwrap(),
+                Bool::False => writeln!(self.writer, "    push 0").unwrap(),
             },
-            Expr::Local(local) => {
-                self.gen_address(expr);
-
-                if matches!(local.ty, Type::Array { .. }) {
-                    // leave address
+            Expr::Local(_) => {
+                if self.ty(id).is_aggregate() {
+                    self.gen_address(id); // leave address
+                } else if let Some(operand) = self.operand(id) {
+                    writeln!(self.writer, "    push {}", operand).unwrap();
                 } else {
+                    // a static, or narrower than a word
+                    self.gen_address(id);
                     writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    mov rax, [rax]").unwrap(); // address into value on itself
+                    self.gen_load("rax", &self.ty(id));
+                    writeln!(self.writer, "    push rax").unwrap();
+                }
+            }
+            Expr::Char(Char { value }) => writeln!(self.writer, "    push {}", value).unwrap(),
+            Expr::Float(Float { value }) => {
+                let bits = value.parse::<f64>().unwrap().to_bits();
+                writeln!(self.writer, "    mov rax, {}", bits as i64).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Str(Str { value }) => {
+                writeln!(self.writer, "    lea rax, .LS{}[rip]", self.strings.len()).unwrap();
+                self.strings.push(value.clone());
+                if self.ty(id) == Type::Str {
+                    // a temporary on the frame, left as its address
+                    let offset = self.alloc(&Type::Str);
+                    writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rax", offset).unwrap();
+                    writeln!(
+                        self.writer,
+                        "    mov QWORD PTR [rbp-{}], {}",
+                        offset - 8,
+                        value.len()
+                    )
+                    .unwrap();
+                    writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+                }
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Number(Number { value }) => {
+                if value.parse::<i32>().is_ok() {
+                    writeln!(self.writer, "    push {}", value).unwrap(); // num is imm
+                } else {
+                    // push only takes a sign-extended imm32
+                    writeln!(self.writer, "    mov rax, {}", value).unwrap();
                     writeln!(self.writer, "    push rax").unwrap();
                 }
             }
-            Expr::Number(Number { value }) => writeln!(self.writer, "    push {}", value).unwrap(), // num is imm
         }
     }
 
-    fn gen_address(&mut self, expr: &Expr) {
-        match expr {
+    fn gen_address(&mut self, id: NodeId) {
+        let arena = self.arena;
+        match &arena[id] {
             Expr::Local(local) => {
-                let offset = self
-                    .offset_table
-                    .get(&local.name)
-                    .expect("found undefined local");
-
-                writeln!(self.writer, "    mov rax, rbp").unwrap(); // retrieve rbp into rax
-                writeln!(self.writer, "    sub rax, {}", offset).unwrap(); // local stored at offset from rbp
+                if let Some(offset) = self.offset_table.get(&local.name) {
+                    writeln!(self.writer, "    mov rax, rbp").unwrap(); // retrieve rbp into rax
+                    writeln!(self.writer, "    sub rax, {}", offset).unwrap(); // local stored at offset from rbp
+                } else if self.statics.contains(&local.name) {
+                    writeln!(self.writer, "    lea rax, [rip+{}]", symbol(&local.name)).unwrap();
+                } else {
+                    panic!("found undefined local {}", local.name)
+                }
                 writeln!(self.writer, "    push rax").unwrap(); // return local's address
             }
+            Expr::Index(Index { base, index }) => {
+                let size = self.ty(id).size();
+                // the value of an array is its address, same as a pointer
+                self.gen_expr(*base);
+                if self.ty(*base) == Type::Str {
+                    writeln!(self.writer, "    pop rax").unwrap();
+                    writeln!(self.writer, "    push QWORD PTR [rax]").unwrap();
+                }
+                self.gen_expr(*index);
+
+                writeln!(self.writer, "    pop rdi").unwrap();
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    imul rdi, {}", size).unwrap();
+                writeln!(self.writer, "    add rax, rdi").unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Field(Field { base, name }) => {
+                let offset = self.ty(*base).offset_of(name);
+                // the value of a struct is its address
+                self.gen_expr(*base);
+                if offset != 0 {
+                    writeln!(self.writer, "    pop rax").unwrap();
+                    writeln!(self.writer, "    add rax, {}", offset).unwrap();
+                    writeln!(self.writer, "    push rax").unwrap();
+                }
+            }
             Expr::UnOp(UnOp {
                 kind: UnOpKind::Deref,
                 expr,
-            }) => match &**expr {
-                Expr::UnOp(_) => self.gen_address(expr),
+            }) => match &arena[*expr] {
+                Expr::UnOp(_) => self.gen_address(*expr),
                 Expr::Local(_) => {
-                    self.gen_expr(expr);
+                    self.gen_expr(*expr);
                 }
-                _ => panic!("invalid lval {:?}", expr),
+                expr => panic!("invalid lval {:?}", expr),
             },
-            _ => panic!("invalid lval {:?}", expr),
+            Expr::Enclosed(Enclosed { expr }) => self.gen_address(*expr),
+            // temporaries of aggregates already live on the frame
+            _ if self.ty(id).is_aggregate() => self.gen_expr(id),
+            expr => panic!("invalid lval {:?}", expr),
         }
     }
 
-    fn gen_binop(&mut self, BinOp { op, lhs, rhs }: &BinOp) {
-        match (op, lhs.ty(), rhs.ty()) {
+    fn gen_binop(&mut self, &BinOp { ref op, lhs, rhs }: &BinOp) {
+        match (op, self.ty(lhs), self.ty(rhs)) {
             (
                 BinOpKind::Add
                 | BinOpKind::Sub
@@ -297,10 +924,18 @@ impl<W: Write> SofaGenerater<W> {
                 | BinOpKind::Rem
                 | BinOpKind::BitAnd
                 | BinOpKind::BitOr
-                | BinOpKind::BitXor,
-                Type::I64,
-                Type::I64,
-            ) => self.gen_math(op, lhs, rhs),
+                | BinOpKind::BitXor
+                | BinOpKind::Shl
+                | BinOpKind::Shr,
+                a,
+                b,
+            ) if a == b && a.is_integer() => self.gen_math(op, lhs, rhs, &a),
+
+            (
+                BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor,
+                ty @ Type::Bool,
+                Type::Bool,
+            ) => self.gen_math(op, lhs, rhs, &ty),
 
             (
                 BinOpKind::Eq
@@ -309,9 +944,36 @@ impl<W: Write> SofaGenerater<W> {
                 | BinOpKind::Le
                 | BinOpKind::GtEq
                 | BinOpKind::Gt,
-                Type::I64,
-                Type::I64,
-            ) => self.gen_cmp(op, lhs, rhs),
+                a,
+                b,
+            ) if a == b
+                && (a.is_integer() || matches!(a, Type::Bool | Type::Char | Type::Ptr { .. })) =>
+            {
+                self.gen_cmp(op, lhs, rhs, a.is_signed())
+            }
+
+            (
+                BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul | BinOpKind::Div,
+                Type::F64,
+                Type::F64,
+            ) => self.gen_float_math(op, lhs, rhs),
+
+            (
+                BinOpKind::Eq
+                | BinOpKind::Neq
+                | BinOpKind::LeEq
+                | BinOpKind::Le
+                | BinOpKind::GtEq
+                | BinOpKind::Gt,
+                Type::F64,
+                Type::F64,
+            ) => self.gen_float_cmp(op, lhs, rhs),
+
+            (BinOpKind::Eq | BinOpKind::Neq, ty @ Type::Enum { .. }, Type::Enum { .. })
+                if !ty.is_aggregate() =>
+            {
+                self.gen_cmp(op, lhs, rhs, false)
+            }
 
             (BinOpKind::Add, Type::Ptr { to }, Type::I64) => {
                 self.gen_expr(lhs);
@@ -405,58 +1067,334 @@ impl<W: Write> SofaGenerater<W> {
                 writeln!(self.writer, "{}:", label3).unwrap();
             }
 
-            _ => panic!("{:?} for {:?} and {:?} is not implemented", op, lhs, rhs),
+            _ => panic!(
+                "{:?} for {:?} and {:?} is not implemented",
+                op, self.arena[lhs], self.arena[rhs]
+            ),
         }
 
         writeln!(self.writer, "    push rax").unwrap();
     }
 
-    fn gen_math(&mut self, op: &BinOpKind, lhs: &Expr, rhs: &Expr) {
-        self.gen_expr(lhs);
-        self.gen_expr(rhs);
+    /// call `target` with `args` in registers, pushing the returned value
+    fn gen_call(&mut self, target: &str, args: &[NodeId], ret: &Type, abi: Abi) {
+        // evaluate every argument before filling registers,
+        // since a nested call would clobber them
+        let mut operands = vec![];
+        for (i, expr) in args.iter().enumerate() {
+            let rest_trivial = args[i + 1..].iter().all(|x| self.operand(*x).is_some());
+            operands.push(self.gen_operand(*expr, rest_trivial));
+        }
+        let tys: Vec<_> = args.iter().map(|x| self.ty(*x)).collect();
+        let regs = arg_regs(&tys);
+        // floats go through rax, which is not an argument register
+        for (operand, reg) in operands.iter().zip(&regs).rev() {
+            if let Operand::Stack = operand {
+                if reg.starts_with("xmm") {
+                    writeln!(self.writer, "    pop rax").unwrap();
+                    writeln!(self.writer, "    movq {}, rax", reg).unwrap();
+                } else {
+                    writeln!(self.writer, "    pop {}", reg).unwrap();
+                }
+            }
+        }
+        for (operand, reg) in operands.iter().zip(&regs) {
+            if matches!(operand, Operand::Stack) {
+                continue;
+            }
+            if reg.starts_with("xmm") {
+                writeln!(self.writer, "    mov rax, {}", operand).unwrap();
+                writeln!(self.writer, "    movq {}, rax", reg).unwrap();
+            } else {
+                writeln!(self.writer, "    mov {}, {}", reg, operand).unwrap();
+            }
+        }
+        let floats = regs.iter().filter(|x| x.starts_with("xmm")).count();
+        match abi {
+            Abi::Sofa => writeln!(self.writer, "    call {}", target).unwrap(),
+            Abi::C { variadic } => {
+                // whatever was pushed leaves rsp unaligned
+                let rsp_offset = self.save_rsp();
+                writeln!(self.writer, "    and rsp, -16").unwrap();
+                if variadic {
+                    // number of vector registers used
+                    writeln!(self.writer, "    mov eax, {}", floats).unwrap();
+                }
+                writeln!(self.writer, "    call {}", target).unwrap();
+                writeln!(self.writer, "    mov rsp, QWORD PTR [rbp-{}]", rsp_offset).unwrap();
+                // C leaves the upper bits of narrow return values undefined
+                self.gen_extend(ret);
+            }
+        }
 
-        writeln!(self.writer, "    pop rdi").unwrap();
-        writeln!(self.writer, "    pop rax").unwrap();
+        if *ret == Type::F64 {
+            writeln!(self.writer, "    movq rax, xmm0").unwrap();
+        } else if ret.is_aggregate() {
+            // returned in rax and rdx, spilled to a temporary
+            let offset = self.alloc(ret);
+            writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rax", offset).unwrap();
+            if ret.size() > 8 {
+                writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rdx", offset - 8).unwrap();
+            }
+            writeln!(self.writer, "    lea rax, [rbp-{}]", offset).unwrap();
+        }
+        writeln!(self.writer, "    push rax").unwrap();
+    }
 
-        match op {
-            BinOpKind::Add => writeln!(self.writer, "    add rax, rdi").unwrap(),
-            BinOpKind::Sub => writeln!(self.writer, "    sub rax, rdi").unwrap(),
-            BinOpKind::Mul => writeln!(self.writer, "    imul rax, rdi").unwrap(),
-            BinOpKind::Div => {
-                writeln!(self.writer, "    cqo").unwrap();
-                writeln!(self.writer, "    idiv rdi").unwrap();
-            }
-            BinOpKind::Rem => {
-                writeln!(self.writer, "    cqo").unwrap();
-                writeln!(self.writer, "    idiv rdi").unwrap();
-                writeln!(self.writer, "    mov rax, rdx").unwrap();
-            }
-            BinOpKind::BitAnd => writeln!(self.writer, "    and rax, rdi").unwrap(),
-            BinOpKind::BitOr => writeln!(self.writer, "    or rax, rdi").unwrap(),
-            BinOpKind::BitXor => writeln!(self.writer, "    xor rax, rdi").unwrap(),
-            _ => unreachable!(),
+    /// bring `local` into scope with the value in rax, or at [rsi] for aggregates,
+    /// returning the offset of whatever it shadowed
+    fn gen_bind(&mut self, local: &Local) -> Option<usize> {
+        let offset = self.alloc(&local.ty);
+        if local.ty.is_aggregate() {
+            writeln!(self.writer, "    lea rdi, [rbp-{}]", offset).unwrap();
+            self.gen_copy(&local.ty);
+        } else {
+            writeln!(self.writer, "    mov QWORD PTR [rbp-{}], rax", offset).unwrap();
+        }
+        self.offset_table.insert(local.name.clone(), offset)
+    }
+
+    /// take `name` out of scope, restoring what it shadowed
+    fn unbind(&mut self, name: &str, shadowed: Option<usize>) {
+        match shadowed {
+            Some(offset) => self.offset_table.insert(name.to_string(), offset),
+            None => self.offset_table.remove(name),
         };
     }
 
-    fn gen_cmp(&mut self, op: &BinOpKind, lhs: &Expr, rhs: &Expr) {
-        self.gen_expr(lhs);
-        self.gen_expr(rhs);
-        if matches!(op, BinOpKind::Gt | BinOpKind::GtEq) {
-            writeln!(self.writer, "    pop rax").unwrap();
-            writeln!(self.writer, "    pop rdi").unwrap();
+    /// evaluate `value` into the slot at `offset`
+    fn gen_store(&mut self, value: NodeId, offset: usize) {
+        let ty = self.ty(value);
+        if ty.is_aggregate() {
+            self.gen_expr(value);
+            writeln!(self.writer, "    pop rsi").unwrap();
+            writeln!(self.writer, "    lea rdi, [rbp-{}]", offset).unwrap();
+            self.gen_copy(&ty);
         } else {
+            let operand = self.gen_operand(value, true);
+            self.load("rdi", operand);
+            self.gen_store_rdi(&format!("rbp-{}", offset), &ty);
+        }
+    }
+
+    /// copy a value of `ty` from [rsi] to [rdi]
+    fn gen_copy(&mut self, ty: &Type) {
+        writeln!(self.writer, "    mov rcx, {}", ty.size()).unwrap();
+        writeln!(self.writer, "    rep movsb").unwrap();
+    }
+
+    /// load the scalar of `ty` at [`addr`] into rax, extended to the full register
+    fn gen_load(&mut self, addr: &str, ty: &Type) {
+        let instr = match ty {
+            Type::I8 => "movsx rax, BYTE PTR",
+            Type::I16 => "movsx rax, WORD PTR",
+            Type::I32 => "movsxd rax, DWORD PTR",
+            Type::U8 | Type::Char | Type::Bool => "movzx eax, BYTE PTR",
+            Type::U16 => "movzx eax, WORD PTR",
+            // writing eax clears the upper half
+            Type::U32 => "mov eax, DWORD PTR",
+            _ => "mov rax, QWORD PTR",
+        };
+        writeln!(self.writer, "    {} [{}]", instr, addr).unwrap();
+    }
+
+    /// store the scalar of `ty` in rdi to [`addr`], only as many bytes as it takes
+    fn gen_store_rdi(&mut self, addr: &str, ty: &Type) {
+        let (ptr, reg) = match ty.size() {
+            1 => ("BYTE", "dil"),
+            2 => ("WORD", "di"),
+            4 => ("DWORD", "edi"),
+            _ => ("QWORD", "rdi"),
+        };
+        writeln!(self.writer, "    mov {} PTR [{}], {}", ptr, addr, reg).unwrap();
+    }
+
+    /// wrap rax around to the range of `ty`, after arithmetic that may overflow it
+    fn gen_extend(&mut self, ty: &Type) {
+        let instr = match ty {
+            Type::I8 => "movsx rax, al",
+            Type::I16 => "movsx rax, ax",
+            Type::I32 => "movsxd rax, eax",
+            Type::U8 | Type::Char | Type::Bool => "movzx eax, al",
+            Type::U16 => "movzx eax, ax",
+            Type::U32 => "mov eax, eax",
+            _ => return,
+        };
+        writeln!(self.writer, "    {}", instr).unwrap();
+    }
+
+    /// operand referring to `expr` without emitting any code, if it is trivial
+    fn operand(&self, id: NodeId) -> Option<Operand> {
+        match &self.arena[id] {
+            Expr::Number(Number { value }) => {
+                value.parse::<i32>().ok().map(|x| Operand::Imm(x as i64))
+            }
+            Expr::Variant(Variant { ty, index, .. }) if !ty.is_aggregate() => {
+                Some(Operand::Imm(*index as i64))
+            }
+            Expr::Char(Char { value }) => Some(Operand::Imm(*value as i64)),
+            Expr::Bool(Bool::True) => Some(Operand::Imm(1)),
+            Expr::Bool(Bool::False) => Some(Operand::Imm(0)),
+            Expr::Local(local) if !self.ty(id).is_aggregate() && self.ty(id).size() == 8 => self
+                .offset_table
+                .get(&local.name)
+                .map(|&x| Operand::Local(x)),
+            Expr::Enclosed(Enclosed { expr }) => self.operand(*expr),
+            _ => None,
+        }
+    }
+
+    /// evaluate `expr`, pushing it only when it is not trivial.
+    ///
+    /// a local is read at its use rather than here,
+    /// so it can be deferred only if nothing evaluated in between could write to it
+    fn gen_operand(&mut self, id: NodeId, defer_local: bool) -> Operand {
+        match self.operand(id) {
+            Some(Operand::Local(_)) if !defer_local => {
+                self.gen_expr(id);
+                Operand::Stack
+            }
+            Some(operand) => operand,
+            None => {
+                self.gen_expr(id);
+                Operand::Stack
+            }
+        }
+    }
+
+    fn load(&mut self, reg: &str, operand: Operand) {
+        match operand {
+            Operand::Stack => writeln!(self.writer, "    pop {}", reg).unwrap(),
+            _ => writeln!(self.writer, "    mov {}, {}", reg, operand).unwrap(),
+        }
+    }
+
+    /// evaluate `lhs` into rax, and return how `rhs` can be referred to
+    fn gen_operands(&mut self, lhs: NodeId, rhs: NodeId) -> String {
+        let defer_local = self.operand(rhs).is_some();
+        let lhs = self.gen_operand(lhs, defer_local);
+        let rhs = self.gen_operand(rhs, true);
+
+        let rhs = if let Operand::Stack = rhs {
             writeln!(self.writer, "    pop rdi").unwrap();
-            writeln!(self.writer, "    pop rax").unwrap();
+            "rdi".to_string()
+        } else {
+            rhs.to_string()
+        };
+        self.load("rax", lhs);
+        rhs
+    }
+
+    /// `op` on operands of `ty`, wrapping around its range
+    fn gen_math(&mut self, op: &BinOpKind, lhs: NodeId, rhs: NodeId, ty: &Type) {
+        let rhs = self.gen_operands(lhs, rhs);
+
+        match op {
+            BinOpKind::Add => writeln!(self.writer, "    add rax, {}", rhs).unwrap(),
+            BinOpKind::Sub => writeln!(self.writer, "    sub rax, {}", rhs).unwrap(),
+            BinOpKind::Mul => writeln!(self.writer, "    imul rax, {}", rhs).unwrap(),
+            BinOpKind::Div | BinOpKind::Rem => {
+                // idiv takes neither imm nor a second operand
+                if rhs != "rdi" {
+                    writeln!(self.writer, "    mov rdi, {}", rhs).unwrap();
+                }
+                if ty.is_signed() {
+                    writeln!(self.writer, "    cqo").unwrap();
+                    writeln!(self.writer, "    idiv rdi").unwrap();
+                } else {
+                    writeln!(self.writer, "    xor edx, edx").unwrap();
+                    writeln!(self.writer, "    div rdi").unwrap();
+                }
+                if *op == BinOpKind::Rem {
+                    writeln!(self.writer, "    mov rax, rdx").unwrap();
+                }
+            }
+            BinOpKind::BitAnd => writeln!(self.writer, "    and rax, {}", rhs).unwrap(),
+            BinOpKind::BitOr => writeln!(self.writer, "    or rax, {}", rhs).unwrap(),
+            BinOpKind::BitXor => writeln!(self.writer, "    xor rax, {}", rhs).unwrap(),
+            BinOpKind::Shl | BinOpKind::Shr => {
+                let instr = match op {
+                    BinOpKind::Shl => "shl",
+                    _ if ty.is_signed() => "sar",
+                    _ => "shr",
+                };
+                if rhs.parse::<i64>().is_ok() {
+                    writeln!(self.writer, "    {} rax, {}", instr, rhs).unwrap();
+                } else {
+                    // a variable count is only taken in cl
+                    writeln!(self.writer, "    mov rcx, {}", rhs).unwrap();
+                    writeln!(self.writer, "    {} rax, cl", instr).unwrap();
+                }
+            }
+            _ => unreachable!(),
+        };
+        self.gen_extend(ty);
+    }
+
+    fn gen_float_math(&mut self, op: &BinOpKind, lhs: NodeId, rhs: NodeId) {
+        let rhs = self.gen_operands(lhs, rhs);
+
+        writeln!(self.writer, "    movq xmm0, rax").unwrap();
+        writeln!(self.writer, "    movq xmm1, {}", rhs).unwrap();
+        let instr = match op {
+            BinOpKind::Add => "addsd",
+            BinOpKind::Sub => "subsd",
+            BinOpKind::Mul => "mulsd",
+            BinOpKind::Div => "divsd",
+            _ => unreachable!(),
+        };
+        writeln!(self.writer, "    {} xmm0, xmm1", instr).unwrap();
+        writeln!(self.writer, "    movq rax, xmm0").unwrap();
+    }
+
+    /// comparisons with NaN are false, except `!=`
+    fn gen_float_cmp(&mut self, op: &BinOpKind, lhs: NodeId, rhs: NodeId) {
+        let rhs = self.gen_operands(lhs, rhs);
+
+        writeln!(self.writer, "    movq xmm0, rax").unwrap();
+        writeln!(self.writer, "    movq xmm1, {}", rhs).unwrap();
+        // unordered sets CF, so only `a` and `ae` exclude NaN, with the operands swapped for `<`
+        let (lhs, rhs) = match op {
+            BinOpKind::Le | BinOpKind::LeEq => ("xmm1", "xmm0"),
+            _ => ("xmm0", "xmm1"),
+        };
+        writeln!(self.writer, "    ucomisd {}, {}", lhs, rhs).unwrap();
+        match op {
+            BinOpKind::Gt | BinOpKind::Le => writeln!(self.writer, "    seta al").unwrap(),
+            BinOpKind::GtEq | BinOpKind::LeEq => writeln!(self.writer, "    setae al").unwrap(),
+            BinOpKind::Eq => {
+                writeln!(self.writer, "    sete al").unwrap();
+                writeln!(self.writer, "    setnp cl").unwrap();
+                writeln!(self.writer, "    and al, cl").unwrap();
+            }
+            BinOpKind::Neq => {
+                writeln!(self.writer, "    setne al").unwrap();
+                writeln!(self.writer, "    setp cl").unwrap();
+                writeln!(self.writer, "    or al, cl").unwrap();
+            }
+            _ => unreachable!(),
         }
-        writeln!(self.writer, "    cmp rax, rdi").unwrap();
+        writeln!(self.writer, "    movzb rax, al").unwrap();
+    }
+
+    fn gen_cmp(&mut self, op: &BinOpKind, lhs: NodeId, rhs: NodeId, signed: bool) {
+        let rhs = self.gen_operands(lhs, rhs);
+
+        writeln!(self.writer, "    cmp rax, {}", rhs).unwrap();
         writeln!(
             self.writer,
             "    {} al",
-            match op {
-                BinOpKind::Eq => "sete",
-                BinOpKind::Neq => "setne",
-                BinOpKind::LeEq | BinOpKind::GtEq => "setle",
-                BinOpKind::Le | BinOpKind::Gt => "setl",
+            match (op, signed) {
+                (BinOpKind::Eq, _) => "sete",
+                (BinOpKind::Neq, _) => "setne",
+                (BinOpKind::LeEq, true) => "setle",
+                (BinOpKind::Le, true) => "setl",
+                (BinOpKind::GtEq, true) => "setge",
+                (BinOpKind::Gt, true) => "setg",
+                (BinOpKind::LeEq, false) => "setbe",
+                (BinOpKind::Le, false) => "setb",
+                (BinOpKind::GtEq, false) => "setae",
+                (BinOpKind::Gt, false) => "seta",
                 _ => unreachable!(),
             }
         )
@@ -464,3 +1402,123 @@ impl<W: Write> SofaGenerater<W> {
         writeln!(self.writer, "    movzb rax, al").unwrap();
     }
 }
+
+/// registers the args of `tys` are passed in, as System V assigns them:
+/// floats in xmm registers and everything else in general purpose ones, each in order
+fn arg_regs(tys: &[Type]) -> Vec<&'static str> {
+    let mut ints = ARG_REGS.iter();
+    let mut floats = FLOAT_ARG_REGS.iter();
+    tys.iter()
+        .map(|ty| match ty {
+            Type::F64 => floats.next(),
+            _ => ints.next(),
+        })
+        .map(|x| *x.expect("too many arguments to pass in registers"))
+        .collect()
+}
+
+/// label of the item `name`, `foo::bar` becomes `foo.bar`
+fn symbol(name: &str) -> String {
+    name.replace("::", ".")
+}
+
+/// `s` as the contents of a `.string` directive
+fn escape(s: &str) -> String {
+    let mut res = String::new();
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' => {
+                res.push('\\');
+                res.push(b as char);
+            }
+            b' '..=b'~' => res.push(b as char),
+            _ => res.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    res
+}
+
+/// the fields of the struct or tuple `id` with zeros for the padding between them
+fn static_fields(
+    arena: &Arena<Expr>,
+    types: &Types,
+    id: NodeId,
+    fields: &[(String, NodeId)],
+    values: &mut Vec<(usize, i64)>,
+) {
+    let ty = &types[&id];
+    let mut end = 0;
+    for (name, value) in fields.iter() {
+        let offset = ty.offset_of(name);
+        if offset > end {
+            values.push((offset - end, 0));
+        }
+        static_values(arena, types, *value, values);
+        end = offset + types[value].size();
+    }
+    if ty.size() > end {
+        values.push((ty.size() - end, 0));
+    }
+}
+
+/// scalars of the static initializer `id` in memory order, with their sizes in bytes
+fn static_values(arena: &Arena<Expr>, types: &Types, id: NodeId, values: &mut Vec<(usize, i64)>) {
+    let static_values =
+        |id: &NodeId, values: &mut Vec<(usize, i64)>| static_values(arena, types, *id, values);
+    let size = types[&id].size();
+    match &arena[id] {
+        // a u64 as its bits
+        Expr::Number(Number { value }) => {
+            values.push((size, value.parse::<i128>().unwrap() as i64))
+        }
+        Expr::Float(Float { value }) => {
+            values.push((size, value.parse::<f64>().unwrap().to_bits() as i64))
+        }
+        Expr::Char(Char { value }) => values.push((size, *value as i64)),
+        Expr::Bool(Bool::True) => values.push((size, 1)),
+        Expr::Bool(Bool::False) => values.push((size, 0)),
+        Expr::Enclosed(Enclosed { expr }) => static_values(expr, values),
+        Expr::Array(Array { elements }) => {
+            for element in elements.iter() {
+                static_values(element, values);
+            }
+        }
+        Expr::Tuple(Tuple { elements }) => {
+            let fields: Vec<_> = elements
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (i.to_string(), *x))
+                .collect();
+            static_fields(arena, types, id, &fields, values);
+        }
+        Expr::Struct(Struct { ty, fields }) => {
+            // in declaration order, whatever order the literal is written in
+            let Type::Struct { fields: decl, .. } = ty else {
+                unreachable!()
+            };
+            let fields: Vec<_> = decl
+                .iter()
+                .map(|(name, _)| fields.iter().find(|(x, _)| x == name).unwrap().clone())
+                .collect();
+            static_fields(arena, types, id, &fields, values);
+        }
+        Expr::Variant(Variant { index, payload, .. }) => {
+            values.push((8, *index as i64));
+            let mut payload_size = 0;
+            if let Some(payload) = payload {
+                static_values(payload, values);
+                payload_size = types[payload].size();
+            }
+            // padded up to the largest payload
+            if size > 8 + payload_size {
+                values.push((size - 8 - payload_size, 0));
+            }
+        }
+        // checked to be constant by `typeck`
+        expr if types[&id] == Type::F64 => {
+            let value = const_eval::eval_f64(arena, expr).unwrap();
+            values.push((size, value.to_bits() as i64))
+        }
+        expr => values.push((size, const_eval::eval(arena, expr).unwrap())),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{
+    ast::{
+        Arena, BinOp, BinOpKind, Bool, Cast, Char, Enclosed, Expr, Float, NodeId, Number, UnOp,
+        UnOpKind,
+    },
+    ty::Type,
+};
+
+/// the constant `expr` of `ty` evaluated into a literal, so it can be inlined
+pub fn constant(arena: &Arena<Expr>, expr: &Expr, ty: &Type) -> Result<Expr, String> {
+    match ty {
+        Type::F64 => Ok(Expr::Float(Float {
+            value: format!("{:?}", eval_f64(arena, expr)?),
+        })),
+        // as is, so a u64 past i64::MAX is kept
+        ty if matches!(expr, Expr::Number(_)) => match expr {
+            Expr::Number(Number { value }) if !ty.fits(value.parse().unwrap()) => {
+                Err(format!("literal out of range for {}", ty))
+            }
+            _ => Ok(expr.clone()),
+        },
+        _ => literal(eval(arena, expr)?, ty),
+    }
+}
+
+/// evaluate a constant expression of i64 or bool at compile time, bools as 0 or 1
+pub fn eval(arena: &Arena<Expr>, expr: &Expr) -> Result<i64, String> {
+    let eval = |id: &NodeId| eval(arena, &arena[*id]);
+    let overflow = || "overflow in constant".to_string();
+    let div_zero = || "division by zero in constant".to_string();
+    let res = match expr {
+        Expr::Number(Number { value }) => value.parse().map_err(|_| overflow())?,
+        Expr::Char(Char { value }) => *value as i64,
+        Expr::Bool(Bool::True) => 1,
+        Expr::Bool(Bool::False) => 0,
+        Expr::Enclosed(Enclosed { expr }) => eval(expr)?,
+        Expr::Cast(Cast { expr: inner, .. }) => {
+            expr.checked_ty(arena)?;
+            eval(inner)?
+        }
+        Expr::UnOp(UnOp { kind, expr: inner }) => {
+            let value = eval(inner)?;
+            match kind {
+                UnOpKind::Neg => value.checked_neg().ok_or_else(overflow)?,
+                UnOpKind::Not => (value == 0) as i64,
+                UnOpKind::BitNot => !value,
+                UnOpKind::Ref | UnOpKind::Deref => return Err(not_constant(expr)),
+            }
+        }
+        Expr::BinOp(BinOp { op, lhs, rhs }) => {
+            // type errors are caught here, before anything is evaluated
+            expr.checked_ty(arena)?;
+            let (lhs, rhs) = (eval(lhs)?, eval(rhs)?);
+            match op {
+                BinOpKind::Eq => (lhs == rhs) as i64,
+                BinOpKind::Neq => (lhs != rhs) as i64,
+                BinOpKind::LeEq => (lhs <= rhs) as i64,
+                BinOpKind::Le => (lhs < rhs) as i64,
+                BinOpKind::GtEq => (lhs >= rhs) as i64,
+                BinOpKind::Gt => (lhs > rhs) as i64,
+                BinOpKind::Add => lhs.checked_add(rhs).ok_or_else(overflow)?,
+                BinOpKind::Sub => lhs.checked_sub(rhs).ok_or_else(overflow)?,
+                BinOpKind::Mul => lhs.checked_mul(rhs).ok_or_else(overflow)?,
+                BinOpKind::Div => lhs.checked_div(rhs).ok_or_else(div_zero)?,
+                BinOpKind::Rem => lhs.checked_rem(rhs).ok_or_else(div_zero)?,
+                BinOpKind::BitAnd | BinOpKind::LogAnd => lhs & rhs,
+                BinOpKind::BitOr | BinOpKind::LogOr => lhs | rhs,
+                BinOpKind::BitXor => lhs ^ rhs,
+                BinOpKind::Shl => lhs << (rhs & 63),
+                BinOpKind::Shr => lhs >> (rhs & 63),
+            }
+        }
+        _ => return Err(not_constant(expr)),
+    };
+    Ok(res)
+}
+
+/// evaluate a constant expression of f64 at compile time
+pub fn eval_f64(arena: &Arena<Expr>, expr: &Expr) -> Result<f64, String> {
+    let eval_f64 = |id: &NodeId| eval_f64(arena, &arena[*id]);
+    let res = match expr {
+        Expr::Float(Float { value }) => value.parse().unwrap(),
+        Expr::Enclosed(Enclosed { expr }) => eval_f64(expr)?,
+        Expr::Cast(Cast { expr: inner, .. }) => match arena[*inner].checked_ty(arena)? {
+            Type::F64 => eval_f64(inner)?,
+            _ => eval(arena, &arena[*inner])? as f64,
+        },
+        Expr::UnOp(UnOp {
+            kind: UnOpKind::Neg,
+            expr,
+        }) => -eval_f64(expr)?,
+        Expr::BinOp(BinOp { op, lhs, rhs }) => {
+            expr.checked_ty(arena)?;
+            let (lhs, rhs) = (eval_f64(lhs)?, eval_f64(rhs)?);
+            match op {
+                BinOpKind::Add => lhs + rhs,
+                BinOpKind::Sub => lhs - rhs,
+                BinOpKind::Mul => lhs * rhs,
+                BinOpKind::Div => lhs / rhs,
+                _ => {
+                    return Err(
+                        "only `+`, `-`, `*` and `/` are supported on f64 constants".to_string()
+                    )
+                }
+            }
+        }
+        _ => return Err(not_constant(expr)),
+    };
+    Ok(res)
+}
+
+/// `value` of `ty` as a literal, so it can be inlined
+pub fn literal(value: i64, ty: &Type) -> Result<Expr, String> {
+    let res = match ty {
+        ty if ty.is_integer() && !ty.fits(value as i128) => {
+            return Err(format!("constant {} out of range for {}", value, ty))
+        }
+        ty if ty.is_integer() => Expr::Number(Number {
+            value: value.to_string(),
+        }),
+        Type::Char => Expr::Char(Char { value: value as u8 }),
+        Type::Bool if value == 0 => Expr::Bool(Bool::False),
+        Type::Bool => Expr::Bool(Bool::True),
+        _ => return Err(format!("constants of {} are not supported", ty)),
+    };
+    Ok(res)
+}
+
+fn not_constant(expr: &Expr) -> String {
+    let what = match expr {
+        Expr::UnOp(UnOp {
+            kind: UnOpKind::Ref,
+            ..
+        }) => "a reference",
+        Expr::UnOp(UnOp {
+            kind: UnOpKind::Deref,
+            ..
+        }) => "a dereference",
+        Expr::Local(_) => "a variable",
+        Expr::FnCall(_) | Expr::Call(_) => "a call",
+        _ => "this expression",
+    };
+    format!("expected a constant expression, found {}", what)
+}
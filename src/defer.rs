@@ -0,0 +1,186 @@
+use crate::{
+    ast::{
+        Arena, Ast, Block, Break, Continue, Defer, Expr, For, Init, Local, Loop, NodeId, Return,
+        Stmt, While,
+    },
+    fold::{self, deep_copy, take, Fold},
+    ty::Type,
+    visit::{walk_expr, Visitor},
+};
+
+/// rewrite every `defer` away, so that the deferred expressions run in reverse order
+/// wherever their block is left: at its end, or by `return`, `break` and `continue`
+pub fn lower(ast: &mut Ast) {
+    // most programs have none, and are left untouched
+    if !has_defer(ast) {
+        return;
+    }
+
+    let mut lowering = Lowering { temps: 0 };
+    *ast = lowering.fold_ast(std::mem::take(ast));
+}
+
+fn has_defer(ast: &Ast) -> bool {
+    struct Finder(bool);
+    impl Visitor for Finder {
+        fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+            match &arena[id] {
+                Expr::Defer(_) => self.0 = true,
+                _ => walk_expr(self, arena, id),
+            }
+        }
+    }
+
+    let mut finder = Finder(false);
+    finder.visit_ast(ast);
+    finder.0
+}
+
+struct Lowering {
+    /// temporaries made so far, numbering their names
+    temps: usize,
+}
+
+impl Fold for Lowering {
+    fn fold_block(&mut self, arena: &mut Arena<Expr>, block: Block) -> Block {
+        // inner blocks first, so that their deferred run before the ones here
+        let block = fold::walk_block(self, arena, block);
+
+        // innermost last
+        let mut deferred = vec![];
+        let mut exprs = vec![];
+        for id in block.exprs {
+            match &arena[id] {
+                Expr::Defer(Defer { expr }) => deferred.push(*expr),
+                Expr::Stmt(Stmt { expr }) if matches!(arena[*expr], Expr::Defer(_)) => {
+                    let Expr::Defer(Defer { expr }) = arena[*expr] else {
+                        unreachable!()
+                    };
+                    deferred.push(expr);
+                }
+                _ if deferred.is_empty() => exprs.push(id),
+                _ => {
+                    let mut escape = Escape {
+                        lowering: self,
+                        deferred: &deferred,
+                        loops: vec![],
+                    };
+                    exprs.push(escape.fold_expr(arena, id));
+                }
+            }
+        }
+        if deferred.is_empty() {
+            return Block { exprs };
+        }
+
+        // the value of the block is kept while they run
+        match exprs.pop() {
+            Some(last) if arena[last].ty(arena) == Type::Never => exprs.push(last),
+            Some(last)
+                if matches!(arena[last], Expr::Stmt(_)) || arena[last].ty(arena) == Type::Void =>
+            {
+                exprs.push(last);
+                exprs.extend(run(arena, &deferred));
+            }
+            Some(last) => {
+                let temp = self.temp(arena, last, &mut exprs);
+                exprs.extend(run(arena, &deferred));
+                exprs.push(temp);
+            }
+            None => exprs.extend(run(arena, &deferred)),
+        }
+        Block { exprs }
+    }
+}
+
+impl Lowering {
+    /// a local initialized to `value` by an `Init` pushed to `exprs`
+    fn temp(&mut self, arena: &mut Arena<Expr>, value: NodeId, exprs: &mut Vec<NodeId>) -> NodeId {
+        let local = Local {
+            name: format!("__defer{}", self.temps),
+            ty: arena[value].ty(arena),
+        };
+        self.temps += 1;
+        let name = arena.alloc(Expr::Local(local.clone()));
+        exprs.push(arena.alloc(Expr::Init(Init {
+            name,
+            value: Some(value),
+            mutable: false,
+        })));
+        arena.alloc(Expr::Local(local))
+    }
+}
+
+/// runs `deferred` before every `return`, and every `break` or `continue`
+/// out of a loop not in the expression folded
+struct Escape<'a> {
+    lowering: &'a mut Lowering,
+    deferred: &'a [NodeId],
+    /// labels of the loops entered so far
+    loops: Vec<Option<String>>,
+}
+
+impl Fold for Escape<'_> {
+    fn fold_expr(&mut self, arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+        let leaves = match &arena[id] {
+            Expr::Return(_) => true,
+            Expr::Break(Break { label, .. }) | Expr::Continue(Continue { label }) => match label {
+                Some(label) => !self.loops.iter().any(|x| x.as_ref() == Some(label)),
+                None => self.loops.is_empty(),
+            },
+            _ => false,
+        };
+        if leaves {
+            let mut exprs = vec![];
+            // the value leaving is evaluated before they run
+            let leaving = match take(arena, id) {
+                Expr::Return(Return { expr: value }) if arena[value].ty(arena) != Type::Void => {
+                    Expr::Return(Return {
+                        expr: self.lowering.temp(arena, value, &mut exprs),
+                    })
+                }
+                Expr::Break(Break {
+                    label,
+                    expr: Some(value),
+                }) if arena[value].ty(arena) != Type::Void => Expr::Break(Break {
+                    label,
+                    expr: Some(self.lowering.temp(arena, value, &mut exprs)),
+                }),
+                expr => expr,
+            };
+            exprs.extend(run(arena, self.deferred));
+            exprs.push(arena.alloc(leaving));
+            arena[id] = Expr::Block(Block { exprs });
+            return id;
+        }
+
+        // a loop inside is not left by what breaks out of it
+        let label = match &arena[id] {
+            Expr::Loop(Loop { label, .. })
+            | Expr::While(While { label, .. })
+            | Expr::For(For { label, .. }) => Some(label.clone()),
+            _ => None,
+        };
+        let is_loop = label.is_some();
+        if let Some(label) = label {
+            self.loops.push(label);
+        }
+        let res = fold::walk_expr(self, arena, id);
+        if is_loop {
+            self.loops.pop();
+        }
+        res
+    }
+}
+
+/// statements running copies of `deferred`, the last deferred first
+fn run(arena: &mut Arena<Expr>, deferred: &[NodeId]) -> Vec<NodeId> {
+    deferred
+        .iter()
+        .rev()
+        .map(|x| {
+            let expr = deep_copy(arena, *x);
+            arena.alloc(Expr::Stmt(Stmt { expr }))
+        })
+        .collect()
+}
@@ -0,0 +1,157 @@
+use crate::{
+    ast::{
+        Arena, Arm, Assign, Ast, BinOp, BinOpKind, Block, Expr, For, Init, Local, NodeId, Number,
+        Range, Stmt, While,
+    },
+    defer,
+    fold::{self, Fold},
+    ty::Type,
+};
+
+/// rewrite the surface AST into the core one codegen takes,
+/// without `defer` or `for`.
+/// compound assignment, `?:` and `if let` are already rewritten by the parser
+pub fn lower(ast: &mut Ast) {
+    defer::lower(ast);
+
+    *ast = Desugar { fors: 0 }.fold_ast(std::mem::take(ast));
+}
+
+struct Desugar {
+    /// `for`s rewritten so far, numbering their temporaries
+    fors: usize,
+}
+
+impl Fold for Desugar {
+    fn fold_expr(&mut self, arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+        // inner loops first, so that they are already rewritten when renaming
+        let id = fold::walk_expr(self, arena, id);
+        if let Expr::For(_) = &arena[id] {
+            let Expr::For(f) = fold::take(arena, id) else {
+                unreachable!()
+            };
+            arena[id] = Expr::Block(self.for_loop(arena, f));
+        }
+        id
+    }
+}
+
+impl Desugar {
+    /// `for x in start..end { .. }` is
+    /// ```text
+    /// {
+    ///     let mut next = start;
+    ///     let end = end;
+    ///     while next < end {
+    ///         let mut x = next;
+    ///         next = next + 1;
+    ///         ..
+    ///     }
+    /// }
+    /// ```
+    /// with `x` renamed so that it is not seen after the loop,
+    /// and advanced first so that `continue` does not skip it.
+    /// `x` is a copy, so the body may change it without affecting the loop
+    fn for_loop(&mut self, arena: &mut Arena<Expr>, f: For) -> Block {
+        let For {
+            label,
+            var,
+            range: Range { start, end },
+            body,
+        } = f;
+        assert_eq!(arena[start].ty(arena), Type::I64);
+        assert_eq!(arena[end].ty(arena), Type::I64);
+
+        let n = self.fors;
+        self.fors += 1;
+        let local = |name: String| Local {
+            name,
+            ty: Type::I64,
+        };
+        let next = local(format!("__for{}", n));
+        let end_local = local(format!("__for{}_end", n));
+        let renamed = local(format!("__for{}_{}", n, var.name));
+
+        let mut rename = Rename {
+            from: &var.name,
+            to: &renamed.name,
+        };
+        let body = rename.fold_block(arena, body);
+
+        let init = |arena: &mut Arena<Expr>, local: &Local, value: NodeId| {
+            let name = arena.alloc(Expr::Local(local.clone()));
+            arena.alloc(Expr::Init(Init {
+                name,
+                value: Some(value),
+                mutable: *local != end_local,
+            }))
+        };
+        let get = |arena: &mut Arena<Expr>, local: &Local| arena.alloc(Expr::Local(local.clone()));
+
+        let lhs = get(arena, &next);
+        let rhs = get(arena, &end_local);
+        let cond = arena.alloc(Expr::BinOp(BinOp {
+            op: BinOpKind::Le,
+            lhs,
+            rhs,
+        }));
+
+        let value = get(arena, &next);
+        let bind = init(arena, &renamed, value);
+        let lhs = get(arena, &next);
+        let one = arena.alloc(Expr::Number(Number {
+            value: "1".to_string(),
+        }));
+        let rhs = arena.alloc(Expr::BinOp(BinOp {
+            op: BinOpKind::Add,
+            lhs,
+            rhs: one,
+        }));
+        let lhs = get(arena, &next);
+        let advance = arena.alloc(Expr::Assign(Assign { lhs, rhs }));
+        let advance = arena.alloc(Expr::Stmt(Stmt { expr: advance }));
+
+        let mut exprs = vec![bind, advance];
+        exprs.extend(body.exprs);
+        let while_loop = arena.alloc(Expr::While(While {
+            label,
+            cond,
+            body: Block { exprs },
+        }));
+
+        Block {
+            exprs: vec![
+                init(arena, &next, start),
+                init(arena, &end_local, end),
+                while_loop,
+            ],
+        }
+    }
+}
+
+/// renames every use of the local `from`,
+/// which is unambiguous since only patterns can shadow it without also being renamed
+struct Rename<'a> {
+    from: &'a str,
+    to: &'a str,
+}
+
+impl Fold for Rename<'_> {
+    fn fold_arm(&mut self, arena: &mut Arena<Expr>, arm: Arm) -> Arm {
+        match arm.pat.binding() {
+            Some(local) if local.name == self.from => arm,
+            _ => fold::walk_arm(self, arena, arm),
+        }
+    }
+
+    fn fold_local(&mut self, local: Local) -> Local {
+        if local.name == self.from {
+            Local {
+                name: self.to.to_string(),
+                ..local
+            }
+        } else {
+            local
+        }
+    }
+}
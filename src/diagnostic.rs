@@ -0,0 +1,94 @@
+use std::fmt;
+
+use crate::source_map::SourceMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    /// (start, end) in global offsets of `SourceMap`, same as `Token::pos`
+    pub pos: (usize, usize),
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, pos: (usize, usize)) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            pos,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// render like rustc does, e.g.
+    ///
+    /// ```text
+    /// error: unknown character `；`
+    ///  --> main.sofa:2:14
+    ///   |
+    /// 2 |     return 0；
+    ///   |             ^
+    ///   = help: did you mean `;`?
+    /// ```
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let loc = sources.lookup(self.pos.0);
+        let end = sources.lookup(self.pos.1);
+        let text = loc.file.line(loc.line);
+        // spans over multiple lines are underlined up to the end of the first one
+        let end_col = if end.line == loc.line {
+            end.col
+        } else {
+            text.chars().count() + 1
+        };
+        let width = end_col.saturating_sub(loc.col).max(1);
+        let gutter = " ".repeat(loc.line.to_string().len());
+
+        let mut res = format!("{}: {}\n", self.level, self.message);
+        res += &format!("{}--> {}:{}:{}\n", gutter, loc.file.name, loc.line, loc.col);
+        res += &format!("{} |\n", gutter);
+        res += &format!("{} | {}\n", loc.line, text);
+        res += &format!(
+            "{} | {}{}\n",
+            gutter,
+            " ".repeat(loc.col - 1),
+            "^".repeat(width)
+        );
+        if let Some(help) = &self.help {
+            res += &format!("{} = help: {}\n", gutter, help);
+        }
+        res
+    }
+}
+
+/// print every diagnostic and exit
+pub fn abort(diagnostics: &[Diagnostic], sources: &SourceMap) -> ! {
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic.render(sources));
+    }
+    eprintln!(
+        "error: aborting due to {} previous error(s)",
+        diagnostics.len()
+    );
+    std::process::exit(1);
+}
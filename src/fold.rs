@@ -0,0 +1,280 @@
+use crate::ast::{
+    Arena, Arm, Array, Assign, Ast, BinOp, Block, Break, Call, Cast, Closure, Defer, Definition,
+    Enclosed, Expr, Field, FnCall, FnDef, For, Global, IfElse, Index, Init, Local, Loop, Match,
+    NodeId, Range, Return, Static, Stmt, Struct, Tuple, UnOp, Variant, While,
+};
+
+/// rewriting traversal of the AST, taking every node and returning its replacement,
+/// children are rewritten before their parent by the `walk_*`s,
+/// and an expression walked keeps its id, with the rewritten one put back in the `arena`
+pub trait Fold {
+    fn fold_ast(&mut self, ast: Ast) -> Ast {
+        walk_ast(self, ast)
+    }
+
+    fn fold_global(&mut self, arena: &mut Arena<Expr>, global: Global) -> Global {
+        walk_global(self, arena, global)
+    }
+
+    fn fold_definition(&mut self, arena: &mut Arena<Expr>, definition: Definition) -> Definition {
+        walk_definition(self, arena, definition)
+    }
+
+    fn fold_fn_def(&mut self, arena: &mut Arena<Expr>, f: FnDef) -> FnDef {
+        walk_fn_def(self, arena, f)
+    }
+
+    fn fold_static(&mut self, arena: &mut Arena<Expr>, s: Static) -> Static {
+        walk_static(self, arena, s)
+    }
+
+    fn fold_block(&mut self, arena: &mut Arena<Expr>, block: Block) -> Block {
+        walk_block(self, arena, block)
+    }
+
+    fn fold_expr(&mut self, arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+        walk_expr(self, arena, id)
+    }
+
+    fn fold_arm(&mut self, arena: &mut Arena<Expr>, arm: Arm) -> Arm {
+        walk_arm(self, arena, arm)
+    }
+
+    /// every local bound or used, including args but not those bound by patterns
+    fn fold_local(&mut self, local: Local) -> Local {
+        local
+    }
+}
+
+pub fn walk_ast<F: Fold + ?Sized>(f: &mut F, ast: Ast) -> Ast {
+    let Ast {
+        mut arena,
+        node,
+        comments,
+        spans,
+    } = ast;
+    let node = f.fold_global(&mut arena, node);
+    Ast {
+        arena,
+        node,
+        comments,
+        spans,
+    }
+}
+
+pub fn walk_global<F: Fold + ?Sized>(f: &mut F, arena: &mut Arena<Expr>, global: Global) -> Global {
+    Global {
+        definitions: global
+            .definitions
+            .into_iter()
+            .map(|x| f.fold_definition(arena, x))
+            .collect(),
+    }
+}
+
+pub fn walk_definition<F: Fold + ?Sized>(
+    f: &mut F,
+    arena: &mut Arena<Expr>,
+    definition: Definition,
+) -> Definition {
+    match definition {
+        Definition::Fn(x) => Definition::Fn(f.fold_fn_def(arena, x)),
+        Definition::Static(x) => Definition::Static(f.fold_static(arena, x)),
+        Definition::Extern(x) => Definition::Extern(x),
+    }
+}
+
+pub fn walk_fn_def<F: Fold + ?Sized>(f: &mut F, arena: &mut Arena<Expr>, def: FnDef) -> FnDef {
+    FnDef {
+        args: def.args.into_iter().map(|x| f.fold_local(x)).collect(),
+        body: f.fold_block(arena, def.body),
+        ..def
+    }
+}
+
+pub fn walk_static<F: Fold + ?Sized>(f: &mut F, arena: &mut Arena<Expr>, s: Static) -> Static {
+    Static {
+        init: f.fold_expr(arena, s.init),
+        ..s
+    }
+}
+
+pub fn walk_block<F: Fold + ?Sized>(f: &mut F, arena: &mut Arena<Expr>, block: Block) -> Block {
+    Block {
+        exprs: fold_exprs(f, arena, block.exprs),
+    }
+}
+
+pub fn walk_arm<F: Fold + ?Sized>(f: &mut F, arena: &mut Arena<Expr>, arm: Arm) -> Arm {
+    Arm {
+        pat: arm.pat,
+        body: f.fold_expr(arena, arm.body),
+    }
+}
+
+pub fn walk_expr<F: Fold + ?Sized>(f: &mut F, arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+    let expr = match take(arena, id) {
+        Expr::Stmt(Stmt { expr }) => Expr::Stmt(Stmt {
+            expr: f.fold_expr(arena, expr),
+        }),
+        Expr::Block(block) => Expr::Block(f.fold_block(arena, block)),
+        Expr::Return(Return { expr }) => Expr::Return(Return {
+            expr: f.fold_expr(arena, expr),
+        }),
+        Expr::Loop(Loop { label, body, ty }) => Expr::Loop(Loop {
+            label,
+            body: f.fold_block(arena, body),
+            ty,
+        }),
+        Expr::While(While { label, cond, body }) => Expr::While(While {
+            label,
+            cond: f.fold_expr(arena, cond),
+            body: f.fold_block(arena, body),
+        }),
+        Expr::For(For {
+            label,
+            var,
+            range: Range { start, end },
+            body,
+        }) => Expr::For(For {
+            label,
+            range: Range {
+                start: f.fold_expr(arena, start),
+                end: f.fold_expr(arena, end),
+            },
+            var: f.fold_local(var),
+            body: f.fold_block(arena, body),
+        }),
+        Expr::Break(Break { label, expr }) => Expr::Break(Break {
+            label,
+            expr: expr.map(|x| f.fold_expr(arena, x)),
+        }),
+        Expr::Defer(Defer { expr }) => Expr::Defer(Defer {
+            expr: f.fold_expr(arena, expr),
+        }),
+        Expr::IfElse(IfElse {
+            cond,
+            if_body,
+            else_body,
+        }) => Expr::IfElse(IfElse {
+            cond: f.fold_expr(arena, cond),
+            if_body: f.fold_block(arena, if_body),
+            else_body: else_body.map(|x| f.fold_block(arena, x)),
+        }),
+        Expr::Match(Match { scrutinee, arms }) => Expr::Match(Match {
+            scrutinee: f.fold_expr(arena, scrutinee),
+            arms: arms.into_iter().map(|x| f.fold_arm(arena, x)).collect(),
+        }),
+        Expr::FnCall(FnCall {
+            name,
+            args,
+            fn_type,
+        }) => Expr::FnCall(FnCall {
+            name,
+            args: fold_exprs(f, arena, args),
+            fn_type,
+        }),
+        Expr::Call(Call { callee, args }) => Expr::Call(Call {
+            callee: f.fold_expr(arena, callee),
+            args: fold_exprs(f, arena, args),
+        }),
+        Expr::Closure(Closure { name, captures, ty }) => Expr::Closure(Closure {
+            name,
+            captures: fold_exprs(f, arena, captures),
+            ty,
+        }),
+        Expr::Init(Init {
+            name,
+            value,
+            mutable,
+        }) => {
+            let value = value.map(|x| f.fold_expr(arena, x));
+            Expr::Init(Init {
+                name: f.fold_expr(arena, name),
+                value,
+                mutable,
+            })
+        }
+        Expr::Assign(Assign { lhs, rhs }) => Expr::Assign(Assign {
+            lhs: f.fold_expr(arena, lhs),
+            rhs: f.fold_expr(arena, rhs),
+        }),
+        Expr::BinOp(BinOp { op, lhs, rhs }) => Expr::BinOp(BinOp {
+            op,
+            lhs: f.fold_expr(arena, lhs),
+            rhs: f.fold_expr(arena, rhs),
+        }),
+        Expr::UnOp(UnOp { kind, expr }) => Expr::UnOp(UnOp {
+            kind,
+            expr: f.fold_expr(arena, expr),
+        }),
+        Expr::Enclosed(Enclosed { expr }) => Expr::Enclosed(Enclosed {
+            expr: f.fold_expr(arena, expr),
+        }),
+        Expr::Cast(Cast { expr, ty }) => Expr::Cast(Cast {
+            expr: f.fold_expr(arena, expr),
+            ty,
+        }),
+        Expr::Index(Index { base, index }) => Expr::Index(Index {
+            base: f.fold_expr(arena, base),
+            index: f.fold_expr(arena, index),
+        }),
+        Expr::Field(Field { base, name }) => Expr::Field(Field {
+            base: f.fold_expr(arena, base),
+            name,
+        }),
+        Expr::Array(Array { elements }) => Expr::Array(Array {
+            elements: fold_exprs(f, arena, elements),
+        }),
+        Expr::Tuple(Tuple { elements }) => Expr::Tuple(Tuple {
+            elements: fold_exprs(f, arena, elements),
+        }),
+        Expr::Struct(Struct { ty, fields }) => Expr::Struct(Struct {
+            ty,
+            fields: fields
+                .into_iter()
+                .map(|(name, x)| (name, f.fold_expr(arena, x)))
+                .collect(),
+        }),
+        Expr::Variant(Variant { ty, index, payload }) => Expr::Variant(Variant {
+            ty,
+            index,
+            payload: payload.map(|x| f.fold_expr(arena, x)),
+        }),
+        Expr::Local(local) => Expr::Local(f.fold_local(local)),
+        expr @ (Expr::Continue(_)
+        | Expr::Bool(_)
+        | Expr::Number(_)
+        | Expr::Float(_)
+        | Expr::Char(_)
+        | Expr::Str(_)) => expr,
+    };
+    arena[id] = expr;
+    id
+}
+
+/// the expression at `id`, leaving a placeholder until it is put back
+pub fn take(arena: &mut Arena<Expr>, id: NodeId) -> Expr {
+    std::mem::replace(&mut arena[id], Expr::Block(Block { exprs: vec![] }))
+}
+
+fn fold_exprs<F: Fold + ?Sized>(
+    f: &mut F,
+    arena: &mut Arena<Expr>,
+    ids: Vec<NodeId>,
+) -> Vec<NodeId> {
+    ids.into_iter().map(|x| f.fold_expr(arena, x)).collect()
+}
+
+/// a copy of the expression at `id` and all its children, allocated anew
+pub fn deep_copy(arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+    struct Copier;
+    impl Fold for Copier {
+        fn fold_expr(&mut self, arena: &mut Arena<Expr>, id: NodeId) -> NodeId {
+            let copy = arena.alloc(arena[id].clone());
+            walk_expr(self, arena, copy)
+        }
+    }
+
+    Copier.fold_expr(arena, id)
+}
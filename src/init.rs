@@ -0,0 +1,49 @@
+use std::{fs, io, path::Path};
+
+const MAIN: &str = r"fn main() -> i64 {
+    0
+}
+";
+
+const TEST: &str = r"// exits with 0 when the test passes
+fn add(a: i64, b: i64) -> i64 {
+    a + b
+}
+
+fn main() -> i64 {
+    if add(1, 2) == 3 { 0 } else { 1 }
+}
+";
+
+const GITIGNORE: &str = "/target\n";
+
+/// scaffold a new project in `dir`, which must be missing or empty
+pub fn init(dir: &Path) -> io::Result<()> {
+    if dir.exists() && dir.read_dir()?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("destination `{}` is not empty", dir.display()),
+        ));
+    }
+
+    fs::create_dir_all(dir)?;
+    // named after the directory, which `.` and `..` only give once resolved
+    let dir = dir.canonicalize()?;
+    let name = dir
+        .file_name()
+        .and_then(|x| x.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid project name"))?;
+
+    fs::create_dir_all(dir.join("src"))?;
+    fs::create_dir_all(dir.join("tests"))?;
+
+    fs::write(
+        dir.join("sofa.toml"),
+        format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+    )?;
+    fs::write(dir.join("src/main.sofa"), MAIN)?;
+    fs::write(dir.join("tests/add.sofa"), TEST)?;
+    fs::write(dir.join(".gitignore"), GITIGNORE)?;
+
+    Ok(())
+}
@@ -1,3 +1,10 @@
+use std::fmt;
+
+use crate::{
+    diagnostic::Diagnostic,
+    source_map::{FileId, SourceMap},
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenKind {
     // punctuations
@@ -39,6 +46,10 @@ pub enum TokenKind {
     Bang,
     /// ?
     Question,
+    /// ~
+    Tilde,
+    /// #
+    Pound,
     /// :
     Colon,
     /// ;
@@ -47,6 +58,50 @@ pub enum TokenKind {
     Comma,
     /// .
     Dot,
+    /// ->
+    Arrow,
+    /// =>
+    FatArrow,
+    /// ==
+    EqEq,
+    /// !=
+    BangEq,
+    /// <=
+    LtEq,
+    /// >=
+    GtEq,
+    /// &&
+    AndAnd,
+    /// ||
+    OrOr,
+    /// <<
+    Shl,
+    /// >>
+    Shr,
+    /// ..
+    DotDot,
+    /// ::
+    ColonColon,
+    /// +=
+    PlusEq,
+    /// -=
+    MinusEq,
+    /// *=
+    StarEq,
+    /// /=
+    SlashEq,
+    /// %=
+    PercentEq,
+    /// &=
+    AndEq,
+    /// |=
+    OrEq,
+    /// ^=
+    CaretEq,
+    /// <<=
+    ShlEq,
+    /// >>=
+    ShrEq,
 
     // keywords
     Fn,
@@ -54,26 +109,166 @@ pub enum TokenKind {
     If,
     Else,
     Loop,
+    While,
+    For,
+    Break,
+    Continue,
     Return,
+    Struct,
+    Enum,
+    Match,
+    Const,
+    Static,
+    Extern,
+    As,
+    Mod,
+    Use,
+    Pub,
+    Mut,
+    Impl,
+    Trait,
+    Defer,
 
     /// boolean
     True,
     False,
 
+    /// loop label, `value` holds the name without the `'`
+    Label,
     /// identifier
     Ident,
     /// number literal
     Number,
+    /// floating point literal like `3.14` or `1e9`
+    Float,
+    /// string literal, `value` holds the unescaped content
+    Str,
+    /// character literal, `value` holds the unescaped char
+    Char,
+
+    /// `/// ...`, `value` holds the text after `///`
+    DocComment,
+
+    /// end of input, always the last token
+    Eof,
 
+    // trivia
     /// whitespace
     Whitespace,
+    /// `// ...`
+    LineComment,
+    /// `/* ... */`
+    BlockComment,
     // EOF,
 }
 
+impl TokenKind {
+    /// insignificant to the grammar, dropped unless tokenized with trivia
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment
+        )
+    }
+}
+
+impl fmt::Display for TokenKind {
+    /// as written in the source, for diagnostics
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TokenKind::Plus => "`+`",
+            TokenKind::Minus => "`-`",
+            TokenKind::Star => "`*`",
+            TokenKind::Slash => "`/`",
+            TokenKind::Percent => "`%`",
+            TokenKind::And => "`&`",
+            TokenKind::Or => "`|`",
+            TokenKind::Caret => "`^`",
+            TokenKind::Lt => "`<`",
+            TokenKind::Gt => "`>`",
+            TokenKind::LParen => "`(`",
+            TokenKind::RParen => "`)`",
+            TokenKind::LBrace => "`{`",
+            TokenKind::RBrace => "`}`",
+            TokenKind::LBlanket => "`[`",
+            TokenKind::RBlanket => "`]`",
+            TokenKind::Eq => "`=`",
+            TokenKind::Bang => "`!`",
+            TokenKind::Question => "`?`",
+            TokenKind::Pound => "`#`",
+            TokenKind::Tilde => "`~`",
+            TokenKind::Colon => "`:`",
+            TokenKind::Semi => "`;`",
+            TokenKind::Comma => "`,`",
+            TokenKind::Dot => "`.`",
+            TokenKind::Arrow => "`->`",
+            TokenKind::FatArrow => "`=>`",
+            TokenKind::EqEq => "`==`",
+            TokenKind::BangEq => "`!=`",
+            TokenKind::LtEq => "`<=`",
+            TokenKind::GtEq => "`>=`",
+            TokenKind::AndAnd => "`&&`",
+            TokenKind::OrOr => "`||`",
+            TokenKind::Shl => "`<<`",
+            TokenKind::Shr => "`>>`",
+            TokenKind::DotDot => "`..`",
+            TokenKind::ColonColon => "`::`",
+            TokenKind::PlusEq => "`+=`",
+            TokenKind::MinusEq => "`-=`",
+            TokenKind::StarEq => "`*=`",
+            TokenKind::SlashEq => "`/=`",
+            TokenKind::PercentEq => "`%=`",
+            TokenKind::AndEq => "`&=`",
+            TokenKind::OrEq => "`|=`",
+            TokenKind::CaretEq => "`^=`",
+            TokenKind::ShlEq => "`<<=`",
+            TokenKind::ShrEq => "`>>=`",
+            TokenKind::Fn => "`fn`",
+            TokenKind::Let => "`let`",
+            TokenKind::If => "`if`",
+            TokenKind::Else => "`else`",
+            TokenKind::Loop => "`loop`",
+            TokenKind::While => "`while`",
+            TokenKind::For => "`for`",
+            TokenKind::Break => "`break`",
+            TokenKind::Continue => "`continue`",
+            TokenKind::Return => "`return`",
+            TokenKind::Struct => "`struct`",
+            TokenKind::Enum => "`enum`",
+            TokenKind::Match => "`match`",
+            TokenKind::Const => "`const`",
+            TokenKind::Static => "`static`",
+            TokenKind::Extern => "`extern`",
+            TokenKind::As => "`as`",
+            TokenKind::Mod => "`mod`",
+            TokenKind::Use => "`use`",
+            TokenKind::Pub => "`pub`",
+            TokenKind::Mut => "`mut`",
+            TokenKind::Impl => "`impl`",
+            TokenKind::Trait => "`trait`",
+            TokenKind::Defer => "`defer`",
+            TokenKind::True => "`true`",
+            TokenKind::False => "`false`",
+            TokenKind::Label => "label",
+            TokenKind::Ident => "identifier",
+            TokenKind::Number => "number",
+            TokenKind::Float => "floating point literal",
+            TokenKind::Str => "string literal",
+            TokenKind::Char => "character literal",
+            TokenKind::DocComment => "doc comment",
+            TokenKind::Eof => "end of input",
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::LineComment | TokenKind::BlockComment => "comment",
+        };
+        f.write_str(s)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub value: Option<String>,
+    /// (start, end) in global offsets of `SourceMap`
     pub pos: (usize, usize),
 }
 
@@ -85,55 +280,219 @@ fn is_id_body(c: &char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_')
 }
 
-const KEYWORDS: &[(&str, TokenKind)] = &[
-    ("fn", TokenKind::Fn),
-    ("let", TokenKind::Let),
-    ("if", TokenKind::If),
-    ("else", TokenKind::Else),
-    ("loop", TokenKind::Loop),
-    ("return", TokenKind::Return),
-    ("true", TokenKind::True),
-    ("false", TokenKind::False),
-];
-
+/// reserved words, lexed as their own kind everywhere
+///
+/// contextual keywords such as `in` are not listed here: they are lexed as `Ident`
+/// and recognized by the parser only where the grammar expects them,
+/// so programs using them as names keep compiling
 fn to_keyword(id: &str) -> Option<TokenKind> {
-    for (token, kind) in KEYWORDS.iter().copied() {
-        if token == id {
-            return Some(kind);
-        }
+    // compiled into a dispatch on length and then bytes, no table scan
+    let kind = match id {
+        "fn" => TokenKind::Fn,
+        "let" => TokenKind::Let,
+        "if" => TokenKind::If,
+        "else" => TokenKind::Else,
+        "loop" => TokenKind::Loop,
+        "while" => TokenKind::While,
+        "for" => TokenKind::For,
+        "break" => TokenKind::Break,
+        "continue" => TokenKind::Continue,
+        "return" => TokenKind::Return,
+        "struct" => TokenKind::Struct,
+        "enum" => TokenKind::Enum,
+        "match" => TokenKind::Match,
+        "const" => TokenKind::Const,
+        "static" => TokenKind::Static,
+        "extern" => TokenKind::Extern,
+        "as" => TokenKind::As,
+        "mod" => TokenKind::Mod,
+        "use" => TokenKind::Use,
+        "pub" => TokenKind::Pub,
+        "mut" => TokenKind::Mut,
+        "impl" => TokenKind::Impl,
+        "trait" => TokenKind::Trait,
+        "defer" => TokenKind::Defer,
+        "true" => TokenKind::True,
+        "false" => TokenKind::False,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// likely intent for a character that is commonly typed by mistake,
+/// such as smart quotes or full-width symbols from an IME
+fn suggest(c: char) -> Option<char> {
+    match c {
+        '\u{201c}' | '\u{201d}' | '\u{201e}' => Some('"'),
+        '\u{2018}' | '\u{2019}' => Some('\''),
+        '\u{2212}' | '\u{2013}' | '\u{2014}' => Some('-'),
+        '\u{d7}' => Some('*'),
+        '\u{f7}' => Some('/'),
+        '\u{a0}' | '\u{3000}' => Some(' '),
+        // full-width forms of ascii, e.g. `；` or `（`
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0),
+        _ => None,
     }
+}
+
+/// every error found while lexing, which does not stop at the first one
+#[derive(Debug)]
+pub struct LexError {
+    pub diagnostics: Vec<Diagnostic>,
+}
 
-    None
+/// yields significant tokens lazily,
+/// collecting the errors found on the way into `diagnostics`
+pub struct Lexer<'a> {
+    cursor: Cursor<'a>,
+    /// shebang line, yielded first as trivia
+    shebang: Option<Token>,
+    /// whether `Eof` is yielded
+    done: bool,
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-    let mut cursor = Cursor::new(input);
-    std::iter::from_fn(move || {
-        if cursor.is_eof() {
+impl<'a> Lexer<'a> {
+    pub fn new(sources: &'a SourceMap, file: FileId) -> Self {
+        let file = sources.get(file);
+        let src = file.src();
+        let mut cursor = Cursor::new(src, file.start);
+
+        // `#!/usr/bin/env sofa` so a script can be executable, but `#![...]` is not one
+        let shebang = (src.starts_with("#!") && !src.starts_with("#![")).then(|| {
+            while !cursor.is_eof() && cursor.next() != &'\n' {
+                cursor.bump();
+            }
+            cursor.trivia(TokenKind::LineComment)
+        });
+
+        Self {
+            cursor,
+            shebang,
+            done: false,
+        }
+    }
+
+    /// errors found so far
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.cursor.diagnostics
+    }
+
+    /// next token including trivia
+    fn next_with_trivia(&mut self) -> Option<Token> {
+        if let Some(shebang) = self.shebang.take() {
+            Some(shebang)
+        } else if self.done {
             None
+        } else if self.cursor.is_eof() {
+            self.done = true;
+            Some(Token {
+                kind: TokenKind::Eof,
+                value: None,
+                pos: self.cursor.update_pos(),
+            })
         } else {
-            Some(cursor.token())
+            Some(self.cursor.token())
         }
-    })
-    .filter(|x| x.kind != TokenKind::Whitespace)
-    .collect()
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(token) = self.next_with_trivia() {
+            if !token.kind.is_trivia() {
+                return Some(token);
+            }
+        }
+        None
+    }
+}
+
+pub fn tokenize(sources: &SourceMap, file: FileId) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer::new(sources, file);
+    let tokens = lexer.by_ref().collect();
+
+    if lexer.diagnostics().is_empty() {
+        Ok(tokens)
+    } else {
+        Err(LexError {
+            diagnostics: lexer.diagnostics().to_vec(),
+        })
+    }
+}
+
+/// a significant token with the trivia around it
+#[derive(Debug, Clone)]
+pub struct TriviaToken {
+    /// trivia from the line after the previous token
+    pub leading: Vec<Token>,
+    pub token: Token,
+    /// trivia up to the end of the line
+    pub trailing: Vec<Token>,
+}
+
+/// tokenize keeping whitespace and comments attached to their neighbors,
+/// so tools like a formatter can reproduce the source as written
+pub fn tokenize_with_trivia(
+    sources: &SourceMap,
+    file: FileId,
+) -> Result<Vec<TriviaToken>, LexError> {
+    let mut lexer = Lexer::new(sources, file);
+    let mut res: Vec<TriviaToken> = vec![];
+    let mut leading = vec![];
+    let mut same_line = false;
+
+    while let Some(token) = lexer.next_with_trivia() {
+        if token.kind.is_trivia() {
+            let newline = token.value.as_ref().is_some_and(|x| x.contains('\n'));
+            match res.last_mut() {
+                Some(last) if same_line && !newline => last.trailing.push(token),
+                _ => {
+                    same_line = false;
+                    leading.push(token);
+                }
+            }
+        } else {
+            res.push(TriviaToken {
+                leading: std::mem::take(&mut leading),
+                token,
+                trailing: vec![],
+            });
+            same_line = true;
+        }
+    }
+    if lexer.diagnostics().is_empty() {
+        Ok(res)
+    } else {
+        Err(LexError {
+            diagnostics: lexer.diagnostics().to_vec(),
+        })
+    }
 }
 
 const EOF_CHAR: char = '\0';
 
 struct Cursor<'a> {
+    src: &'a str,
+    /// global offset of `src`
+    start: usize,
     pos: usize,
     last: usize,
     chars: std::iter::Peekable<std::str::Chars<'a>>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Cursor<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a str, start: usize) -> Self {
         let chars = source.chars().peekable();
         Self {
-            pos: 0,
-            last: 0,
+            src: source,
+            start,
+            pos: start,
+            last: start,
             chars,
+            diagnostics: vec![],
         }
     }
 
@@ -148,8 +507,9 @@ impl<'a> Cursor<'a> {
     }
 
     fn bump(&mut self) -> Option<char> {
-        self.pos += 1;
-        self.chars.next()
+        let c = self.chars.next();
+        self.pos += c.map_or(0, char::len_utf8);
+        c
     }
 
     fn consume(&mut self, target: &str) {
@@ -163,18 +523,324 @@ impl<'a> Cursor<'a> {
         self.next() == &EOF_CHAR
     }
 
+    /// punctuation which may pair up with the next char into another token, like `=` and `==`
+    fn punct(&mut self, single: TokenKind, pairs: &[(char, TokenKind)]) -> Token {
+        self.bump();
+        let kind = match pairs.iter().find(|(c, _)| c == self.next()) {
+            Some(&(_, kind)) => {
+                self.bump();
+                kind
+            }
+            None => single,
+        };
+
+        Token {
+            kind,
+            value: None,
+            pos: self.update_pos(),
+        }
+    }
+
+    /// trivia token holding its text as written
+    fn trivia(&mut self, kind: TokenKind) -> Token {
+        let pos = self.update_pos();
+        Token {
+            kind,
+            value: Some(self.src[pos.0 - self.start..pos.1 - self.start].to_string()),
+            pos,
+        }
+    }
+
+    /// the char `n` chars after `next`
+    fn nth(&self, n: usize) -> char {
+        self.chars.clone().nth(n).unwrap_or(EOF_CHAR)
+    }
+
+    /// whether `.5` or `e9` follows, which continues an integer into a float.
+    /// `0..n` is not a float since a digit must follow `.`
+    fn is_float_rest(&self) -> bool {
+        match (self.nth(0), self.nth(1)) {
+            ('.', c) => c.is_ascii_digit(),
+            ('e' | 'E', '+' | '-') => self.nth(2).is_ascii_digit(),
+            ('e' | 'E', c) => c.is_ascii_digit(),
+            _ => false,
+        }
+    }
+
+    fn digits(&mut self) -> String {
+        let raw = self
+            .chars
+            .clone()
+            .take_while(|&x| x.is_ascii_digit() || x == '_')
+            .collect::<String>();
+        self.consume(&raw);
+        raw.replace('_', "")
+    }
+
+    /// the rest of a float literal whose integer part `int` is already consumed
+    fn float(&mut self, int: &str) -> Token {
+        let mut value = int.to_string();
+        if self.next() == &'.' {
+            self.bump();
+            value.push('.');
+            value += &self.digits();
+        }
+        if matches!(self.next(), 'e' | 'E') && self.is_float_rest() {
+            self.bump();
+            value.push('e');
+            if let '+' | '-' = self.next() {
+                value.push(self.bump().unwrap());
+            }
+            value += &self.digits();
+        }
+
+        Token {
+            kind: TokenKind::Float,
+            value: Some(value),
+            pos: self.update_pos(),
+        }
+    }
+
+    /// decimal, or `0x`, `0o`, `0b` prefixed integer, normalized into decimal
+    /// with `_` separators stripped
+    fn number(&mut self) -> Token {
+        let radix = match (*self.next(), self.nth(1)) {
+            ('0', 'x') => 16,
+            ('0', 'o') => 8,
+            ('0', 'b') => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.bump();
+            self.bump();
+        }
+
+        let raw = self
+            .chars
+            .clone()
+            .take_while(|&x| x.is_digit(radix) || x == '_')
+            .collect::<String>();
+        self.consume(&raw);
+
+        // `_` is only a separator, like `1_000_000`
+        let digits = raw.replace('_', "");
+        // `t.0.1` is two field accesses rather than `t.` followed by `0.1`
+        let after_dot = self.src[..self.last - self.start].ends_with('.');
+        if radix == 10 && !after_dot && self.is_float_rest() {
+            return self.float(&digits);
+        }
+        let pos = self.update_pos();
+
+        if digits.is_empty() {
+            self.diagnostics.push(Diagnostic::error(
+                format!("no valid digits found for number in base {}", radix),
+                pos,
+            ));
+            return Token {
+                kind: TokenKind::Number,
+                value: Some("0".to_string()),
+                pos,
+            };
+        }
+
+        // u64::MAX is the largest literal that can be valid,
+        // whether it fits the type it ends up with is up to the parser and `typeck`
+        let value = match u64::from_str_radix(&digits, radix) {
+            Ok(n) => n.to_string(),
+            Err(_) => {
+                self.diagnostics.push(
+                    Diagnostic::error("integer literal is too large", pos).with_help(format!(
+                        "the largest integer literal is `{}`, the maximum of `u64`",
+                        u64::MAX
+                    )),
+                );
+                "0".to_string()
+            }
+        };
+
+        Token {
+            kind: TokenKind::Number,
+            value: Some(value),
+            pos,
+        }
+    }
+
+    /// `"..."`
+    fn string(&mut self) -> Token {
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                Some(c) => value.push(c),
+                None => {
+                    let pos = self.update_pos();
+                    self.diagnostics.push(Diagnostic::error(
+                        "unterminated double quote string",
+                        (pos.0, pos.0 + 1),
+                    ));
+                    return Token {
+                        kind: TokenKind::Str,
+                        value: Some(value),
+                        pos,
+                    };
+                }
+            }
+        }
+
+        Token {
+            kind: TokenKind::Str,
+            value: Some(value),
+            pos: self.update_pos(),
+        }
+    }
+
+    /// whether a `'` starts a label rather than a character literal
+    fn is_label(&self) -> bool {
+        let mut rest = self.chars.clone().skip(1);
+        matches!(rest.next(), Some(c) if is_id_head(&c)) && rest.next() != Some('\'')
+    }
+
+    /// `'name`
+    fn label(&mut self) -> Token {
+        self.bump();
+        let name = self
+            .chars
+            .clone()
+            .take_while(is_id_body)
+            .collect::<String>();
+        self.consume(&name);
+        Token {
+            kind: TokenKind::Label,
+            value: Some(name),
+            pos: self.update_pos(),
+        }
+    }
+
+    /// `'c'`
+    fn character(&mut self) -> Token {
+        self.bump();
+        let c = match self.bump() {
+            Some('\\') => self.escape(),
+            Some('\'') => {
+                let pos = self.update_pos();
+                self.diagnostics
+                    .push(Diagnostic::error("empty character literal", pos));
+                return Token {
+                    kind: TokenKind::Char,
+                    value: Some('\0'.to_string()),
+                    pos,
+                };
+            }
+            c => c,
+        };
+
+        if self.next() == &'\'' {
+            self.bump();
+        } else {
+            let pos = self.update_pos();
+            self.diagnostics.push(Diagnostic::error(
+                "unterminated character literal",
+                (pos.0, pos.0 + 1),
+            ));
+        }
+
+        let pos = self.update_pos();
+        let c = c.unwrap_or('\0');
+        if !c.is_ascii() {
+            self.diagnostics.push(
+                Diagnostic::error("character literal out of range for char", pos)
+                    .with_help("a char is a single byte, so only ASCII fits in it"),
+            );
+        }
+        Token {
+            kind: TokenKind::Char,
+            value: Some(c.to_string()),
+            pos,
+        }
+    }
+
+    /// the char denoted by an escape sequence, the leading `\` already bumped
+    fn escape(&mut self) -> Option<char> {
+        let start = self.pos - 1;
+        match self.bump() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('\\') => Some('\\'),
+            Some('"') => Some('"'),
+            Some('\'') => Some('\''),
+            Some('0') => Some('\0'),
+            Some(c) => {
+                self.diagnostics.push(Diagnostic::error(
+                    format!("unknown character escape `{}`", c.escape_debug()),
+                    (start, self.pos),
+                ));
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// `/// ...` but not `//// ...`, the leading `/` already bumped
+    fn doc_comment(&mut self) -> Token {
+        self.bump();
+        self.bump();
+        let text = self
+            .chars
+            .clone()
+            .take_while(|&x| x != '\n')
+            .collect::<String>();
+        for _ in text.chars() {
+            self.bump();
+        }
+
+        Token {
+            kind: TokenKind::DocComment,
+            value: Some(text),
+            pos: self.update_pos(),
+        }
+    }
+
+    /// `/* ... */` which may nest, the leading `/` already bumped
+    fn block_comment(&mut self) -> Token {
+        self.bump();
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.bump(), self.next()) {
+                (Some('/'), '*') => {
+                    self.bump();
+                    depth += 1;
+                }
+                (Some('*'), '/') => {
+                    self.bump();
+                    depth -= 1;
+                }
+                (Some(_), _) => {}
+                (None, _) => {
+                    self.diagnostics.push(Diagnostic::error(
+                        "unterminated block comment",
+                        (self.last, self.last + 2),
+                    ));
+                    break;
+                }
+            }
+        }
+
+        self.trivia(TokenKind::BlockComment)
+    }
+
     fn token(&mut self) -> Token {
-        let current_pos = self.pos;
         match self.next() {
             whitespace if whitespace.is_ascii_whitespace() => {
                 while self.next().is_ascii_whitespace() {
                     self.bump();
                 }
-                Token {
-                    kind: TokenKind::Whitespace,
-                    value: None,
-                    pos: self.update_pos(),
-                }
+                self.trivia(TokenKind::Whitespace)
             }
 
             // identity or keyword
@@ -202,65 +868,47 @@ impl<'a> Cursor<'a> {
             }
 
             // numeric literal
-            c if c.is_ascii_digit() => {
-                let num: String = self
-                    .chars
-                    .clone()
-                    .take_while(|&x| x.is_ascii_digit())
-                    .collect::<String>()
-                    .parse()
-                    .unwrap();
-                self.consume(&num);
-                Token {
-                    kind: TokenKind::Number,
-                    value: Some(num),
-                    pos: self.update_pos(),
+            c if c.is_ascii_digit() => self.number(),
+
+            // string literal
+            '"' => self.string(),
+
+            // label, or character literal like `'a'`
+            '\'' => {
+                if self.is_label() {
+                    self.label()
+                } else {
+                    self.character()
                 }
             }
 
             // punctuations
-            '=' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Eq,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '+' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Plus,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '-' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Minus,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '*' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Star,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
+            '=' => self.punct(
+                TokenKind::Eq,
+                &[('=', TokenKind::EqEq), ('>', TokenKind::FatArrow)],
+            ),
+            '+' => self.punct(TokenKind::Plus, &[('=', TokenKind::PlusEq)]),
+            '-' => self.punct(
+                TokenKind::Minus,
+                &[('>', TokenKind::Arrow), ('=', TokenKind::MinusEq)],
+            ),
+            '*' => self.punct(TokenKind::Star, &[('=', TokenKind::StarEq)]),
             '/' => {
                 self.bump();
-                if self.next() == &'/' {
+                if self.next() == &'/' && self.nth(1) == '/' && self.nth(2) != '/' {
+                    self.doc_comment()
+                } else if self.next() == &'/' {
                     // line comment
                     while !self.is_eof() && self.next() != &'\n' {
                         self.bump();
                     }
+                    self.trivia(TokenKind::LineComment)
+                } else if self.next() == &'*' {
+                    self.block_comment()
+                } else if self.next() == &'=' {
                     self.bump();
                     Token {
-                        kind: TokenKind::Whitespace,
+                        kind: TokenKind::SlashEq,
                         value: None,
                         pos: self.update_pos(),
                     }
@@ -272,52 +920,44 @@ impl<'a> Cursor<'a> {
                     }
                 }
             }
-            '%' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Percent,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '&' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::And,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '|' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Or,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '^' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Caret,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
+            '%' => self.punct(TokenKind::Percent, &[('=', TokenKind::PercentEq)]),
+            '&' => self.punct(
+                TokenKind::And,
+                &[('&', TokenKind::AndAnd), ('=', TokenKind::AndEq)],
+            ),
+            '|' => self.punct(
+                TokenKind::Or,
+                &[('|', TokenKind::OrOr), ('=', TokenKind::OrEq)],
+            ),
+            '^' => self.punct(TokenKind::Caret, &[('=', TokenKind::CaretEq)]),
             '<' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Lt,
-                    value: None,
-                    pos: self.update_pos(),
+                if self.nth(1) == '<' && self.nth(2) == '=' {
+                    self.consume("<<=");
+                    Token {
+                        kind: TokenKind::ShlEq,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
+                } else {
+                    self.punct(
+                        TokenKind::Lt,
+                        &[('=', TokenKind::LtEq), ('<', TokenKind::Shl)],
+                    )
                 }
             }
             '>' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Gt,
-                    value: None,
-                    pos: self.update_pos(),
+                if self.nth(1) == '>' && self.nth(2) == '=' {
+                    self.consume(">>=");
+                    Token {
+                        kind: TokenKind::ShrEq,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
+                } else {
+                    self.punct(
+                        TokenKind::Gt,
+                        &[('=', TokenKind::GtEq), ('>', TokenKind::Shr)],
+                    )
                 }
             }
             '(' => {
@@ -376,38 +1016,33 @@ impl<'a> Cursor<'a> {
                     pos: self.update_pos(),
                 }
             }
-            '.' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Dot,
-                    value: None,
-                    pos: self.update_pos(),
-                }
-            }
-            '!' => {
+            '.' => self.punct(TokenKind::Dot, &[('.', TokenKind::DotDot)]),
+            '!' => self.punct(TokenKind::Bang, &[('=', TokenKind::BangEq)]),
+            '?' => {
                 self.bump();
                 Token {
-                    kind: TokenKind::Bang,
+                    kind: TokenKind::Question,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
-            '?' => {
+            '~' => {
                 self.bump();
                 Token {
-                    kind: TokenKind::Question,
+                    kind: TokenKind::Tilde,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
-            ':' => {
+            '#' => {
                 self.bump();
                 Token {
-                    kind: TokenKind::Colon,
+                    kind: TokenKind::Pound,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
+            ':' => self.punct(TokenKind::Colon, &[(':', TokenKind::ColonColon)]),
             ';' => {
                 self.bump();
                 Token {
@@ -417,7 +1052,27 @@ impl<'a> Cursor<'a> {
                 }
             }
 
-            unknown => panic!("unexpected {:?} at {}", unknown, current_pos),
+            // report, skip it and keep lexing to find further errors
+            &unknown => {
+                self.bump();
+                let token = self.trivia(TokenKind::Whitespace);
+                let pos = token.pos;
+
+                let mut diagnostic = Diagnostic::error(
+                    format!("unknown character `{}`", unknown.escape_debug()),
+                    pos,
+                );
+                match suggest(unknown) {
+                    Some(' ') => {
+                        diagnostic = diagnostic.with_help("replace it with a regular space")
+                    }
+                    Some(c) => diagnostic = diagnostic.with_help(format!("did you mean `{}`?", c)),
+                    None => {}
+                }
+                self.diagnostics.push(diagnostic);
+
+                token
+            }
         }
     }
 }
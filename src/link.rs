@@ -0,0 +1,23 @@
+use std::{path::Path, process::Command};
+
+/// assemble `asm` into an object file
+pub fn assemble(asm: &Path, out: &Path) {
+    run(Command::new("gcc").arg("-c").arg(asm).arg("-o").arg(out));
+}
+
+/// assemble and link `asm` into an executable, passing `link_args` through to the linker
+pub fn link(asm: &Path, out: &Path, link_args: &[String]) {
+    run(Command::new("gcc")
+        .arg(asm)
+        .arg("-o")
+        .arg(out)
+        .args(link_args));
+}
+
+fn run(command: &mut Command) {
+    let status = command.status().expect("failed to execute gcc");
+    if !status.success() {
+        eprintln!("error: {:?} failed with {}", command, status);
+        std::process::exit(1);
+    }
+}
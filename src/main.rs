@@ -1,66 +1,544 @@
 mod ast;
+mod ast_map;
 mod cli;
 mod codegen;
+mod const_eval;
+mod defer;
+mod desugar;
+mod diagnostic;
+mod fold;
+mod init;
 mod lexer;
+mod link;
+mod manifest;
 mod parser;
+mod resolve;
+mod source_map;
+mod stats;
 mod ty;
+mod typeck;
+mod visit;
 
 use clap::Parser;
 use std::{
-    fs::File,
-    io::{stdout, Read},
+    io::{stdout, Write},
+    path::Path,
 };
 
 fn main() {
     // read option
-    let args = cli::SofaC::parse();
+    let mut args = cli::SofaC::parse();
+
+    // executable to run once built
+    let mut run = None;
+    match args.command.take() {
+        Some(cli::Command::Init { path }) => {
+            if let Err(e) = init::init(path.as_ref()) {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            println!("Created project `{}`", path);
+            println!("run it with `sofa run` from within it");
+            return;
+        }
+        Some(cli::Command::Run) => {
+            let Some(root) = std::env::current_dir()
+                .ok()
+                .and_then(|x| manifest::find(&x))
+                .and_then(|x| x.parent().map(Path::to_path_buf))
+            else {
+                eprintln!(
+                    "error: could not find `sofa.toml` in the current directory or any parent"
+                );
+                std::process::exit(1);
+            };
+            args.file = Some(root.join("src/main.sofa").display().to_string());
+            args.out_dir = Some(root.join("target").display().to_string());
+            args.emit = cli::Emit::Exe;
+            args.stdout = false;
+            run = Some(args.output_path(""));
+        }
+        None => {}
+    }
 
     // read input source
-    let source = args
-        .console
-        .or_else(|| {
-            let mut f = File::open(args.file.unwrap()).unwrap();
-            let mut buf = String::new();
-            f.read_to_string(&mut buf).unwrap();
-            Some(buf)
-        })
-        .unwrap();
+    let mut sources = source_map::SourceMap::new();
+    let file = match (&args.console, &args.file) {
+        (Some(source), _) => sources.add_file("<console>", source.clone()),
+        (None, Some(path)) => sources.load(path.as_ref()).unwrap_or_else(|e| {
+            eprintln!("error: cannot read {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        (None, None) => {
+            eprintln!("error: no input, give it with `--file` or `--console`");
+            std::process::exit(1);
+        }
+    };
 
-    // tokenize source into tokens
-    let tokens = lexer::tokenize(&source);
+    // the parser takes the tokens straight from the lexer
+    let mut lexer = lexer::Lexer::new(&sources, file);
+    let parser = parser::SofaParser::new(lexer.by_ref());
+    if !lexer.diagnostics().is_empty() {
+        diagnostic::abort(lexer.diagnostics(), &sources);
+    }
+
+    // comments are only kept in the dumped AST
+    let trivia = if args.emit == cli::Emit::Ast {
+        lexer::tokenize_with_trivia(&sources, file)
+            .unwrap_or_else(|e| diagnostic::abort(&e.diagnostics, &sources))
+    } else {
+        vec![]
+    };
+
+    // `mod`s are loaded relative to the root file
+    let root_dir = args
+        .file
+        .as_deref()
+        .and_then(|x| Path::new(x).parent())
+        .map(Path::to_path_buf);
+    let mut load = |path: &str| {
+        let Some(dir) = &root_dir else {
+            eprintln!("error: cannot load module `{}` without a root file", path);
+            std::process::exit(1);
+        };
+        let path = dir.join(path);
+        let file = sources.load(&path).unwrap_or_else(|e| {
+            eprintln!("error: cannot load module {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        lexer::tokenize(&sources, file)
+            .unwrap_or_else(|e| diagnostic::abort(&e.diagnostics, &sources))
+    };
 
     // parse tokens
-    let parser = parser::SofaParser::new(&tokens);
-    let ast = parser.parse();
+    let parser = parser
+        .with_loader(&mut load)
+        .with_unit_assign(args.unit_assign)
+        .with_comments(&trivia);
+    let mut ast = parser.parse().unwrap_or_else(|e| {
+        let diagnostics: Vec<_> = e.iter().map(|x| x.to_diagnostic()).collect();
+        diagnostic::abort(&diagnostics, &sources)
+    });
+    if matches!(args.emit, cli::Emit::Ast | cli::Emit::Dot) {
+        if let Some(dir) = &args.out_dir {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        if args.emit == cli::Emit::Ast {
+            let json = serde_json::to_string(&ast).unwrap();
+            std::fs::write(args.output_path("json"), json).unwrap();
+        } else {
+            std::fs::write(args.output_path("dot"), ast::to_dot(&ast)).unwrap();
+        }
+        return;
+    }
+    desugar::lower(&mut ast);
+    if let Err(errors) = resolve::resolve(&ast) {
+        for e in errors.iter() {
+            eprintln!("error: {}\n  in `{}`\n", e.message, e.item);
+        }
+        eprintln!("error: aborting due to {} previous error(s)", errors.len());
+        std::process::exit(1);
+    }
+    let types = typeck::check(&ast).unwrap_or_else(|errors| {
+        for e in errors.iter() {
+            eprintln!(
+                "{}",
+                e.render(diagnostic::Level::Error, &ast.spans, &sources)
+            );
+        }
+        eprintln!("error: aborting due to {} previous error(s)", errors.len());
+        std::process::exit(1);
+    });
+    for w in typeck::unused_values(&ast, &types).iter() {
+        eprintln!(
+            "{}",
+            w.render(diagnostic::Level::Warning, &ast.spans, &sources)
+        );
+    }
 
     // generate assembly
+    let mut asm = vec![];
+    {
+        let mut generater = codegen::SofaGenerater::new(&mut asm);
+        if args.debug_info {
+            generater = generater.with_debug_info(&sources);
+        }
+        generater.gen(&ast, types);
+    } // flushed on drop
+
+    if let Some(format) = args.stats {
+        let stats = stats::collect(std::str::from_utf8(&asm).unwrap());
+        match format {
+            cli::StatsFormat::Text => eprint!("{}", stats::to_text(&stats)),
+            cli::StatsFormat::Json => eprintln!("{}", stats::to_json(&stats)),
+        }
+    }
+
     if args.stdout {
-        let mut generater = codegen::SofaGenerater::new(stdout());
-        generater.gen(&ast);
+        stdout().write_all(&asm).unwrap();
     } else {
-        let out = args.out.unwrap_or_else(|| "tmp.s".to_string());
-        let mut generater = codegen::SofaGenerater::new(
-            std::fs::File::options()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(out)
-                .unwrap(),
-        );
-        generater.gen(&ast);
+        if let Some(dir) = &args.out_dir {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let asm_path = if args.emit == cli::Emit::Asm {
+            args.output_path("s")
+        } else {
+            std::env::temp_dir().join(format!("sofa{}.s", std::process::id()))
+        };
+        std::fs::write(&asm_path, &asm).unwrap();
+
+        match args.emit {
+            cli::Emit::Asm | cli::Emit::Ast | cli::Emit::Dot => {}
+            cli::Emit::Obj => link::assemble(&asm_path, &args.output_path("o")),
+            cli::Emit::Exe => {
+                let link_args = args.link_args().unwrap_or_else(|e| {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                });
+                link::link(&asm_path, &args.output_path(""), &link_args)
+            }
+        }
+        if args.emit != cli::Emit::Asm {
+            std::fs::remove_file(asm_path).unwrap();
+        }
     }
+
+    if let Some(exe) = run {
+        let status = std::process::Command::new(&exe)
+            .status()
+            .unwrap_or_else(|e| {
+                eprintln!("error: cannot run {}: {}", exe.display(), e);
+                std::process::exit(1);
+            });
+        // killed by a signal if there is no code
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+/// `s` as a whole file, with its comments
+#[cfg(test)]
+fn parse(s: &str) -> ast::Ast {
+    let mut sources = source_map::SourceMap::new();
+    let file = sources.add_file("test.sofa", s.to_string());
+    let tokens = lexer::tokenize(&sources, file).unwrap();
+    let trivia = lexer::tokenize_with_trivia(&sources, file).unwrap();
+    parser::SofaParser::new(tokens)
+        .with_comments(&trivia)
+        .parse()
+        .unwrap()
 }
 
 #[test]
 fn test_example() {
     let s = include_str!("../example/test.sofa");
-    let tokens = lexer::tokenize(s);
+    let mut sources = source_map::SourceMap::new();
+    let file = sources.add_file("test.sofa", s.to_string());
+    let tokens = lexer::tokenize(&sources, file).unwrap();
     // dbg!(&tokens);
 
-    let parser = parser::SofaParser::new(&tokens);
-    let ast = parser.parse();
+    let parser = parser::SofaParser::new(tokens);
+    let ast = parser.parse().unwrap();
     dbg!(&ast);
 
     let mut generater = codegen::SofaGenerater::new(std::io::stdout());
-    generater.gen(&ast);
+    generater.gen(&ast, typeck::check(&ast).unwrap());
+}
+
+#[test]
+fn test_manifest_link() {
+    let s = r#"
+[package]
+name = "demo"
+args = "not linked"
+
+[link]
+# extern math
+libs = ["m"]
+args = ["-static", "-Wl,--gc-sections",]
+"#;
+    let link = manifest::link(s).unwrap();
+    assert_eq!(link.libs, ["m"]);
+    assert_eq!(link.args, ["-static", "-Wl,--gc-sections"]);
+
+    let e = manifest::link("[link]\nflags = []\n").unwrap_err();
+    assert_eq!(e, "line 2: unknown key `flags` in [link]");
+    // `sofa init`'s has none
+    assert_eq!(
+        manifest::link("[package]\nname = \"demo\"\n"),
+        Ok(manifest::Link::default())
+    );
+}
+
+#[test]
+fn test_codegen_option() {
+    let args = cli::SofaC::try_parse_from(["sofa", "-C", "link-arg=-static"]).unwrap();
+    assert_eq!(
+        args.codegen,
+        [cli::CodegenOption::LinkArg("-static".to_string())]
+    );
+    assert!(cli::SofaC::try_parse_from(["sofa", "-C", "foo"]).is_err());
+}
+
+#[test]
+fn test_try_size_unknown() {
+    use ty::Type;
+    let param = Type::Param {
+        name: "T".to_string(),
+    };
+    let tuple = Type::Tuple {
+        elements: vec![Type::I64, param.clone()],
+    };
+    assert_eq!(tuple.try_size(), None);
+    let variants = vec![("A".to_string(), Some(param)), ("B".to_string(), None)];
+    let ty = Type::Enum {
+        name: "E".to_string(),
+        variants,
+    };
+    assert_eq!(ty.try_size(), None);
+    assert_eq!(ty.try_align(), Some(8));
+}
+
+#[test]
+fn test_resolve_shadowing() {
+    let s = "fn main() -> i64 { let a = 1; { let a = 2; a; } let b = a; let a = b + 1; a }";
+    let ast = parse(s);
+
+    let shadowings = resolve::resolve(&ast).unwrap();
+    let names: Vec<_> = shadowings
+        .iter()
+        .map(|x| (&x.name[..], &x.item[..]))
+        .collect();
+    assert_eq!(names, [("a", "main"), ("a", "main")]);
+    assert!(matches!(ast.arena[shadowings[1].init], ast::Expr::Init(_)));
+}
+
+#[test]
+fn test_block_slots_reused() {
+    let s = "fn main() -> i64 { { let a: [i64; 4] = [1, 2, 3, 4]; } { let b: [i64; 4] = [5, 6, 7, 8]; } 0 }";
+    let ast = parse(s);
+
+    let mut asm = vec![];
+    codegen::SofaGenerater::new(&mut asm).gen(&ast, typeck::check(&ast).unwrap());
+    // a slot for the array and one for the temporary it is copied from, in either block
+    assert!(String::from_utf8(asm)
+        .unwrap()
+        .contains(".set .Lmain_frame, 64"));
+}
+
+#[test]
+fn test_stats_skip_strings() {
+    let s = r#"fn main() -> i64 { let s = "hello"; 0 }"#;
+    let ast = parse(s);
+
+    let mut asm = vec![];
+    codegen::SofaGenerater::new(&mut asm).gen(&ast, typeck::check(&ast).unwrap());
+    let asm = String::from_utf8(asm).unwrap();
+    // the string literals in .rodata come after the last fn
+    let (text, rodata) = asm.split_once(".section .rodata").unwrap();
+    assert!(rodata.contains(".string \"hello\""));
+    let [main] = &stats::collect(&asm)[..] else {
+        panic!("expected fn main")
+    };
+    assert_eq!(main.instructions, stats::collect(text)[0].instructions);
+}
+
+#[test]
+fn test_trivia_round_trip() {
+    let s = "#!/usr/bin/env sofa\n/* a */ fn main() { // b\n    return 1; }\n";
+    let mut sources = source_map::SourceMap::new();
+    let file = sources.add_file("test.sofa", s.to_string());
+    let tokens = lexer::tokenize_with_trivia(&sources, file).unwrap();
+
+    let mut res = String::new();
+    for t in tokens.iter() {
+        for x in t.leading.iter().chain([&t.token]).chain(t.trailing.iter()) {
+            res += &s[x.pos.0..x.pos.1];
+        }
+    }
+    assert_eq!(res, s);
+    assert_eq!(tokens[0].leading.len(), 4); // shebang, newline, `/* a */`, space
+    assert_eq!(tokens[4].trailing.len(), 2); // ` `, `// b`
+}
+
+#[test]
+fn test_comments() {
+    let s =
+        "// a\n/// b\n#[no_mangle]\n/// c\nfn main() {\n    /* d */ let x = 1;\n    x; // e\n}\n";
+    let ast = parse(s);
+
+    let ast::Definition::Fn(main) = &ast.node.definitions[0] else {
+        panic!("expected fn main")
+    };
+    let texts: Vec<_> = main.comments.iter().map(|x| (&x.text[..], x.doc)).collect();
+    assert_eq!(texts, [("// a", false), (" b", true), (" c", true)]);
+
+    // a trailing comment belongs to the line it ends
+    let [(id, comments)] = &ast.comments.iter().collect::<Vec<_>>()[..] else {
+        panic!("expected one commented statement")
+    };
+    assert_eq!(**id, main.body.exprs[0]);
+    assert_eq!(comments[0].text, "/* d */");
+
+    // keyed by id in the JSON too
+    let json = serde_json::to_string(&ast).unwrap();
+    let read: ast::Ast = serde_json::from_str(&json).unwrap();
+    assert_eq!(&read.comments[id], *comments);
+}
+
+#[test]
+fn test_ast_json_round_trip() {
+    let s = include_str!("../example/test.sofa");
+    let ast = parse(s);
+
+    let json = serde_json::to_string(&ast).unwrap();
+    let read: ast::Ast = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&read).unwrap(), json);
+
+    // the same program either way
+    let mut expected = vec![];
+    codegen::SofaGenerater::new(&mut expected).gen(&ast, typeck::check(&ast).unwrap());
+    let mut actual = vec![];
+    codegen::SofaGenerater::new(&mut actual).gen(&read, typeck::check(&read).unwrap());
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_ast_to_dot() {
+    let s = "fn main() -> i64 { let a = 1; a + 2 }";
+    let ast = parse(s);
+
+    let dot = ast::to_dot(&ast);
+    assert!(dot.starts_with("digraph ast {"));
+    // a tree, every node but the root has a parent
+    let nodes = dot.matches("[label=").count();
+    assert_eq!(dot.matches(" -> ").count(), nodes - 1);
+    assert!(dot.contains("[label=\"Add\"]"));
+}
+
+#[test]
+fn test_ast_map() {
+    let s = "fn f() -> i64 { 1 } fn main() -> i64 { let a = 1; if a == 1 { a + 2 } else { f() } }";
+    let ast = parse(s);
+    let map = ast_map::AstMap::new(&ast);
+
+    let ast::Definition::Fn(main) = map.item(1) else {
+        panic!("expected fn main")
+    };
+    let &[_, tail] = &main.body.exprs[..] else {
+        panic!("expected 2 exprs")
+    };
+    assert_eq!(map.parent(tail), Some(ast_map::Parent::Item(1)));
+    let ast::Expr::IfElse(ifelse) = map.get(tail) else {
+        panic!("expected if")
+    };
+    let add = ifelse.if_body.exprs[0];
+    assert_eq!(map.parent(add), Some(ast_map::Parent::Expr(tail)));
+    assert_eq!(map.enclosing_fn(add).unwrap().name, "main");
+}
+
+#[test]
+fn test_build_ast() {
+    use ast::{build, BinOpKind};
+    use ty::Type;
+
+    let mut b = build::Builder::new();
+    // fn double(x: i64) -> i64 { x + x }
+    let (lhs, rhs) = (b.local("x", Type::I64), b.local("x", Type::I64));
+    let sum = b.binop(BinOpKind::Add, lhs, rhs);
+    let double = build::fn_def("double")
+        .arg("x", Type::I64)
+        .ret(Type::I64)
+        .block(vec![sum]);
+
+    // fn main() -> i64 { let a = 0; a = double(20); return if true { a + 2 } else { 0 } }
+    let zero = b.number(0);
+    let init = b.init("a", zero);
+    let twenty = b.number(20);
+    let call = b.call("double", double.fn_type.clone(), vec![twenty]);
+    let a = b.local("a", Type::I64);
+    let assign = b.assign(a, call);
+    let assign = b.stmt(assign);
+    let cond = b.bool(true);
+    let (a, two) = (b.local("a", Type::I64), b.number(2));
+    let then = b.binop(BinOpKind::Add, a, two);
+    let otherwise = b.number(0);
+    let if_else = b.if_else(cond, vec![then], vec![otherwise]);
+    let tail = b.ret(if_else);
+    let main = build::fn_def("main")
+        .ret(Type::I64)
+        .block(vec![init, assign, tail]);
+    let ast = b.finish(vec![double, main]);
+
+    let mut asm = vec![];
+    codegen::SofaGenerater::new(&mut asm).gen(&ast, typeck::check(&ast).unwrap());
+    let asm = String::from_utf8(asm).unwrap();
+    assert!(asm.contains("double:"));
+    assert!(asm.contains("call double"));
+    assert!(asm.contains("add rax, 2"));
+}
+
+#[test]
+fn test_unresolved_call() {
+    use ast::build;
+    use ty::Type;
+
+    // fn main() -> i64 { g(1) }, without `g`
+    let mut b = build::Builder::new();
+    let one = b.number(1);
+    let call = b.call("g", Type::Unknown, vec![one]);
+    let main = build::fn_def("main").ret(Type::I64).block(vec![call]);
+    let ast = b.finish(vec![main]);
+
+    let errors = typeck::check(&ast).unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|x| &x.message[..]).collect();
+    assert_eq!(messages, ["cannot find function `g`"]);
+}
+
+#[test]
+fn test_structural_eq() {
+    use ast::{build, structural::ExprRef, BinOpKind};
+    use std::collections::HashSet;
+    use ty::Type;
+
+    let ast = parse("fn main() -> i64 { let a = 1; (a + 2) * (a + 2) }");
+    let commented =
+        parse("/// main\nfn main() -> i64 {\n    // a\n    let a = 1;\n    (a + 2) * (a + 2)\n}");
+    assert_eq!(ast, commented);
+    assert_ne!(
+        ast,
+        parse("fn main() -> i64 { let a = 1; (a + 2) * (a + 3) }")
+    );
+
+    // the same tree, allocated in another order
+    let mut b = build::Builder::new();
+    let sum = |b: &mut build::Builder| {
+        let (a, two) = (b.local("a", Type::I64), b.number(2));
+        let sum = b.binop(BinOpKind::Add, a, two);
+        b.expr(ast::Expr::Enclosed(ast::Enclosed { expr: sum }))
+    };
+    let (rhs, lhs) = (sum(&mut b), sum(&mut b));
+    let product = b.binop(BinOpKind::Mul, lhs, rhs);
+    let one = b.number(1);
+    let init = b.init("a", one);
+    let init = b.stmt(init);
+    let main = build::fn_def("main")
+        .ret(Type::I64)
+        .block(vec![init, product]);
+    assert_eq!(b.finish(vec![main]), ast);
+
+    // both sides of `*` are the same subtree
+    let ast::Definition::Fn(main) = &ast.node.definitions[0] else {
+        panic!("expected fn main")
+    };
+    let ast::Expr::BinOp(product) = &ast.arena[main.body.exprs[1]] else {
+        panic!("expected `*`")
+    };
+    let subtrees: HashSet<_> = [product.lhs, product.rhs, main.body.exprs[1]]
+        .into_iter()
+        .map(|id| ExprRef {
+            arena: &ast.arena,
+            id,
+        })
+        .collect();
+    assert_eq!(subtrees.len(), 2);
 }
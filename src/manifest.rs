@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+/// `[link]` of a `sofa.toml`, forwarded to the link step before the command line's
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Link {
+    /// passed to the linker as is, like `-C link-arg=`
+    pub args: Vec<String>,
+    /// native libraries, like `-l`
+    pub libs: Vec<String>,
+}
+
+/// the `sofa.toml` in `dir` or the closest of its ancestors
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|x| x.join("sofa.toml"))
+        .find(|x| x.is_file())
+}
+
+/// read `[link]` from the manifest `s`, other tables being left alone.
+/// only the TOML `sofa init` and this need is understood, strings and arrays of them on one line
+pub fn link(s: &str) -> Result<Link, String> {
+    let mut res = Link::default();
+    let mut table = "";
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            table = name.trim();
+            continue;
+        }
+        if table != "link" {
+            continue;
+        }
+
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `key = value`"))?;
+        let values = strings(value.trim()).ok_or_else(|| error("expected an array of strings"))?;
+        match key.trim() {
+            "args" => res.args = values,
+            "libs" => res.libs = values,
+            key => return Err(error(&format!("unknown key `{}` in [link]", key))),
+        }
+    }
+    Ok(res)
+}
+
+/// `["a", "b"]`, without escapes
+fn strings(s: &str) -> Option<Vec<String>> {
+    let mut rest = s.strip_prefix('[')?.trim_start();
+    let mut res = vec![];
+    while !rest.starts_with(']') {
+        let (value, after) = rest.strip_prefix('"')?.split_once('"')?;
+        res.push(value.to_string());
+        rest = after.trim_start();
+        // a trailing comma is allowed
+        match rest.strip_prefix(',') {
+            Some(after) => rest = after.trim_start(),
+            None if rest.starts_with(']') => {}
+            None => return None,
+        }
+    }
+    (rest == "]").then_some(res)
+}
@@ -1,34 +1,312 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
     ast::{
-        Assign, Ast, BinOp, BinOpKind, Block, Bool, Enclosed, Expr, FnCall, FnDef, Global, IfElse,
-        Init, Local, Loop, Number, Return, Stmt, UnOp, UnOpKind,
+        Arena, Arm, Array, Assign, Ast, Attribute, BinOp, BinOpKind, Block, Bool, Break, Call,
+        Cast, Char, Closure, Comment, Continue, Defer, Definition, Enclosed, Expr, Extern, Field,
+        Float, FnCall, FnDef, For, Global, IfElse, Index, Init, Local, Loop, Match, NodeId, Number,
+        Pattern, Range, Return, Static, Stmt, Str, Struct, Tuple, UnOp, UnOpKind, Variant, While,
     },
-    lexer::{Token, TokenKind},
+    const_eval,
+    diagnostic::Diagnostic,
+    fold::deep_copy,
+    lexer::{Token, TokenKind, TriviaToken},
     ty::Type,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assoc {
+    Left,
+    Right,
+    /// `a < b < c` is rejected
+    Non,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Infix {
+    BinOp(BinOpKind),
+    Assign,
+    /// `op=`
+    AssignOp(BinOpKind),
+    /// `as`, taking a type on the right
+    Cast,
+    /// `? a : b`, taking two operands on the right
+    Ternary,
+}
+
+/// infix operators with their precedence, higher binds tighter
+#[rustfmt::skip]
+const INFIX: &[(TokenKind, u8, Assoc, Infix)] = &[
+    (TokenKind::Eq, 0, Assoc::Right, Infix::Assign),
+    (TokenKind::PlusEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Add)),
+    (TokenKind::MinusEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Sub)),
+    (TokenKind::StarEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Mul)),
+    (TokenKind::SlashEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Div)),
+    (TokenKind::PercentEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Rem)),
+    (TokenKind::AndEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::BitAnd)),
+    (TokenKind::OrEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::BitOr)),
+    (TokenKind::CaretEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::BitXor)),
+    (TokenKind::ShlEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Shl)),
+    (TokenKind::ShrEq, 0, Assoc::Right, Infix::AssignOp(BinOpKind::Shr)),
+    (TokenKind::Question, 1, Assoc::Right, Infix::Ternary),
+    (TokenKind::OrOr, 2, Assoc::Left, Infix::BinOp(BinOpKind::LogOr)),
+    (TokenKind::AndAnd, 3, Assoc::Left, Infix::BinOp(BinOpKind::LogAnd)),
+    (TokenKind::EqEq, 4, Assoc::Non, Infix::BinOp(BinOpKind::Eq)),
+    (TokenKind::BangEq, 4, Assoc::Non, Infix::BinOp(BinOpKind::Neq)),
+    (TokenKind::LtEq, 4, Assoc::Non, Infix::BinOp(BinOpKind::LeEq)),
+    (TokenKind::Lt, 4, Assoc::Non, Infix::BinOp(BinOpKind::Le)),
+    (TokenKind::GtEq, 4, Assoc::Non, Infix::BinOp(BinOpKind::GtEq)),
+    (TokenKind::Gt, 4, Assoc::Non, Infix::BinOp(BinOpKind::Gt)),
+    (TokenKind::Or, 5, Assoc::Left, Infix::BinOp(BinOpKind::BitOr)),
+    (TokenKind::Caret, 6, Assoc::Left, Infix::BinOp(BinOpKind::BitXor)),
+    (TokenKind::And, 7, Assoc::Left, Infix::BinOp(BinOpKind::BitAnd)),
+    (TokenKind::Shl, 8, Assoc::Left, Infix::BinOp(BinOpKind::Shl)),
+    (TokenKind::Shr, 8, Assoc::Left, Infix::BinOp(BinOpKind::Shr)),
+    (TokenKind::Plus, 9, Assoc::Left, Infix::BinOp(BinOpKind::Add)),
+    (TokenKind::Minus, 9, Assoc::Left, Infix::BinOp(BinOpKind::Sub)),
+    (TokenKind::Star, 10, Assoc::Left, Infix::BinOp(BinOpKind::Mul)),
+    (TokenKind::Slash, 10, Assoc::Left, Infix::BinOp(BinOpKind::Div)),
+    (TokenKind::Percent, 10, Assoc::Left, Infix::BinOp(BinOpKind::Rem)),
+    (TokenKind::As, 11, Assoc::Left, Infix::Cast),
+];
+
+/// prefix operators, all binding tighter than `INFIX`
+const PREFIX: &[(TokenKind, UnOpKind)] = &[
+    (TokenKind::Star, UnOpKind::Deref),
+    (TokenKind::And, UnOpKind::Ref),
+    (TokenKind::Bang, UnOpKind::Not),
+    (TokenKind::Tilde, UnOpKind::BitNot),
+    (TokenKind::Minus, UnOpKind::Neg),
+];
+
+pub type Loader<'a> = dyn FnMut(&str) -> Vec<Token> + 'a;
+
+type PResult<T> = Result<T, ParseError>;
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// span of the offending tokens
+    pub pos: (usize, usize),
+    pub kind: ParseErrorKind,
+    pub help: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ParseErrorKind {
+    /// a token found where the grammar doesn't allow it
+    Unexpected {
+        found: TokenKind,
+        /// what would have been accepted instead, like `;` or `expression`
+        expected: Vec<String>,
+    },
+    /// well-formed but meaningless, like `1 < 2 < 3`
+    Invalid(String),
+}
+
+impl ParseError {
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let res = match &self.kind {
+            ParseErrorKind::Unexpected { found, expected } => {
+                let expected = match expected.as_slice() {
+                    [one] => one.clone(),
+                    many => format!("one of {}", many.join(", ")),
+                };
+                Diagnostic::error(format!("expected {}, found {}", expected, found), self.pos)
+            }
+            ParseErrorKind::Invalid(message) => Diagnostic::error(message, self.pos),
+        };
+        match &self.help {
+            Some(help) => res.with_help(help),
+            None => res,
+        }
+    }
+}
+
+/// locals declared within a closure, and what it captures from outside
+struct ClosureScope {
+    declared: HashSet<String>,
+    /// name, value in the enclosing scope, and offset in the environment
+    captures: Vec<(String, NodeId, usize)>,
+}
+
+/// names bound by a destructuring `let`, before the type of the value is known
+enum LetPattern {
+    /// with whether it is `mut`
+    Name(String, bool),
+    Wildcard,
+    Tuple(Vec<LetPattern>),
+    /// fields with what they are bound to, and whether the rest is left out with `..`
+    Struct(String, Vec<(String, LetPattern)>, bool),
+}
+
+/// fn with type parameters, parsed again for each set of types it is called with
+struct Generic {
+    /// type parameters, along with the traits each must implement
+    params: Vec<(String, Vec<String>)>,
+    tokens: Rc<[Token]>,
+    /// position of its `fn`
+    head: usize,
+    /// module it is defined in, where its body is resolved
+    prefix: String,
+    uses: HashMap<String, String>,
+}
+
+/// fn generated from a generic for `tys`
+struct Instance {
+    generic: String,
+    tys: Vec<Type>,
+    name: String,
+    fn_type: Type,
+}
+
 pub struct SofaParser<'ctx> {
     head: usize,
-    tokens: &'ctx [Token],
+    tokens: Rc<[Token]>,
     /// mapping idents to signatures
     // TODO:
     // id -> (name?, type, scope)
     signatures: HashMap<String, Type>,
+    /// names of fns and statics, as opposed to locals
+    items: HashSet<String>,
+    /// enclosing closures, innermost last
+    closures: Vec<ClosureScope>,
+    /// default values of the trailing parameters of each fn, filled in at calls leaving them out
+    defaults: HashMap<String, Vec<Expr>>,
+    /// type of `Self` within an `impl`
+    self_ty: Option<Type>,
+    /// required methods of each trait, with `Self` as a parameter
+    traits: HashMap<String, Vec<(String, Type)>>,
+    /// pairs of a type and a trait implemented for it
+    impls: HashSet<(String, String)>,
+    /// fns with type parameters by name
+    generics: HashMap<String, Generic>,
+    /// fns generated from generics so far
+    instances: Vec<Instance>,
+    /// fns lifted out of closures and generated from generics so far
+    lifted: Vec<FnDef>,
+    /// closures lifted so far in every module, numbering their fns
+    closure_count: usize,
+    /// user-defined types by name
+    types: HashMap<String, Type>,
+    /// values of consts, inlined at each use
+    consts: HashMap<String, Expr>,
+    /// type broken out of each enclosing loop so far, innermost last
+    breaks: Vec<(Option<String>, Option<Type>)>,
+    /// path of the module being parsed, like `foo::bar::`, empty at the root
+    prefix: String,
+    /// names brought into scope by `use`, mapped to their full paths
+    uses: HashMap<String, String>,
+    /// tokenizes the file of a `mod`, given its path relative to the root file
+    loader: Option<&'ctx mut Loader<'ctx>>,
+    /// whether assignments evaluate to unit rather than to the value stored
+    unit_assign: bool,
+    /// every expression parsed so far, shared with the parsers of `mod`s
+    arena: Arena<Expr>,
+    /// comments before the token starting at each offset, if parsed with them
+    trivia: HashMap<usize, Vec<Comment>>,
+    /// comments attached to statements so far
+    comments: BTreeMap<NodeId, Vec<Comment>>,
+    /// source span of each expression allocated, where it has one
+    spans: BTreeMap<NodeId, (usize, usize)>,
+    /// tokens `start..end` of the expression parsed last, spanned by the next one allocated
+    last: (usize, usize),
+    /// errors recovered from so far
+    errors: Vec<ParseError>,
+    /// whether an error skipped everything up to the next item,
+    /// leaving the enclosing blocks unclosed
+    skipped_to_item: bool,
 }
 
 impl<'ctx> SofaParser<'ctx> {
-    pub fn new(tokens: &'ctx [Token]) -> Self {
+    /// parser over `tokens`, taken in full up front as generics and lookahead go back over them
+    pub fn new(tokens: impl IntoIterator<Item = Token>) -> Self {
         Self {
             head: 0,
-            tokens,
+            tokens: tokens.into_iter().collect(),
             signatures: HashMap::new(),
+            items: HashSet::new(),
+            closures: vec![],
+            defaults: HashMap::new(),
+            self_ty: None,
+            traits: HashMap::new(),
+            impls: HashSet::new(),
+            generics: HashMap::new(),
+            instances: vec![],
+            lifted: vec![],
+            closure_count: 0,
+            types: HashMap::new(),
+            consts: HashMap::new(),
+            breaks: vec![],
+            prefix: String::new(),
+            uses: HashMap::new(),
+            loader: None,
+            unit_assign: false,
+            arena: Arena::new(),
+            trivia: HashMap::new(),
+            comments: BTreeMap::new(),
+            spans: BTreeMap::new(),
+            last: (0, 0),
+            errors: vec![],
+            skipped_to_item: false,
         }
     }
 
+    pub fn with_loader(mut self, loader: &'ctx mut Loader<'ctx>) -> Self {
+        self.loader = Some(loader);
+        self
+    }
+
+    /// the semantics before chaining, where `a = b = c` is a type error
+    pub fn with_unit_assign(mut self, unit_assign: bool) -> Self {
+        self.unit_assign = unit_assign;
+        self
+    }
+
+    /// attach the comments in `tokens` to the items and statements they precede,
+    /// `tokens` being those parsed along with their trivia
+    pub fn with_comments(mut self, tokens: &[TriviaToken]) -> Self {
+        for t in tokens.iter() {
+            let comments: Vec<_> = t
+                .leading
+                .iter()
+                .filter(|x| x.kind != TokenKind::Whitespace)
+                .map(|x| Comment {
+                    text: x.value.clone().unwrap(),
+                    doc: false,
+                })
+                .collect();
+            if !comments.is_empty() {
+                self.trivia.insert(t.token.pos.0, comments);
+            }
+        }
+        self
+    }
+
+    /// allocate `expr` spanning the expression parsed last, which it is usually made of
+    fn alloc(&mut self, expr: Expr) -> NodeId {
+        self.alloc_at(expr, self.last)
+    }
+
+    /// allocate `expr` spanning the tokens `start..end`
+    fn alloc_at(&mut self, expr: Expr, (start, end): (usize, usize)) -> NodeId {
+        let id = self.arena.alloc(expr);
+        if start < end {
+            let span = (self.tokens[start].pos.0, self.tokens[end - 1].pos.1);
+            self.spans.insert(id, span);
+        }
+        id
+    }
+
     fn is_eof(&mut self) -> bool {
-        self.head >= self.tokens.len()
+        self.peek(&[TokenKind::Eof])
     }
 
     fn get(&self) -> &Token {
@@ -36,7 +314,11 @@ impl<'ctx> SofaParser<'ctx> {
     }
 
     fn peek(&mut self, target: &[TokenKind]) -> bool {
-        (0..target.len()).all(|i| !self.is_eof() && self.tokens[self.head + i].kind == target[i])
+        (0..target.len()).all(|i| {
+            self.tokens
+                .get(self.head + i)
+                .is_some_and(|x| x.kind == target[i])
+        })
     }
 
     fn consume(&mut self, target: &[TokenKind]) -> bool {
@@ -48,67 +330,106 @@ impl<'ctx> SofaParser<'ctx> {
         }
     }
 
-    fn expect(&mut self, target: &[TokenKind]) {
-        if !(self.consume(target)) {
-            panic!("found {:?}, not {:?}", self.tokens[self.head], target)
-        }
-    }
-
-    fn consume_binop(&mut self) -> Option<BinOpKind> {
-        if self.consume(&[TokenKind::Eq, TokenKind::Eq]) {
-            Some(BinOpKind::Eq)
-        } else if self.consume(&[TokenKind::Bang, TokenKind::Eq]) {
-            Some(BinOpKind::Neq)
-        } else if self.consume(&[TokenKind::Lt, TokenKind::Eq]) {
-            Some(BinOpKind::LeEq)
-        } else if self.consume(&[TokenKind::Lt]) {
-            Some(BinOpKind::Le)
-        } else if self.consume(&[TokenKind::Gt, TokenKind::Eq]) {
-            Some(BinOpKind::GtEq)
-        } else if self.consume(&[TokenKind::Gt]) {
-            Some(BinOpKind::Gt)
-        } else if self.consume(&[TokenKind::Plus]) {
-            Some(BinOpKind::Add)
-        } else if self.consume(&[TokenKind::Minus]) {
-            Some(BinOpKind::Sub)
-        } else if self.consume(&[TokenKind::Star]) {
-            Some(BinOpKind::Mul)
-        } else if self.consume(&[TokenKind::Slash]) {
-            Some(BinOpKind::Div)
-        } else if self.consume(&[TokenKind::Percent]) {
-            Some(BinOpKind::Rem)
-        } else if self.consume(&[TokenKind::And, TokenKind::And]) {
-            Some(BinOpKind::LogAnd)
-        } else if self.consume(&[TokenKind::Or, TokenKind::Or]) {
-            Some(BinOpKind::LogOr)
-        } else if self.consume(&[TokenKind::And]) {
-            Some(BinOpKind::BitAnd)
-        } else if self.consume(&[TokenKind::Or]) {
-            Some(BinOpKind::BitOr)
-        } else if self.consume(&[TokenKind::Caret]) {
-            Some(BinOpKind::BitXor)
+    fn expect(&mut self, target: &[TokenKind]) -> PResult<()> {
+        if self.consume(target) {
+            Ok(())
         } else {
-            None
+            Err(self.unexpected(target))
         }
     }
 
-    fn expect_ident(&mut self) -> String {
+    /// error at the current token, which is none of `expected`
+    fn unexpected<T: ToString>(&self, expected: impl IntoIterator<Item = T>) -> ParseError {
+        let found = self.get();
+        ParseError {
+            pos: found.pos,
+            kind: ParseErrorKind::Unexpected {
+                found: found.kind,
+                expected: expected.into_iter().map(|x| x.to_string()).collect(),
+            },
+            help: None,
+        }
+    }
+
+    /// type of `expr`, parsed from the token at `start`, or why it is ill-typed
+    fn expr_ty(&self, expr: &Expr, start: usize) -> PResult<Type> {
+        expr.checked_ty(&self.arena)
+            .map_err(|e| self.invalid(start, e))
+    }
+
+    /// error spanning the tokens from the one at `start` to the last one consumed
+    fn invalid(&self, start: usize, message: impl Into<String>) -> ParseError {
+        let end = self.tokens[self.head.max(start + 1) - 1].pos.1;
+        ParseError {
+            pos: (self.tokens[start].pos.0, end),
+            kind: ParseErrorKind::Invalid(message.into()),
+            help: None,
+        }
+    }
+
+    fn expect_ident(&mut self) -> PResult<String> {
         let id = self.tokens[self.head].value.clone();
-        self.expect(&[TokenKind::Ident]);
-        id.unwrap()
+        self.expect(&[TokenKind::Ident])?;
+        Ok(id.unwrap())
     }
 
-    fn expect_number(&mut self) -> String {
+    fn peek_contextual(&mut self, keyword: &str) -> bool {
+        self.peek(&[TokenKind::Ident]) && self.get().value.as_deref() == Some(keyword)
+    }
+
+    /// keyword lexed as `Ident`, reserved only at this point of the grammar
+    fn expect_contextual(&mut self, keyword: &str) -> PResult<()> {
+        if self.peek_contextual(keyword) {
+            self.head += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected([format!("`{}`", keyword)]))
+        }
+    }
+
+    fn expect_number(&mut self) -> PResult<String> {
         let id = self.tokens[self.head].value.clone();
-        self.expect(&[TokenKind::Number]);
-        id.unwrap()
+        self.expect(&[TokenKind::Number])?;
+        Ok(id.unwrap())
+    }
+
+    /// skip the rest of a broken statement, up to and including its `;`,
+    /// or up to the `}` closing its block or the next `fn`
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.get().kind {
+                TokenKind::Eof | TokenKind::Fn => {
+                    self.skipped_to_item = true;
+                    return;
+                }
+                TokenKind::RBrace if depth == 0 => return,
+                TokenKind::Semi if depth == 0 => {
+                    self.head += 1;
+                    return;
+                }
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+            self.head += 1;
+        }
     }
 }
 
 impl<'ctx> SofaParser<'ctx> {
-    pub fn parse(mut self) -> Ast {
-        Ast {
-            node: self.global(),
+    /// every error found, which does not stop at the first one
+    pub fn parse(mut self) -> Result<Ast, Vec<ParseError>> {
+        let node = self.global();
+        if self.errors.is_empty() {
+            Ok(Ast {
+                arena: self.arena,
+                node,
+                comments: self.comments,
+                spans: self.spans,
+            })
+        } else {
+            Err(self.errors)
         }
     }
 
@@ -116,36 +437,750 @@ impl<'ctx> SofaParser<'ctx> {
         let mut res = Global {
             definitions: vec![],
         };
+        let start = self.arena.next_id();
 
         loop {
+            let comments = self.comments();
+            self.skipped_to_item = false;
+
             if self.is_eof() {
+                let lifted = std::mem::take(&mut self.lifted);
+                res.definitions
+                    .extend(lifted.into_iter().map(Definition::Fn));
+                self.forward_calls(start);
                 break res;
+            }
+            match self.item(comments) {
+                Ok(definitions) => res.definitions.extend(definitions),
+                Err(e) => {
+                    self.errors.push(e);
+                    // skip to the next item
+                    self.head += 1;
+                    while !self.is_eof() && !self.peek(&[TokenKind::Fn]) {
+                        self.head += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn item(&mut self, mut comments: Vec<Comment>) -> PResult<Vec<Definition>> {
+        let start = self.head;
+        let attrs = self.attributes(&mut comments)?;
+        // everything is visible from everywhere for now
+        self.consume(&[TokenKind::Pub]);
+        if !attrs.is_empty() && !self.peek(&[TokenKind::Fn]) {
+            return Err(self.invalid(start, "attributes are only allowed on fns"));
+        }
+
+        let res = if self.peek(&[TokenKind::Mod]) {
+            self.mod_decl()?
+        } else if self.peek(&[TokenKind::Use]) {
+            self.use_decl()?;
+            vec![]
+        } else if self.peek(&[TokenKind::Struct]) {
+            self.struct_def()?;
+            vec![]
+        } else if self.peek(&[TokenKind::Enum]) {
+            self.enum_def()?;
+            vec![]
+        } else if self.peek(&[TokenKind::Const]) {
+            self.const_def()?;
+            vec![]
+        } else if self.peek_contextual("type") {
+            self.type_alias()?;
+            vec![]
+        } else if self.peek(&[TokenKind::Static]) {
+            vec![Definition::Static(self.static_def()?)]
+        } else if self.peek(&[TokenKind::Trait]) {
+            self.trait_def()?;
+            vec![]
+        } else if self.peek(&[TokenKind::Fn, TokenKind::Ident, TokenKind::Lt]) {
+            self.generic_def()?;
+            vec![]
+        } else if self.peek(&[TokenKind::Fn]) {
+            let mut f = self.fn_def()?;
+            f.comments = comments;
+            f.attrs = attrs;
+            vec![Definition::Fn(f)]
+        } else if self.peek(&[TokenKind::Extern]) {
+            vec![Definition::Extern(self.extern_def()?)]
+        } else if self.peek(&[TokenKind::Impl]) {
+            self.impl_def()?
+        } else {
+            return Err(self.unexpected(["item"]));
+        };
+        Ok(res)
+    }
+
+    /// comments before the token at `head`, consuming doc comments
+    fn comments(&mut self) -> Vec<Comment> {
+        let mut res = vec![];
+        loop {
+            // looked up again when a generic is instantiated
+            if let Some(comments) = self.trivia.get(&self.get().pos.0) {
+                res.extend(comments.iter().cloned());
+            }
+            if !self.peek(&[TokenKind::DocComment]) {
+                break res;
+            }
+            res.push(Comment {
+                text: self.get().value.clone().unwrap(),
+                doc: true,
+            });
+            self.head += 1;
+        }
+    }
+
+    /// `#[name(arg, ..)]`s before an item, with the comments between them added to `comments`
+    fn attributes(&mut self, comments: &mut Vec<Comment>) -> PResult<Vec<Attribute>> {
+        let mut res = vec![];
+        while self.consume(&[TokenKind::Pound]) {
+            self.expect(&[TokenKind::LBlanket])?;
+            let name = self.expect_ident()?;
+            let mut args = vec![];
+            if self.consume(&[TokenKind::LParen]) {
+                while !self.consume(&[TokenKind::RParen]) {
+                    if !(self.peek(&[TokenKind::Ident])
+                        || self.peek(&[TokenKind::Number])
+                        || self.peek(&[TokenKind::Str]))
+                    {
+                        return Err(self.unexpected(["identifier", "number", "string literal"]));
+                    }
+                    args.push(self.get().value.clone().unwrap());
+                    self.head += 1;
+                    self.consume(&[TokenKind::Comma]);
+                }
+            }
+            self.expect(&[TokenKind::RBlanket])?;
+            res.push(Attribute { name, args });
+            comments.extend(self.comments());
+        }
+        Ok(res)
+    }
+
+    /// `mod foo;`, parsing `foo.sofa` (or `foo/bar.sofa` for `bar` in `foo`)
+    /// into definitions named `foo::..`
+    fn mod_decl(&mut self) -> PResult<Vec<Definition>> {
+        let start = self.head;
+        self.expect(&[TokenKind::Mod])?;
+        let name = self.expect_ident()?;
+        self.expect(&[TokenKind::Semi])?;
+
+        let prefix = format!("{}{}::", self.prefix, name);
+        let file = format!("{}.sofa", prefix.trim_end_matches("::").replace("::", "/"));
+        let Some(loader) = self.loader.as_mut() else {
+            return Err(self.invalid(
+                start,
+                format!("cannot load module `{}` without a root file", name),
+            ));
+        };
+        let tokens = loader(&file);
+
+        // items are shared between modules, `use`s are not
+        let mut parser = SofaParser::new(tokens);
+        parser.prefix = prefix;
+        parser.signatures = std::mem::take(&mut self.signatures);
+        parser.items = std::mem::take(&mut self.items);
+        parser.defaults = std::mem::take(&mut self.defaults);
+        parser.traits = std::mem::take(&mut self.traits);
+        parser.impls = std::mem::take(&mut self.impls);
+        parser.generics = std::mem::take(&mut self.generics);
+        parser.instances = std::mem::take(&mut self.instances);
+        parser.closure_count = self.closure_count;
+        parser.types = std::mem::take(&mut self.types);
+        parser.consts = std::mem::take(&mut self.consts);
+        parser.loader = self.loader.as_mut().map(|x| &mut **x as &mut Loader);
+        parser.unit_assign = self.unit_assign;
+        parser.arena = std::mem::take(&mut self.arena);
+        parser.trivia = std::mem::take(&mut self.trivia);
+        parser.comments = std::mem::take(&mut self.comments);
+        parser.spans = std::mem::take(&mut self.spans);
+        let global = parser.global();
+
+        self.signatures = parser.signatures;
+        self.items = parser.items;
+        self.defaults = parser.defaults;
+        self.traits = parser.traits;
+        self.impls = parser.impls;
+        self.generics = parser.generics;
+        self.instances = parser.instances;
+        self.closure_count = parser.closure_count;
+        self.types = parser.types;
+        self.consts = parser.consts;
+        self.arena = parser.arena;
+        self.trivia = parser.trivia;
+        self.comments = parser.comments;
+        self.spans = parser.spans;
+        self.errors.extend(parser.errors);
+        Ok(global.definitions)
+    }
+
+    /// `use foo::bar;`, so that `bar` refers to `foo::bar`
+    fn use_decl(&mut self) -> PResult<()> {
+        self.expect(&[TokenKind::Use])?;
+        let path = self.path()?;
+        self.expect(&[TokenKind::Semi])?;
+
+        let full = self.resolve(&path);
+        let name = path.rsplit("::").next().unwrap().to_string();
+        self.uses.insert(name, full);
+        Ok(())
+    }
+
+    /// `a::b::c`
+    fn path(&mut self) -> PResult<String> {
+        let mut res = self.expect_ident()?;
+        while self.peek(&[TokenKind::ColonColon, TokenKind::Ident]) {
+            self.head += 1;
+            res += "::";
+            res += &self.expect_ident()?;
+        }
+        Ok(res)
+    }
+
+    /// full name of whatever `path` refers to from the current module,
+    /// looking in the module itself before the root
+    fn resolve(&self, path: &str) -> String {
+        let path = match path.split_once("::") {
+            Some((first, rest)) => match self.uses.get(first) {
+                Some(full) => format!("{}::{}", full, rest),
+                None => path.to_string(),
+            },
+            None => self.uses.get(path).cloned().unwrap_or(path.to_string()),
+        };
+
+        let inner = format!("{}{}", self.prefix, path);
+        let is_item = |x: &String| {
+            self.signatures.contains_key(x)
+                || self.types.contains_key(x)
+                || self.consts.contains_key(x)
+        };
+        if is_item(&inner) {
+            inner
+        } else {
+            path
+        }
+    }
+
+    /// `const NAME: T = value;`, evaluated here and nothing is generated for it
+    fn const_def(&mut self) -> PResult<()> {
+        self.expect(&[TokenKind::Const])?;
+        let name = self.expect_ident()?;
+        self.expect(&[TokenKind::Colon])?;
+        let ty = self.ty()?;
+        self.expect(&[TokenKind::Eq])?;
+        let start = self.head;
+        let value = self.expr()?;
+        let found = self.expr_ty(&value, start)?;
+        if !found.is_literal_of(&ty) {
+            return Err(self.invalid(
+                start,
+                format!(
+                    "mismatched types in const `{}`: expected {}, found {}",
+                    name, ty, found
+                ),
+            ));
+        }
+        let value =
+            const_eval::constant(&self.arena, &value, &ty).map_err(|e| self.invalid(start, e))?;
+        self.expect(&[TokenKind::Semi])?;
+        self.consts
+            .insert(format!("{}{}", self.prefix, name), value);
+        Ok(())
+    }
+
+    /// `static NAME: T = init;`, always writable so `mut` is optional
+    fn static_def(&mut self) -> PResult<Static> {
+        self.expect(&[TokenKind::Static])?;
+        self.consume(&[TokenKind::Mut]);
+        let name = self.expect_ident()?;
+        self.expect(&[TokenKind::Colon])?;
+        let ty = self.ty()?;
+        self.expect(&[TokenKind::Eq])?;
+        let init = self.expr()?;
+        self.expect(&[TokenKind::Semi])?;
+
+        let init = self.alloc(init);
+        let name = format!("{}{}", self.prefix, name);
+        self.signatures.insert(name.clone(), ty.clone());
+        self.items.insert(name.clone());
+        Ok(Static { name, ty, init })
+    }
+
+    /// `type Name = T;`, another name for `T` that is replaced by it wherever it is written
+    fn type_alias(&mut self) -> PResult<()> {
+        self.expect_contextual("type")?;
+        let name = self.expect_ident()?;
+        self.expect(&[TokenKind::Eq])?;
+        let ty = self.ty()?;
+        self.expect(&[TokenKind::Semi])?;
+
+        self.types.insert(format!("{}{}", self.prefix, name), ty);
+        Ok(())
+    }
+
+    /// only registers the type, nothing is generated for it
+    fn struct_def(&mut self) -> PResult<()> {
+        self.expect(&[TokenKind::Struct])?;
+        let name = self.expect_ident()?;
+        self.expect(&[TokenKind::LBrace])?;
+
+        let mut fields: Vec<(String, Type)> = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let start = self.head;
+            let field = self.expect_ident()?;
+            if fields.iter().any(|(x, _)| *x == field) {
+                return Err(self.invalid(
+                    start,
+                    format!("field `{}` is already declared on struct {}", field, name),
+                ));
+            }
+            self.expect(&[TokenKind::Colon])?;
+            fields.push((field, self.ty()?));
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        let name = format!("{}{}", self.prefix, name);
+        self.types
+            .insert(name.clone(), Type::Struct { name, fields });
+        Ok(())
+    }
+
+    /// only registers the type, nothing is generated for it
+    fn enum_def(&mut self) -> PResult<()> {
+        self.expect(&[TokenKind::Enum])?;
+        let name = self.expect_ident()?;
+        self.expect(&[TokenKind::LBrace])?;
+
+        let mut variants: Vec<(String, Option<Type>)> = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let start = self.head;
+            let variant = self.expect_ident()?;
+            if variants.iter().any(|(x, _)| *x == variant) {
+                return Err(self.invalid(
+                    start,
+                    format!("variant `{}` is already declared on enum {}", variant, name),
+                ));
+            }
+            let payload = if self.consume(&[TokenKind::LParen]) {
+                let ty = self.ty()?;
+                self.expect(&[TokenKind::RParen])?;
+                Some(ty)
+            } else {
+                None
+            };
+            variants.push((variant, payload));
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        let name = format!("{}{}", self.prefix, name);
+        self.types
+            .insert(name.clone(), Type::Enum { name, variants });
+        Ok(())
+    }
+
+    /// `impl T { fn .. }` or `impl Trait for T { fn .. }`, with the methods named `T::name`
+    fn impl_def(&mut self) -> PResult<Vec<Definition>> {
+        let impl_start = self.head;
+        self.expect(&[TokenKind::Impl])?;
+        let start = self.head;
+        let trait_name = if self.peek(&[TokenKind::Ident]) {
+            let path = self.path()?;
+            if self.consume(&[TokenKind::For]) {
+                Some(self.resolve(&path))
             } else {
-                res.definitions.push(self.fn_def());
+                self.head = start;
+                None
+            }
+        } else {
+            None
+        };
+        let ty_start = self.head;
+        let ty = self.ty()?;
+        let Some(ty_name) = type_name(&ty).map(str::to_string) else {
+            return Err(self.invalid(ty_start, format!("cannot define methods on {}", ty)));
+        };
+        let header_end = self.head;
+        self.expect(&[TokenKind::LBrace])?;
+
+        let shadowed = self.self_ty.replace(ty.clone());
+        let res = self.methods();
+        self.self_ty = shadowed;
+        let res = res?;
+
+        if let Some(trait_name) = trait_name {
+            // reported at the `impl .. for T` header, after the methods are parsed
+            let error = |message: String| {
+                let pos = (
+                    self.tokens[impl_start].pos.0,
+                    self.tokens[header_end - 1].pos.1,
+                );
+                ParseError {
+                    pos,
+                    kind: ParseErrorKind::Invalid(message),
+                    help: None,
+                }
+            };
+            let Some(required) = self.traits.get(&trait_name) else {
+                return Err(error(format!("cannot find trait `{}`", trait_name)));
+            };
+            let bindings = HashMap::from([("Self".to_string(), ty)]);
+            for (method, sig) in required {
+                let name = format!("{}::{}", ty_name, method);
+                match self.signatures.get(&name) {
+                    Some(actual) if *actual == sig.subst(&bindings) => {}
+                    Some(actual) => {
+                        return Err(error(format!(
+                            "method `{}` has an incompatible signature for trait `{}`: expected {}, found {}",
+                            name,
+                            trait_name,
+                            sig.subst(&bindings),
+                            actual
+                        )))
+                    }
+                    None => {
+                        return Err(error(format!(
+                            "missing method `{}` of trait `{}`",
+                            name, trait_name
+                        )))
+                    }
+                }
+            }
+            for definition in res.iter() {
+                if let Definition::Fn(f) = definition {
+                    let method = f.name.rsplit("::").next().unwrap();
+                    if !required.iter().any(|(x, _)| x == method) {
+                        return Err(error(format!(
+                            "method `{}` is not a member of trait `{}`",
+                            f.name, trait_name
+                        )));
+                    }
+                }
+            }
+            self.impls.insert((ty_name, trait_name));
+        }
+        Ok(res)
+    }
+
+    /// `trait Name { fn method(&self, ..) -> T; .. }`, only registers what its impls must have
+    fn trait_def(&mut self) -> PResult<()> {
+        self.expect(&[TokenKind::Trait])?;
+        let name = self.expect_ident()?;
+        let name = format!("{}{}", self.prefix, name);
+        self.expect(&[TokenKind::LBrace])?;
+
+        let shadowed = self.self_ty.replace(Type::Param {
+            name: "Self".to_string(),
+        });
+        let mut required = vec![];
+        let res = loop {
+            if self.consume(&[TokenKind::RBrace]) {
+                break Ok(());
+            }
+            while self.consume(&[TokenKind::DocComment]) {}
+            let method = self.expect(&[TokenKind::Fn]).and_then(|_| {
+                let method = self.expect_ident()?;
+                let sig = self.signature()?;
+                self.expect(&[TokenKind::Semi])?;
+                Ok((method, sig))
+            });
+            match method {
+                Ok(method) => required.push(method),
+                Err(e) => break Err(e),
+            }
+        };
+        self.self_ty = shadowed;
+        res?;
+
+        self.traits.insert(name, required);
+        Ok(())
+    }
+
+    /// `fn name<T: Trait + Other, U>(..) { .. }`,
+    /// whose body is only parsed once the types are known at each call
+    fn generic_def(&mut self) -> PResult<()> {
+        let head = self.head;
+        self.expect(&[TokenKind::Fn])?;
+        let name = self.expect_ident()?;
+        let name = format!("{}{}", self.prefix, name);
+
+        self.expect(&[TokenKind::Lt])?;
+        let mut params = vec![];
+        while !self.consume(&[TokenKind::Gt]) {
+            let param = self.expect_ident()?;
+            let mut bounds = vec![];
+            if self.consume(&[TokenKind::Colon]) {
+                loop {
+                    let path = self.path()?;
+                    bounds.push(self.resolve(&path));
+                    if !self.consume(&[TokenKind::Plus]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(&[TokenKind::Comma]);
+            params.push((param, bounds));
+        }
+
+        // the parameters stand for any type in the signature
+        let shadowed: Vec<_> = params
+            .iter()
+            .map(|(x, _)| {
+                let param = Type::Param { name: x.clone() };
+                self.types.insert(x.clone(), param)
+            })
+            .collect();
+        let sig = self.signature();
+        for ((param, _), shadowed) in params.iter().zip(shadowed) {
+            match shadowed {
+                Some(ty) => self.types.insert(param.clone(), ty),
+                None => self.types.remove(param),
+            };
+        }
+        let sig = sig?;
+
+        // skip the body
+        self.expect(&[TokenKind::LBrace])?;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_eof() {
+                return Err(self.unexpected(["`}`"]));
+            }
+            match self.get().kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+            self.head += 1;
+        }
+
+        self.signatures.insert(name.clone(), sig);
+        self.items.insert(name.clone());
+        self.generics.insert(
+            name,
+            Generic {
+                params,
+                tokens: self.tokens.clone(),
+                head,
+                prefix: self.prefix.clone(),
+                uses: self.uses.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    /// `(a: T, b: U) -> V` of a fn declared without its body, as a fn type
+    fn signature(&mut self) -> PResult<Type> {
+        self.expect(&[TokenKind::LParen])?;
+        let mut args = vec![];
+        if let Some(ty) = self.self_param() {
+            self.consume(&[TokenKind::Comma]);
+            args.push(ty);
+        }
+        while !self.consume(&[TokenKind::RParen]) {
+            self.expect_ident()?;
+            self.expect(&[TokenKind::Colon])?;
+            args.push(self.ty()?);
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        let ret = if self.consume(&[TokenKind::Arrow]) {
+            self.ty()?
+        } else {
+            Type::Void
+        };
+        Ok(Type::Fn {
+            args,
+            ret: Box::new(ret),
+        })
+    }
+
+    /// call to a generic fn, generating it for the types of the arguments
+    fn generic_call(&mut self, name: String) -> PResult<FnCall> {
+        // the name was just consumed
+        let start = self.head - 1;
+        let args = self.args()?;
+
+        let mut bindings = HashMap::new();
+        if let Type::Fn { args: params, .. } = &self.signatures[&name] {
+            for (param, arg) in params.iter().zip(args.iter()) {
+                let arg = self.expr_ty(&self.arena[*arg], start)?;
+                param.unify(&arg, &mut bindings);
+            }
+        }
+
+        let generic = &self.generics[&name];
+        let mut tys = vec![];
+        for (param, bounds) in generic.params.iter() {
+            let Some(ty) = bindings.remove(param) else {
+                return Err(self.invalid(start, format!("cannot infer `{}` of `{}`", param, name)));
+            };
+            for bound in bounds {
+                let implemented = type_name(&ty)
+                    .is_some_and(|x| self.impls.contains(&(x.to_string(), bound.clone())));
+                if !implemented {
+                    return Err(
+                        self.invalid(start, format!("{} does not implement `{}`", ty, bound))
+                    );
+                }
             }
+            tys.push(ty);
         }
+
+        let (name, fn_type) = self.instantiate(name, tys)?;
+        Ok(FnCall {
+            name,
+            args,
+            fn_type,
+        })
     }
 
-    fn fn_def(&mut self) -> FnDef {
-        self.expect(&[TokenKind::Fn]);
-        let name = self.expect_ident();
+    /// name and type of the generic `name` generated for `tys`, generating it if not done yet
+    fn instantiate(&mut self, generic: String, tys: Vec<Type>) -> PResult<(String, Type)> {
+        if let Some(instance) = self
+            .instances
+            .iter()
+            .find(|x| x.generic == generic && x.tys == tys)
+        {
+            return Ok((instance.name.clone(), instance.fn_type.clone()));
+        }
+
+        let Generic {
+            params,
+            tokens,
+            head,
+            prefix,
+            uses,
+        } = &self.generics[&generic];
+        let bindings: HashMap<_, _> = params
+            .iter()
+            .map(|(x, _)| x.clone())
+            .zip(tys.iter().cloned())
+            .collect();
+        let name = format!("{}.{}", generic, self.instances.len());
+        let fn_type = self.signatures[&generic].subst(&bindings);
+        // registered first, so that it can call itself
+        self.instances.push(Instance {
+            generic: generic.clone(),
+            tys,
+            name: name.clone(),
+            fn_type: fn_type.clone(),
+        });
+
+        // parse it again from its `fn` as if it were defined with the types
+        let tokens = std::mem::replace(&mut self.tokens, tokens.clone());
+        let head = std::mem::replace(&mut self.head, *head);
+        let prefix = std::mem::replace(&mut self.prefix, prefix.clone());
+        let uses = std::mem::replace(&mut self.uses, uses.clone());
+        let signatures = self.signatures.clone();
+        let types = self.types.clone();
+        let self_ty = self.self_ty.take();
+        let closures = std::mem::take(&mut self.closures);
+        let breaks = std::mem::take(&mut self.breaks);
+        self.types.extend(bindings);
 
-        self.expect(&[TokenKind::LParen]);
+        let f = self.fn_def();
+
+        self.tokens = tokens;
+        self.head = head;
+        self.prefix = prefix;
+        self.uses = uses;
+        self.signatures = signatures;
+        self.types = types;
+        self.self_ty = self_ty;
+        self.closures = closures;
+        self.breaks = breaks;
+
+        let mut f = f?;
+        f.name = name.clone();
+        self.lifted.push(f);
+        Ok((name, fn_type))
+    }
+
+    fn methods(&mut self) -> PResult<Vec<Definition>> {
+        let mut res = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let mut comments = self.comments();
+            let attrs = self.attributes(&mut comments)?;
+            self.consume(&[TokenKind::Pub]);
+            let mut f = self.fn_def()?;
+            f.comments = comments;
+            f.attrs = attrs;
+            res.push(Definition::Fn(f));
+        }
+        Ok(res)
+    }
+
+    fn fn_def(&mut self) -> PResult<FnDef> {
+        self.expect(&[TokenKind::Fn])?;
+        let name = self.expect_ident()?;
+        let name = match &self.self_ty {
+            // checked to have methods by `impl_def`
+            Some(ty) => format!("{}::{}", type_name(ty).unwrap(), name),
+            None => format!("{}{}", self.prefix, name),
+        };
+        // type parameters of a generic being generated, already bound to the types
+        if self.consume(&[TokenKind::Lt]) {
+            while !self.consume(&[TokenKind::Gt]) {
+                self.head += 1;
+            }
+        }
+
+        self.expect(&[TokenKind::LParen])?;
         let mut args = vec![];
+        let mut defaults = vec![];
+        if let Some(ty) = self.self_param() {
+            self.consume(&[TokenKind::Comma]);
+            self.signatures.insert("self".to_string(), ty.clone());
+            args.push(Local {
+                name: "self".to_string(),
+                ty,
+            });
+        }
         while !self.consume(&[TokenKind::RParen]) {
-            let name = self.expect_ident();
-            self.expect(&[TokenKind::Colon]);
-            let ty = self.ty();
+            // parameters can always be assigned to, so `mut` is optional
+            self.consume(&[TokenKind::Mut]);
+            let param_start = self.head;
+            let name = self.expect_ident()?;
+            self.expect(&[TokenKind::Colon])?;
+            let ty = self.ty()?;
+            if self.consume(&[TokenKind::Eq]) {
+                // a constant, so that it means the same at every call
+                let start = self.head;
+                let value = self.expr()?;
+                let found = self.expr_ty(&value, start)?;
+                if !found.is_literal_of(&ty) {
+                    return Err(self.invalid(
+                        start,
+                        format!(
+                            "mismatched types in default of `{}`: expected {}, found {}",
+                            name, ty, found
+                        ),
+                    ));
+                }
+                let value = const_eval::constant(&self.arena, &value, &ty)
+                    .map_err(|e| self.invalid(start, e))?;
+                defaults.push(value);
+            } else if !defaults.is_empty() {
+                return Err(self.invalid(
+                    param_start,
+                    format!(
+                        "parameter `{}` after a defaulted one needs a default too",
+                        name
+                    ),
+                ));
+            }
             self.consume(&[TokenKind::Comma]);
 
             self.signatures.insert(name.clone(), ty.clone());
             args.push(Local { name, ty });
         }
 
-        let ret = if self.consume(&[TokenKind::Minus, TokenKind::Gt]) {
-            self.ty()
+        let ret = if self.consume(&[TokenKind::Arrow]) {
+            self.ty()?
         } else {
-            // default void
+            // default unit
             Type::Void
         };
 
@@ -154,224 +1189,1493 @@ impl<'ctx> SofaParser<'ctx> {
             ret: Box::new(ret),
         };
         self.signatures.insert(name.clone(), fn_type.clone());
+        self.items.insert(name.clone());
+        self.defaults.insert(name.clone(), defaults);
 
-        FnDef {
+        Ok(FnDef {
+            comments: vec![],
+            attrs: vec![],
             name,
             args,
             fn_type,
-            body: self.block(),
+            body: self.block()?,
+        })
+    }
+
+    /// `extern "C" fn name(a: T, ...) -> U;`, the ABI being optional.
+    /// the name is not prefixed by the module, as it is the symbol to link against
+    fn extern_def(&mut self) -> PResult<Extern> {
+        self.expect(&[TokenKind::Extern])?;
+        self.consume(&[TokenKind::Str]);
+        self.expect(&[TokenKind::Fn])?;
+        let name = self.expect_ident()?;
+
+        self.expect(&[TokenKind::LParen])?;
+        let mut args = vec![];
+        let mut variadic = false;
+        while !self.consume(&[TokenKind::RParen]) {
+            if self.consume(&[TokenKind::DotDot, TokenKind::Dot]) {
+                variadic = true;
+                self.expect(&[TokenKind::RParen])?;
+                break;
+            }
+            self.expect_ident()?;
+            self.expect(&[TokenKind::Colon])?;
+            args.push(self.ty()?);
+            self.consume(&[TokenKind::Comma]);
         }
+
+        let ret = if self.consume(&[TokenKind::Arrow]) {
+            self.ty()?
+        } else {
+            Type::Void
+        };
+        self.expect(&[TokenKind::Semi])?;
+
+        let fn_type = Type::Fn {
+            args,
+            ret: Box::new(ret),
+        };
+        self.signatures.insert(name.clone(), fn_type);
+        self.items.insert(name.clone());
+        Ok(Extern { name, variadic })
     }
 
-    fn block(&mut self) -> Block {
-        self.expect(&[TokenKind::LBrace]);
+    /// `self`, `&self` or `&mut self` first in the parameters of a method, and its type
+    fn self_param(&mut self) -> Option<Type> {
+        let ty = self.self_ty.clone()?;
+        let mut head = self.head;
+        let by_ref = self.tokens[head].kind == TokenKind::And;
+        if by_ref {
+            head += 1;
+            if self.tokens[head].kind == TokenKind::Mut {
+                head += 1;
+            }
+        }
+        if self.tokens[head].kind != TokenKind::Ident
+            || self.tokens[head].value.as_deref() != Some("self")
+        {
+            return None;
+        }
+
+        self.head = head + 1;
+        Some(if by_ref {
+            Type::Ptr { to: Box::new(ty) }
+        } else {
+            ty
+        })
+    }
+
+    fn block(&mut self) -> PResult<Block> {
+        self.expect(&[TokenKind::LBrace])?;
 
         let mut res = Block { exprs: vec![] };
         while !self.consume(&[TokenKind::RBrace]) {
-            let expr = self.expr();
-            res.exprs.push(if self.consume(&[TokenKind::Semi]) {
-                Expr::Stmt(Stmt {
-                    expr: Box::new(expr),
-                })
+            let comments = self.comments();
+            if self.peek(&[TokenKind::RBrace]) {
+                continue;
+            }
+            if self.skipped_to_item {
+                break;
+            }
+
+            let start = self.head;
+            let expr = match self.expr() {
+                Ok(expr) => expr,
+                Err(e) => {
+                    self.errors.push(e);
+                    // a broken `let` still declares its name, so that uses don't fail to resolve
+                    if let [Token {
+                        kind: TokenKind::Let,
+                        ..
+                    }, Token {
+                        kind: TokenKind::Ident,
+                        value: Some(name),
+                        ..
+                    }, ..] = &self.tokens[start..]
+                    {
+                        self.signatures.insert(name.clone(), Type::Unknown);
+                    }
+                    self.synchronize();
+                    continue;
+                }
+            };
+            // the names bound by a destructuring `let` stay in scope after it
+            if let (TokenKind::Let, Expr::Block(Block { exprs })) = (self.tokens[start].kind, &expr)
+            {
+                self.expect(&[TokenKind::Semi])?;
+                for (i, &expr) in exprs.iter().enumerate() {
+                    let id = self.alloc(Expr::Stmt(Stmt { expr }));
+                    if i == 0 && !comments.is_empty() {
+                        self.comments.insert(id, comments.clone());
+                    }
+                    res.exprs.push(id);
+                }
+                continue;
+            }
+            let expr = if self.consume(&[TokenKind::Semi]) {
+                let expr = self.alloc(expr);
+                Expr::Stmt(Stmt { expr })
             } else {
                 expr
-            });
+            };
+            let id = self.alloc(expr);
+            if !comments.is_empty() {
+                self.comments.insert(id, comments);
+            }
+            res.exprs.push(id);
         }
-        res
+        Ok(res)
+    }
+
+    fn expr(&mut self) -> PResult<Expr> {
+        self.expr_bp(0)
     }
 
-    fn expr(&mut self) -> Expr {
-        let a = self.expr1();
-        self.binop(a)
+    /// Pratt parser, leaving operators looser than `min_prec` to the caller
+    fn expr_bp(&mut self, min_prec: u8) -> PResult<Expr> {
+        let start = self.head;
+        let mut lhs = self.unary()?;
+        // precedence of the last non-associative operator, which can't be followed by its kind
+        let mut non_assoc = None;
+
+        while let Some(&(_, prec, assoc, op)) = INFIX.iter().find(|(kind, ..)| self.peek(&[*kind]))
+        {
+            if prec < min_prec {
+                break;
+            }
+            let lhs_span = (start, self.head);
+            if non_assoc == Some(prec) {
+                return Err(self
+                    .invalid(self.head, "comparison operators cannot be chained")
+                    .with_help("use `&&` to compare both sides"));
+            }
+            self.head += 1;
+
+            if let Infix::Cast = op {
+                lhs = Expr::Cast(Cast {
+                    expr: self.alloc_at(lhs, lhs_span),
+                    ty: self.ty()?,
+                });
+                continue;
+            }
+            // `c ? a : b` is `if c { a } else { b }`
+            if let Infix::Ternary = op {
+                let then = self.expr()?;
+                let then = self.alloc(then);
+                self.expect(&[TokenKind::Colon])?;
+                let otherwise = self.expr_bp(prec)?;
+                let otherwise = self.alloc(otherwise);
+                lhs = Expr::IfElse(IfElse {
+                    cond: self.alloc_at(lhs, lhs_span),
+                    if_body: Block { exprs: vec![then] },
+                    else_body: Some(Block {
+                        exprs: vec![otherwise],
+                    }),
+                });
+                continue;
+            }
+            let rhs = match assoc {
+                Assoc::Right => self.expr_bp(prec)?,
+                Assoc::Left | Assoc::Non => self.expr_bp(prec + 1)?,
+            };
+            non_assoc = (assoc == Assoc::Non).then_some(prec);
+
+            lhs = match op {
+                Infix::BinOp(op) => Expr::BinOp(BinOp {
+                    op,
+                    lhs: self.alloc_at(lhs, lhs_span),
+                    rhs: self.alloc(rhs),
+                }),
+                Infix::Assign => Expr::Assign(Assign {
+                    lhs: self.alloc_at(lhs, lhs_span),
+                    rhs: self.alloc(rhs),
+                }),
+                // `a op= b` is `a = a op b`, so the lhs is evaluated twice
+                Infix::AssignOp(op) => {
+                    let lhs = self.alloc_at(lhs, lhs_span);
+                    let binop = Expr::BinOp(BinOp {
+                        op,
+                        lhs: deep_copy(&mut self.arena, lhs),
+                        rhs: self.alloc(rhs),
+                    });
+                    Expr::Assign(Assign {
+                        lhs,
+                        rhs: self.alloc_at(binop, (start, self.head)),
+                    })
+                }
+                Infix::Cast | Infix::Ternary => unreachable!(),
+            };
+            if self.unit_assign && matches!(op, Infix::Assign | Infix::AssignOp(_)) {
+                lhs = Expr::Stmt(Stmt {
+                    expr: self.alloc_at(lhs, (start, self.head)),
+                });
+            }
+        }
+        self.last = (start, self.head);
+        Ok(lhs)
     }
 
-    fn expr1(&mut self) -> Expr {
+    fn expr1(&mut self) -> PResult<Expr> {
+        let start = self.head;
         let res = if self.peek(&[TokenKind::LBrace]) {
-            Expr::Block(self.block())
+            Expr::Block(self.block()?)
         } else if self.consume(&[TokenKind::Return]) {
+            // bare `return` returns unit, as an empty block
+            let expr = if self.peek(&[TokenKind::Semi]) || self.peek(&[TokenKind::RBrace]) {
+                Expr::Block(Block { exprs: vec![] })
+            } else {
+                self.expr()?
+            };
             Expr::Return(Return {
-                expr: Box::new(self.expr()),
+                expr: self.alloc(expr),
+            })
+        } else if self.peek(&[TokenKind::Label, TokenKind::Colon]) {
+            let label = self.get().value.clone();
+            self.head += 2;
+            self.loop_expr(label)?
+        } else if self.peek(&[TokenKind::Loop])
+            || self.peek(&[TokenKind::While])
+            || self.peek(&[TokenKind::For])
+        {
+            self.loop_expr(None)?
+        } else if self.consume(&[TokenKind::Defer]) {
+            let expr = self.expr()?;
+            Expr::Defer(Defer {
+                expr: self.alloc(expr),
             })
-        } else if self.consume(&[TokenKind::Loop]) {
-            Expr::Loop(Loop { body: self.block() })
+        } else if self.consume(&[TokenKind::Break]) {
+            Expr::Break(self.break_expr()?)
+        } else if self.consume(&[TokenKind::Continue]) {
+            let label = self.label();
+            self.target_loop(&label, start)?;
+            Expr::Continue(Continue { label })
+        } else if self.peek(&[TokenKind::Match]) {
+            Expr::Match(self.match_expr()?)
+        } else if self.peek(&[TokenKind::If, TokenKind::Let]) {
+            Expr::Match(self.if_let()?)
         } else if self.peek(&[TokenKind::If]) {
-            Expr::IfElse(self.ifelse())
-        } else if self.peek(&[TokenKind::Ident, TokenKind::LParen]) {
-            Expr::FnCall(self.fn_call())
-        } else if self.peek(&[TokenKind::Let]) {
-            Expr::Init(self.init())
-        } else if self.peek(&[TokenKind::And])
-            || self.peek(&[TokenKind::Star])
-            || self.peek(&[TokenKind::Minus])
+            Expr::IfElse(self.ifelse()?)
+        } else if self.peek(&[TokenKind::Let, TokenKind::LParen])
+            || self.peek(&[TokenKind::Let, TokenKind::Ident, TokenKind::LBrace])
         {
-            self.unary()
+            Expr::Block(self.destructuring_init()?)
+        } else if self.peek(&[TokenKind::Let]) {
+            Expr::Init(self.init()?)
+        } else if self.peek(&[TokenKind::Or]) || self.peek(&[TokenKind::OrOr]) {
+            Expr::Closure(self.closure()?)
         } else if self.consume(&[TokenKind::LParen]) {
-            let res = Expr::Enclosed(Enclosed {
-                expr: Box::new(self.expr()),
-            });
-            self.expect(&[TokenKind::RParen]);
-            res
+            let expr = self.expr()?;
+            if self.consume(&[TokenKind::Comma]) {
+                let mut elements = vec![self.alloc(expr)];
+                while !self.consume(&[TokenKind::RParen]) {
+                    let element = self.expr()?;
+                    elements.push(self.alloc(element));
+                    self.consume(&[TokenKind::Comma]);
+                }
+                Expr::Tuple(Tuple { elements })
+            } else {
+                self.expect(&[TokenKind::RParen])?;
+                Expr::Enclosed(Enclosed {
+                    expr: self.alloc(expr),
+                })
+            }
+        } else if self.consume(&[TokenKind::LBlanket]) {
+            let mut elements = vec![];
+            while !self.consume(&[TokenKind::RBlanket]) {
+                let element = self.expr()?;
+                elements.push(self.alloc(element));
+                self.consume(&[TokenKind::Comma]);
+            }
+            Expr::Array(Array { elements })
         } else if self.consume(&[TokenKind::True]) {
             Expr::Bool(Bool::True)
         } else if self.consume(&[TokenKind::False]) {
             Expr::Bool(Bool::False)
         } else if self.peek(&[TokenKind::Ident]) {
-            Expr::Local(self.local())
+            self.path_expr()?
         } else if self.peek(&[TokenKind::Number]) {
-            Expr::Number(self.number())
+            Expr::Number(self.number()?)
+        } else if self.peek(&[TokenKind::Float]) {
+            let value = self.get().value.clone().unwrap();
+            self.head += 1;
+            Expr::Float(Float { value })
+        } else if self.peek(&[TokenKind::Str]) {
+            let value = self.get().value.clone().unwrap();
+            self.head += 1;
+            Expr::Str(Str { value })
+        } else if self.peek(&[TokenKind::Char]) {
+            Expr::Char(Char {
+                value: self.char()?,
+            })
         } else {
-            panic!("found {:?}", self.get())
+            return Err(self.unexpected(["expression"]));
         };
 
         // postfix unary
-        if self.consume(&[TokenKind::LBlanket]) {
-            self.index(res)
-        } else {
-            res
+        let mut res = res;
+        loop {
+            // spanned by the base allocated next
+            self.last = (start, self.head);
+            if self.consume(&[TokenKind::LBlanket]) {
+                res = self.index(res)?;
+            } else if self.consume(&[TokenKind::Dot]) {
+                let name = if self.peek(&[TokenKind::Number]) {
+                    self.expect_number()?
+                } else {
+                    self.expect_ident()?
+                };
+                let ty = self.expr_ty(&res, start)?;
+                res = if name == "len" && self.peek(&[TokenKind::LParen]) && ty == Type::Str {
+                    // built in, so every string has it without an `impl str`
+                    self.expect(&[TokenKind::LParen])?;
+                    self.expect(&[TokenKind::RParen])?;
+                    Expr::Field(Field {
+                        base: self.alloc(res),
+                        name,
+                    })
+                } else if self.peek(&[TokenKind::LParen]) {
+                    Expr::FnCall(self.method_call(res, ty, &name, start)?)
+                } else {
+                    // fields are reached through pointers too
+                    let target = match &ty {
+                        Type::Ptr { to } => to,
+                        ty => ty,
+                    };
+                    // left to `typeck` when the type is only known after inference
+                    if !matches!(target, Type::Unknown | Type::Param { .. }) {
+                        target.field(&name).map_err(|e| self.invalid(start, e))?;
+                    }
+                    let base = match ty {
+                        Type::Ptr { .. } => Expr::UnOp(UnOp {
+                            kind: UnOpKind::Deref,
+                            expr: self.alloc(res),
+                        }),
+                        _ => res,
+                    };
+                    Expr::Field(Field {
+                        base: self.alloc(base),
+                        name,
+                    })
+                };
+            } else {
+                break Ok(res);
+            }
         }
     }
 
-    /// infix binop
-    fn binop(&mut self, lhs: Expr) -> Expr {
-        if let Some(op) = self.consume_binop() {
-            Expr::BinOp(BinOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(self.expr()),
+    /// prefix unary, tighter than any infix operator
+    fn unary(&mut self) -> PResult<Expr> {
+        let start = self.head;
+        let res = if self.consume(&[TokenKind::AndAnd]) {
+            // `&&a` is `&(&a)`
+            let expr = self.unary()?;
+            let expr = self.alloc(expr);
+            let inner = self.alloc_at(
+                Expr::UnOp(UnOp {
+                    kind: UnOpKind::Ref,
+                    expr,
+                }),
+                (start, self.head),
+            );
+            Expr::UnOp(UnOp {
+                kind: UnOpKind::Ref,
+                expr: inner,
             })
-        } else if self.consume(&[TokenKind::Eq]) {
-            Expr::Assign(Assign {
-                lhs: Box::new(lhs),
-                rhs: Box::new(self.expr()),
+        } else if self.peek(&[TokenKind::Minus, TokenKind::Number]) {
+            // fold into a negative literal, so that i64::MIN is representable
+            self.head += 1;
+            let value = format!("-{}", self.expect_number()?);
+            if value.parse::<i64>().is_err() {
+                return Err(self
+                    .invalid(start, "literal out of range for i64")
+                    .with_help(format!(
+                        "the literal does not fit into the type `i64` whose range is `{}..={}`",
+                        i64::MIN,
+                        i64::MAX
+                    )));
+            }
+            Expr::Number(Number { value })
+        } else if let Some(&(_, kind)) = PREFIX.iter().find(|(kind, _)| self.peek(&[*kind])) {
+            self.head += 1;
+            let expr = self.unary()?;
+            Expr::UnOp(UnOp {
+                kind,
+                expr: self.alloc(expr),
             })
         } else {
-            lhs
+            self.expr1()?
+        };
+        self.last = (start, self.head);
+        Ok(res)
+    }
+
+    fn index(&mut self, base: Expr) -> PResult<Expr> {
+        let base_span = self.last;
+        let index = self.expr()?;
+        let res = Expr::Index(Index {
+            base: self.alloc_at(base, base_span),
+            index: self.alloc(index),
+        });
+
+        self.expect(&[TokenKind::RBlanket])?;
+        Ok(res)
+    }
+
+    /// a call, struct literal, variant, const or local, all starting with a path
+    fn path_expr(&mut self) -> PResult<Expr> {
+        let start = self.head;
+        let path = self.path()?;
+
+        if path == "size_of" && self.consume(&[TokenKind::ColonColon, TokenKind::Lt]) {
+            // `size_of::<T>()`, known at compile time
+            let ty = self.ty()?;
+            self.expect(&[TokenKind::Gt])?;
+            self.expect(&[TokenKind::LParen])?;
+            self.expect(&[TokenKind::RParen])?;
+            return Ok(Expr::Number(Number {
+                value: ty.size().to_string(),
+            }));
+        }
+
+        // `Self::f` or `Self { .. }` within an impl
+        let path = match &self.self_ty {
+            Some(ty) if path == "Self" || path.starts_with("Self::") => {
+                path.replacen("Self", type_name(ty).unwrap(), 1)
+            }
+            _ => path,
+        };
+
+        if let Some((ty_path, variant)) = path.rsplit_once("::") {
+            let ty = self.types.get(&self.resolve(ty_path));
+            if let Some(ty @ Type::Enum { variants, .. }) = ty {
+                // otherwise an associated fn
+                if variants.iter().any(|(x, _)| x == variant) {
+                    return Ok(Expr::Variant(self.variant(ty.clone(), variant)?));
+                }
+            }
         }
+
+        let name = self.resolve(&path);
+        let res = if self.peek(&[TokenKind::LParen])
+            && self.signatures.contains_key(&name)
+            && !self.items.contains(&name)
+        {
+            // a local holding a closure
+            let ty = self.signatures[&name].clone();
+            let callee = self.local(name, ty);
+            Expr::Call(Call {
+                callee: self.alloc_at(callee, (start, self.head)),
+                args: self.args()?,
+            })
+        } else if self.peek(&[TokenKind::LParen]) && self.generics.contains_key(&name) {
+            Expr::FnCall(self.generic_call(name)?)
+        } else if self.peek(&[TokenKind::LParen]) {
+            Expr::FnCall(self.fn_call(name)?)
+        } else if self.peek(&[TokenKind::LBrace]) && self.types.contains_key(&name) {
+            Expr::Struct(self.struct_lit(name)?)
+        } else if !self.signatures.contains_key(&name) && self.consts.contains_key(&name) {
+            self.consts[&name].clone()
+        } else {
+            // left to `resolve` to report if never declared
+            let ty = self.signatures.get(&name).unwrap_or(&Type::Unknown).clone();
+            self.local(name, ty)
+        };
+        Ok(res)
     }
 
-    /// prefix unary
-    fn unary(&mut self) -> Expr {
-        if self.consume(&[TokenKind::Star]) {
-            Expr::UnOp(UnOp {
-                kind: UnOpKind::Deref,
-                expr: Box::new(self.unary()),
+    fn struct_lit(&mut self, name: String) -> PResult<Struct> {
+        // the name was just consumed
+        let start = self.head - 1;
+        let ty = self.types[&name].clone();
+        let Type::Struct {
+            fields: declared, ..
+        } = &ty
+        else {
+            return Err(self.invalid(start, format!("{} is not a struct", ty)));
+        };
+        self.expect(&[TokenKind::LBrace])?;
+
+        let mut fields: Vec<(String, NodeId)> = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let field_start = self.head;
+            let field = self.expect_ident()?;
+            if fields.iter().any(|(x, _)| *x == field) {
+                return Err(self.invalid(
+                    field_start,
+                    format!("field `{}` specified more than once", field),
+                ));
+            }
+            if declared.iter().all(|(x, _)| *x != field) {
+                return Err(self.invalid(
+                    field_start,
+                    format!("struct {} has no field named `{}`", name, field),
+                ));
+            }
+            self.expect(&[TokenKind::Colon])?;
+            let value = self.expr()?;
+            fields.push((field, self.alloc(value)));
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        let missing: Vec<_> = declared
+            .iter()
+            .filter(|(field, _)| fields.iter().all(|(x, _)| x != field))
+            .map(|(field, _)| format!("`{}`", field))
+            .collect();
+        if !missing.is_empty() {
+            return Err(self.invalid(
+                start,
+                format!(
+                    "missing {} in initializer of {}",
+                    plural("field", &missing),
+                    name
+                ),
+            ));
+        }
+
+        Ok(Struct { ty, fields })
+    }
+
+    /// the variant `name`, which the caller made sure `ty` has
+    fn variant(&mut self, ty: Type, name: &str) -> PResult<Variant> {
+        let (index, payload) = ty.variant(name).unwrap();
+        let payload = match payload {
+            Some(_) => {
+                self.expect(&[TokenKind::LParen])?;
+                let expr = self.expr()?;
+                self.expect(&[TokenKind::RParen])?;
+                Some(self.alloc(expr))
+            }
+            None => None,
+        };
+
+        Ok(Variant { ty, index, payload })
+    }
+
+    fn match_expr(&mut self) -> PResult<Match> {
+        let match_start = self.head;
+        self.expect(&[TokenKind::Match])?;
+        let start = self.head;
+        let scrutinee = self.expr()?;
+        let scrutinee_span = self.last;
+        let ty = self.expr_ty(&scrutinee, start)?;
+        self.expect(&[TokenKind::LBrace])?;
+
+        let mut arms = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let pat = self.pattern(&ty)?;
+            self.expect(&[TokenKind::FatArrow])?;
+            let body = match pat.binding() {
+                Some(local) => self.scoped(local, Self::expr)?,
+                None => self.expr()?,
+            };
+            self.consume(&[TokenKind::Comma]);
+            arms.push(Arm {
+                pat,
+                body: self.alloc(body),
+            });
+        }
+
+        let irrefutable = |x: &Arm| matches!(x.pat, Pattern::Wildcard | Pattern::Binding(_));
+        let missing: Vec<String> = if arms.iter().any(irrefutable) {
+            vec![]
+        } else {
+            match &ty {
+                Type::Bool => [false, true]
+                    .into_iter()
+                    .filter(|b| {
+                        !arms
+                            .iter()
+                            .any(|x| matches!(x.pat, Pattern::Bool(value) if value == *b))
+                    })
+                    .map(|b| format!("`{}`", b))
+                    .collect(),
+                Type::Enum { name, variants } => variants
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| {
+                        !arms
+                            .iter()
+                            .any(|x| matches!(x.pat, Pattern::Variant { index, .. } if index == i))
+                    })
+                    .map(|(_, (variant, payload))| match payload {
+                        Some(_) => format!("`{}::{}(_)`", name, variant),
+                        None => format!("`{}::{}`", name, variant),
+                    })
+                    .collect(),
+                _ => vec!["`_`".to_string()],
+            }
+        };
+        if !missing.is_empty() {
+            return Err(self
+                .invalid(
+                    match_start,
+                    format!(
+                        "non-exhaustive patterns in `match` on {}: {} not covered",
+                        ty,
+                        missing.join(", ")
+                    ),
+                )
+                .with_help("add an arm for each of them, or a `_` arm"));
+        }
+
+        Ok(Match {
+            scrutinee: self.alloc_at(scrutinee, scrutinee_span),
+            arms,
+        })
+    }
+
+    /// pattern matching a value of `ty`
+    fn pattern(&mut self, ty: &Type) -> PResult<Pattern> {
+        let start = self.head;
+        let mismatched = |this: &Self, found: &str| {
+            this.invalid(
+                start,
+                format!("expected a pattern of {}, found {}", ty, found),
+            )
+        };
+        let res = if self.peek(&[TokenKind::Ident, TokenKind::ColonColon]) {
+            let path = self.path()?;
+            let (ty_path, variant) = path.rsplit_once("::").unwrap();
+            if self.types.get(&self.resolve(ty_path)) != Some(ty) {
+                return Err(mismatched(self, &format!("`{}`", path)));
+            }
+
+            let Some((index, payload)) = ty.variant(variant) else {
+                return Err(self.invalid(start, format!("no variant `{}` on enum {}", variant, ty)));
+            };
+            let binding = match payload {
+                Some(payload_ty) => {
+                    self.expect(&[TokenKind::LParen])?;
+                    let name = self.expect_ident()?;
+                    self.expect(&[TokenKind::RParen])?;
+                    Some(Local {
+                        name,
+                        ty: payload_ty,
+                    })
+                }
+                None => None,
+            };
+            Pattern::Variant { index, binding }
+        } else if self.peek(&[TokenKind::Ident]) && self.get().value.as_deref() == Some("_") {
+            self.head += 1;
+            Pattern::Wildcard
+        } else if self.peek(&[TokenKind::Ident]) {
+            Pattern::Binding(Local {
+                name: self.expect_ident()?,
+                ty: ty.clone(),
             })
-        } else if self.consume(&[TokenKind::And]) {
-            Expr::UnOp(UnOp {
-                kind: UnOpKind::Ref,
-                expr: Box::new(self.unary()),
+        } else if self.peek(&[TokenKind::Number]) || self.peek(&[TokenKind::Minus]) {
+            let value = if self.consume(&[TokenKind::Minus]) {
+                format!("-{}", self.expect_number()?)
+            } else {
+                self.expect_number()?
+            };
+            if !ty.is_integer() {
+                return Err(mismatched(self, "an integer"));
+            }
+            let value = value.parse::<i128>().unwrap();
+            if !ty.fits(value) {
+                return Err(self.invalid(start, format!("literal out of range for {}", ty)));
+            }
+            // a u64 as its bits, which is what the scrutinee is compared with
+            Pattern::Number(value as i64)
+        } else if self.peek(&[TokenKind::Char]) {
+            if *ty != Type::Char {
+                return Err(mismatched(self, "a char"));
+            }
+            Pattern::Number(self.char()? as i64)
+        } else if self.peek(&[TokenKind::True]) || self.peek(&[TokenKind::False]) {
+            if *ty != Type::Bool {
+                return Err(mismatched(self, "a bool"));
+            }
+            let value = self.peek(&[TokenKind::True]);
+            self.head += 1;
+            Pattern::Bool(value)
+        } else {
+            return Err(self.unexpected(["pattern"]));
+        };
+        Ok(res)
+    }
+
+    /// parse with `local` in scope, restoring whatever it shadowed afterwards
+    fn scoped<T>(&mut self, local: &Local, f: impl FnOnce(&mut Self) -> T) -> T {
+        let shadowed = self.signatures.insert(local.name.clone(), local.ty.clone());
+        self.declare(&local.name);
+        let res = f(self);
+        match shadowed {
+            Some(ty) => self.signatures.insert(local.name.clone(), ty),
+            None => self.signatures.remove(&local.name),
+        };
+        res
+    }
+
+    fn ifelse(&mut self) -> PResult<IfElse> {
+        self.expect(&[TokenKind::If])?;
+        let cond = self.expr()?;
+        Ok(IfElse {
+            cond: self.alloc(cond),
+            if_body: self.block()?,
+            else_body: self.else_body()?,
+        })
+    }
+
+    /// `if let pat = scrutinee { .. } else { .. }`, which is
+    /// `match scrutinee { pat => { .. }, _ => { .. } }`
+    fn if_let(&mut self) -> PResult<Match> {
+        self.expect(&[TokenKind::If, TokenKind::Let])?;
+
+        // the pattern is parsed once the type of the scrutinee is known
+        let pat_head = self.head;
+        while !self.peek(&[TokenKind::Eq]) {
+            if self.is_eof() || self.peek(&[TokenKind::LBrace]) {
+                return Err(self.unexpected(["`=`"]));
+            }
+            self.head += 1;
+        }
+        self.head += 1;
+        let start = self.head;
+        let scrutinee = self.expr()?;
+        let scrutinee_span = self.last;
+        let ty = self.expr_ty(&scrutinee, start)?;
+        let scrutinee_end = self.head;
+        self.head = pat_head;
+        let pat = self.pattern(&ty)?;
+        self.expect(&[TokenKind::Eq])?;
+        self.head = scrutinee_end;
+
+        let if_body = match pat.binding() {
+            Some(local) => self.scoped(local, Self::block)?,
+            None => self.block()?,
+        };
+        let else_body = self.else_body()?.unwrap_or(Block { exprs: vec![] });
+
+        Ok(Match {
+            scrutinee: self.alloc_at(scrutinee, scrutinee_span),
+            arms: vec![
+                Arm {
+                    pat,
+                    body: self.alloc(Expr::Block(if_body)),
+                },
+                Arm {
+                    pat: Pattern::Wildcard,
+                    body: self.alloc(Expr::Block(else_body)),
+                },
+            ],
+        })
+    }
+
+    /// `else { .. }` of an `if`, if any
+    fn else_body(&mut self) -> PResult<Option<Block>> {
+        let res = if !self.consume(&[TokenKind::Else]) {
+            None
+        } else if self.peek(&[TokenKind::If, TokenKind::Let]) {
+            let expr = Expr::Match(self.if_let()?);
+            Some(Block {
+                exprs: vec![self.alloc(expr)],
             })
-        } else if self.consume(&[TokenKind::Minus]) {
-            Expr::UnOp(UnOp {
-                kind: UnOpKind::Neg,
-                expr: Box::new(self.expr()),
+        } else if self.peek(&[TokenKind::If]) {
+            // `else if` is `else { if .. }`
+            let expr = Expr::IfElse(self.ifelse()?);
+            Some(Block {
+                exprs: vec![self.alloc(expr)],
             })
         } else {
-            self.expr1()
+            Some(self.block()?)
+        };
+        Ok(res)
+    }
+
+    /// block of a loop, along with the type of values broken out of it if any
+    /// `loop`, `while` or `for`, labeled `label` if any
+    fn loop_expr(&mut self, label: Option<String>) -> PResult<Expr> {
+        let res = if self.consume(&[TokenKind::Loop]) {
+            let (body, ty) = self.loop_body(&label)?;
+            Expr::Loop(Loop {
+                label,
+                body,
+                // never ends unless broken out of
+                ty: ty.unwrap_or(Type::Never),
+            })
+        } else if self.consume(&[TokenKind::While]) {
+            let cond = self.expr()?;
+            Expr::While(While {
+                cond: self.alloc(cond),
+                body: self.loop_body(&label)?.0,
+                label,
+            })
+        } else if self.peek(&[TokenKind::For]) {
+            Expr::For(self.for_loop(label)?)
+        } else {
+            return Err(self.unexpected(["`loop`", "`while`", "`for`"]));
+        };
+        Ok(res)
+    }
+
+    fn loop_body(&mut self, label: &Option<String>) -> PResult<(Block, Option<Type>)> {
+        self.breaks.push((label.clone(), None));
+        let body = self.block();
+        let (_, ty) = self.breaks.pop().unwrap();
+        Ok((body?, ty))
+    }
+
+    /// `'name` after `break` or `continue`, if any
+    fn label(&mut self) -> Option<String> {
+        if !self.peek(&[TokenKind::Label]) {
+            return None;
         }
+        let label = self.get().value.clone();
+        self.head += 1;
+        label
     }
 
-    fn index(&mut self, lhs: Expr) -> Expr {
-        let res = Expr::UnOp(UnOp {
-            kind: UnOpKind::Deref,
-            expr: Box::new(Expr::BinOp(BinOp {
-                op: BinOpKind::Add,
-                lhs: Box::new(lhs),
-                rhs: Box::new(self.expr()),
-            })),
-        });
+    /// index of the loop broken out of or continued by the `break` or `continue` at `start`,
+    /// the innermost unless labeled
+    fn target_loop(&self, label: &Option<String>, start: usize) -> PResult<usize> {
+        let res = match label {
+            Some(label) => self
+                .breaks
+                .iter()
+                .rposition(|(x, _)| x.as_ref() == Some(label)),
+            None => self.breaks.len().checked_sub(1),
+        };
+        res.ok_or_else(|| match label {
+            Some(label) => self.invalid(start, format!("use of undeclared label `'{}`", label)),
+            None => self.invalid(start, "`break` or `continue` outside of a loop"),
+        })
+    }
 
-        self.expect(&[TokenKind::RBlanket]);
-        res
+    fn break_expr(&mut self) -> PResult<Break> {
+        // `break` was just consumed
+        let start = self.head - 1;
+        let label = self.label();
+        let i = self.target_loop(&label, start)?;
+        let (expr, ty) = if self.peek(&[TokenKind::Semi]) || self.peek(&[TokenKind::RBrace]) {
+            (None, Type::Void)
+        } else {
+            let expr_start = self.head;
+            let expr = self.expr()?;
+            let ty = self.expr_ty(&expr, expr_start)?;
+            (Some(self.alloc(expr)), ty)
+        };
+
+        let (_, broken) = &mut self.breaks[i];
+        match broken {
+            Some(prev) if *prev != ty => {
+                let message = format!("mismatched types of `break`, {} and {}", prev, ty);
+                return Err(self.invalid(start, message));
+            }
+            _ => *broken = Some(ty),
+        }
+
+        Ok(Break { label, expr })
+    }
+
+    fn for_loop(&mut self, label: Option<String>) -> PResult<For> {
+        self.expect(&[TokenKind::For])?;
+        let name = self.expect_ident()?;
+        self.expect_contextual("in")?;
+
+        let start = self.expr()?;
+        let start_span = self.last;
+        self.expect(&[TokenKind::DotDot])?;
+        let end = self.expr()?;
+        let end_span = self.last;
+
+        // the range is evaluated before the variable comes into scope
+        let var = Local {
+            name,
+            ty: Type::I64,
+        };
+        let body = self.scoped(&var, |x| x.loop_body(&label))?.0;
+
+        Ok(For {
+            label,
+            var,
+            range: Range {
+                start: self.alloc_at(start, start_span),
+                end: self.alloc_at(end, end_span),
+            },
+            body,
+        })
+    }
+
+    fn fn_call(&mut self, name: String) -> PResult<FnCall> {
+        let args = self.args()?;
+        Ok(self.call(name, args))
+    }
+
+    /// `receiver.name(args)`, calling `T::name` with the receiver first,
+    /// taking its address or dereferencing it to match `self`
+    /// `receiver` of `receiver_ty` is parsed from the token at `start`
+    fn method_call(
+        &mut self,
+        receiver: Expr,
+        receiver_ty: Type,
+        name: &str,
+        start: usize,
+    ) -> PResult<FnCall> {
+        let ty = match &receiver_ty {
+            Type::Ptr { to } => (**to).clone(),
+            ty => ty.clone(),
+        };
+        let no_method = format!("no method `{}` on {}", name, ty);
+        let Some(ty_name) = type_name(&ty) else {
+            return Err(self.invalid(start, no_method));
+        };
+        let name = format!("{}::{}", ty_name, name);
+        let self_ty = match self.signatures.get(&name) {
+            Some(Type::Fn { args, .. }) => args.first().cloned(),
+            _ => return Err(self.invalid(start, no_method)),
+        };
+        let Some(self_ty) = self_ty else {
+            return Err(self.invalid(
+                start,
+                format!("`{}` takes no `self`, call it as an associated fn", name),
+            ));
+        };
+
+        let receiver = match (receiver_ty, &self_ty) {
+            (a, b) if a == *b => receiver,
+            (_, Type::Ptr { .. }) => Expr::UnOp(UnOp {
+                kind: UnOpKind::Ref,
+                expr: self.alloc(receiver),
+            }),
+            _ => Expr::UnOp(UnOp {
+                kind: UnOpKind::Deref,
+                expr: self.alloc(receiver),
+            }),
+        };
+        let mut args = vec![self.alloc(receiver)];
+        args.extend(self.args()?);
+        Ok(self.call(name, args))
+    }
+
+    /// call to the fn `name`, filling in the defaults of the parameters left out.
+    /// fns defined further down are `Unknown` until `forward_calls`
+    fn call(&mut self, name: String, mut args: Vec<NodeId>) -> FnCall {
+        let fn_type = self.signatures.get(&name).unwrap_or(&Type::Unknown).clone();
+        self.fill_defaults(&name, &fn_type, &mut args);
+
+        FnCall {
+            fn_type,
+            name,
+            args,
+        }
     }
 
-    fn ifelse(&mut self) -> IfElse {
-        self.expect(&[TokenKind::If]);
-        IfElse {
-            cond: Box::new(self.expr()),
-            if_body: self.block(),
-            else_body: self.consume(&[TokenKind::Else]).then(|| self.block()),
+    fn fill_defaults(&mut self, name: &str, fn_type: &Type, args: &mut Vec<NodeId>) {
+        if let (Type::Fn { args: params, .. }, Some(defaults)) = (fn_type, self.defaults.get(name))
+        {
+            let first_default = params.len() - defaults.len();
+            if (first_default..params.len()).contains(&args.len()) {
+                for default in defaults[args.len() - first_default..].iter() {
+                    args.push(self.arena.alloc(default.clone()));
+                }
+            }
         }
     }
 
-    fn fn_call(&mut self) -> FnCall {
-        let name = self.expect_ident();
-        self.expect(&[TokenKind::LParen]);
+    /// give the calls parsed since `start` to fns defined after them their signature,
+    /// now that every fn of the file is known
+    fn forward_calls(&mut self, start: NodeId) {
+        let ids: Vec<_> = self.arena.ids_since(start).collect();
+        for id in ids {
+            let Expr::FnCall(FnCall {
+                fn_type: Type::Unknown,
+                name,
+                ..
+            }) = &self.arena[id]
+            else {
+                continue;
+            };
+            let inner = format!("{}{}", self.prefix, name);
+            let Some((name, fn_type)) = [inner, name.clone()].into_iter().find_map(|x| match self
+                .signatures
+                .get(&x)
+            {
+                Some(ty @ Type::Fn { .. }) if self.items.contains(&x) => Some((x, ty.clone())),
+                _ => None,
+            }) else {
+                // left to `resolve` to report
+                continue;
+            };
+
+            let Expr::FnCall(call) = &mut self.arena[id] else {
+                unreachable!()
+            };
+            let mut args = std::mem::take(&mut call.args);
+            self.fill_defaults(&name, &fn_type, &mut args);
+            self.arena[id] = Expr::FnCall(FnCall {
+                fn_type,
+                name,
+                args,
+            });
+        }
+    }
+
+    /// `(a, b, c)` of a call
+    fn args(&mut self) -> PResult<Vec<NodeId>> {
+        self.expect(&[TokenKind::LParen])?;
 
         let mut args = vec![];
         while !self.consume(&[TokenKind::RParen]) {
-            args.push(self.expr());
+            let arg = self.expr()?;
+            args.push(self.alloc(arg));
             self.consume(&[TokenKind::Comma]);
         }
+        Ok(args)
+    }
 
-        FnCall {
-            fn_type: self.signatures.get(&name).unwrap_or(&Type::Unknown).clone(),
-            name,
+    /// `|a, b: T| body` or `|| body`, lifted into a fn taking the address of the environment first.
+    /// parameters are i64 unless annotated, as nothing is inferred yet
+    fn closure(&mut self) -> PResult<Closure> {
+        let mut params = vec![];
+        if !self.consume(&[TokenKind::OrOr]) {
+            self.expect(&[TokenKind::Or])?;
+            while !self.consume(&[TokenKind::Or]) {
+                let name = self.expect_ident()?;
+                let ty = if self.consume(&[TokenKind::Colon]) {
+                    self.ty()?
+                } else {
+                    Type::I64
+                };
+                self.consume(&[TokenKind::Comma]);
+                params.push(Local { name, ty });
+            }
+        }
+        let ret = if self.consume(&[TokenKind::Arrow]) {
+            Some(self.ty()?)
+        } else {
+            None
+        };
+
+        let mut args = vec![env()];
+        args.extend(params.iter().cloned());
+        let shadowed: Vec<_> = args
+            .iter()
+            .map(|x| self.signatures.insert(x.name.clone(), x.ty.clone()))
+            .collect();
+        self.closures.push(ClosureScope {
+            declared: args.iter().map(|x| x.name.clone()).collect(),
+            captures: vec![],
+        });
+        // loops outside can't be broken out of from within
+        let breaks = std::mem::take(&mut self.breaks);
+        let body_start = self.head;
+        let body = self.expr();
+        self.breaks = breaks;
+        let scope = self.closures.pop().unwrap();
+        for (arg, shadowed) in args.iter().zip(shadowed) {
+            match shadowed {
+                Some(ty) => self.signatures.insert(arg.name.clone(), ty),
+                None => self.signatures.remove(&arg.name),
+            };
+        }
+        let body = body?;
+
+        let ret = match ret {
+            Some(ret) => ret,
+            None => self.expr_ty(&body, body_start)?,
+        };
+        let ret = Box::new(ret);
+        let name = format!("{}__closure{}", self.prefix, self.closure_count);
+        self.closure_count += 1;
+        let body = self.alloc(body);
+        self.lifted.push(FnDef {
+            comments: vec![],
+            attrs: vec![],
+            name: name.clone(),
+            fn_type: Type::Fn {
+                args: args.iter().map(|x| x.ty.clone()).collect(),
+                ret: ret.clone(),
+            },
             args,
+            body: Block { exprs: vec![body] },
+        });
+
+        Ok(Closure {
+            name,
+            captures: scope.captures.into_iter().map(|(_, x, _)| x).collect(),
+            ty: Type::Fn {
+                args: params.into_iter().map(|x| x.ty).collect(),
+                ret,
+            },
+        })
+    }
+
+    /// the local `name`, read from the environment if it belongs outside the enclosing closure
+    fn local(&mut self, name: String, ty: Type) -> Expr {
+        self.captured(self.closures.len(), name, ty)
+    }
+
+    /// the local `name` as seen from within the first `depth` enclosing closures,
+    /// capturing it into each of them on the way
+    fn captured(&mut self, depth: usize, name: String, ty: Type) -> Expr {
+        if depth == 0
+            || self.items.contains(&name)
+            || self.closures[depth - 1].declared.contains(&name)
+        {
+            return Expr::Local(Local { name, ty });
         }
+
+        let scope = &self.closures[depth - 1];
+        let offset = match scope.captures.iter().find(|(x, ..)| *x == name) {
+            Some(&(_, _, offset)) => offset,
+            None => {
+                let value = self.captured(depth - 1, name.clone(), ty.clone());
+                let value = self.alloc(value);
+                let captures = &mut self.closures[depth - 1].captures;
+                // after the code
+                let tys = captures
+                    .iter()
+                    .map(|(_, x, _)| self.arena[*x].ty(&self.arena));
+                let env = Type::closure_env(tys.chain([ty.clone()]));
+                let offset = env.offset_of(&(captures.len() + 1).to_string());
+                captures.push((name, value, offset));
+                offset
+            }
+        };
+
+        // `*((__env as i64 + offset) as &T)`
+        let env = self.alloc(Expr::Local(env()));
+        let lhs = self.alloc(Expr::Cast(Cast {
+            expr: env,
+            ty: Type::I64,
+        }));
+        let rhs = self.alloc(Expr::Number(Number {
+            value: offset.to_string(),
+        }));
+        let addr = self.alloc(Expr::BinOp(BinOp {
+            op: BinOpKind::Add,
+            lhs,
+            rhs,
+        }));
+        Expr::UnOp(UnOp {
+            kind: UnOpKind::Deref,
+            expr: self.alloc(Expr::Cast(Cast {
+                expr: addr,
+                ty: Type::Ptr { to: Box::new(ty) },
+            })),
+        })
     }
 
-    fn init(&mut self) -> Init {
-        self.expect(&[TokenKind::Let]);
-        let name = self.expect_ident();
+    /// remember that `name` is not captured by the closure being parsed, if any
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.closures.last_mut() {
+            scope.declared.insert(name.to_string());
+        }
+    }
 
-        let mut ty = if self.consume(&[TokenKind::Colon]) {
-            self.ty()
+    fn init(&mut self) -> PResult<Init> {
+        self.expect(&[TokenKind::Let])?;
+        let mutable = self.consume(&[TokenKind::Mut]);
+        let name_span = (self.head, self.head + 1);
+        let name = self.expect_ident()?;
+
+        let annotation = if self.consume(&[TokenKind::Colon]) {
+            Some(self.ty()?)
         } else {
-            Type::Unknown
+            None
         };
 
         let value = if self.consume(&[TokenKind::Eq]) {
-            let expr = self.expr();
-            ty = expr.ty();
-            Some(Box::new(expr))
+            let expr = self.expr()?;
+            Some(self.alloc(expr))
         } else {
             None
         };
+        // checked against the initializer by `typeck`, which also infers what is left unknown
+        let ty = annotation.unwrap_or_else(|| match value {
+            Some(value) => self.arena[value].infer(&self.arena),
+            None => Type::Unknown,
+        });
 
         self.signatures.insert(name.clone(), ty.clone());
+        self.declare(&name);
 
-        Init {
-            name: Box::new(Expr::Local(Local { name, ty })),
+        Ok(Init {
+            name: self.alloc_at(Expr::Local(Local { name, ty }), name_span),
             value,
+            mutable,
+        })
+    }
+
+    /// `let (a, b) = value;` or `let Point { x, y: (a, b) } = value;`,
+    /// the `let`s binding each name in turn to an element or field,
+    /// which `block` puts in the enclosing block
+    fn destructuring_init(&mut self) -> PResult<Block> {
+        let start = self.head;
+        self.expect(&[TokenKind::Let])?;
+        let pat = self.let_pattern()?;
+        self.expect(&[TokenKind::Eq])?;
+        let value_start = self.head;
+        let value = self.expr()?;
+        let ty = self.expr_ty(&value, value_start)?;
+
+        let mut exprs = vec![];
+        // evaluated once, into a temporary unless it already is a local
+        let value = match value {
+            Expr::Local(_) => value,
+            _ => {
+                let local = Local {
+                    name: format!("__destructure{}", self.head),
+                    ty: ty.clone(),
+                };
+                let init = Expr::Init(Init {
+                    name: self.alloc(Expr::Local(local.clone())),
+                    value: Some(self.alloc(value)),
+                    mutable: false,
+                });
+                exprs.push(self.alloc(init));
+                Expr::Local(local)
+            }
+        };
+        self.destructure(pat, value, ty, &mut exprs)
+            .map_err(|e| self.invalid(start, e))?;
+        Ok(Block { exprs })
+    }
+
+    fn let_pattern(&mut self) -> PResult<LetPattern> {
+        let res = if self.consume(&[TokenKind::LParen]) {
+            let mut elements = vec![];
+            while !self.consume(&[TokenKind::RParen]) {
+                elements.push(self.let_pattern()?);
+                self.consume(&[TokenKind::Comma]);
+            }
+            LetPattern::Tuple(elements)
+        } else if self.peek(&[TokenKind::Ident, TokenKind::LBrace]) {
+            let path = self.path()?;
+            self.expect(&[TokenKind::LBrace])?;
+            let mut fields = vec![];
+            let mut rest = false;
+            while !self.consume(&[TokenKind::RBrace]) {
+                if self.consume(&[TokenKind::DotDot]) {
+                    rest = true;
+                    self.expect(&[TokenKind::RBrace])?;
+                    break;
+                }
+                let field = self.expect_ident()?;
+                // `x` is short for `x: x`
+                let pat = if self.consume(&[TokenKind::Colon]) {
+                    self.let_pattern()?
+                } else {
+                    LetPattern::Name(field.clone(), false)
+                };
+                fields.push((field, pat));
+                self.consume(&[TokenKind::Comma]);
+            }
+            LetPattern::Struct(self.resolve(&path), fields, rest)
+        } else {
+            let mutable = self.consume(&[TokenKind::Mut]);
+            match self.expect_ident()? {
+                x if x == "_" => LetPattern::Wildcard,
+                x => LetPattern::Name(x, mutable),
+            }
+        };
+        Ok(res)
+    }
+
+    /// bind the names in `pat` to the parts of `value` of `ty`, which is a place,
+    /// or tell why they don't fit
+    fn destructure(
+        &mut self,
+        pat: LetPattern,
+        value: Expr,
+        ty: Type,
+        out: &mut Vec<NodeId>,
+    ) -> Result<(), String> {
+        let parts = match pat {
+            LetPattern::Wildcard => vec![],
+            LetPattern::Name(name, mutable) => {
+                self.signatures.insert(name.clone(), ty.clone());
+                self.declare(&name);
+                let init = Expr::Init(Init {
+                    name: self.alloc(Expr::Local(Local { name, ty })),
+                    value: Some(self.alloc(value)),
+                    mutable,
+                });
+                out.push(self.alloc(init));
+                return Ok(());
+            }
+            LetPattern::Tuple(elements) => match &ty {
+                Type::Tuple { elements: tys } if tys.len() == elements.len() => elements
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, x)| (i.to_string(), x))
+                    .collect(),
+                _ => {
+                    return Err(format!(
+                        "expected a tuple of {} elements, found {}",
+                        elements.len(),
+                        ty
+                    ))
+                }
+            },
+            LetPattern::Struct(name, fields, rest) => match &ty {
+                Type::Struct {
+                    name: ty_name,
+                    fields: declared,
+                } if *ty_name == name => {
+                    for (field, _) in declared {
+                        if !rest && !fields.iter().any(|(x, _)| x == field) {
+                            return Err(format!("pattern does not mention field `{}`", field));
+                        }
+                    }
+                    if let Some((field, _)) = fields
+                        .iter()
+                        .find(|(x, _)| declared.iter().all(|(y, _)| x != y))
+                    {
+                        return Err(format!("struct {} has no field named `{}`", name, field));
+                    }
+                    fields
+                }
+                _ => return Err(format!("expected a pattern of {}, found `{}`", ty, name)),
+            },
+        };
+
+        for (field, pat) in parts {
+            let part_ty = ty.field(&field)?.1;
+            let part = Expr::Field(Field {
+                base: self.alloc(value.clone()),
+                name: field,
+            });
+            self.destructure(pat, part, part_ty, out)?;
         }
+        Ok(())
     }
 
-    fn ty(&mut self) -> Type {
-        if self.consume(&[TokenKind::And]) {
+    /// `&T`, `[T; N]`, `(T, U)`, `()`, `fn(T, U) -> V`, `!` or a named type
+    fn ty(&mut self) -> PResult<Type> {
+        let res = if self.consume(&[TokenKind::And]) {
+            Type::Ptr {
+                to: Box::new(self.ty()?),
+            }
+        } else if self.consume(&[TokenKind::AndAnd]) {
+            // `&&T` is `&(&T)`
             Type::Ptr {
-                to: Box::new(self.ty()),
+                to: Box::new(Type::Ptr {
+                    to: Box::new(self.ty()?),
+                }),
             }
         } else if self.consume(&[TokenKind::LBlanket]) {
-            let ty = self.ty();
-            self.expect(&[TokenKind::Semi]);
-            let len = self.expect_number().parse().unwrap();
-            self.expect(&[TokenKind::RBlanket]);
+            let ty = self.ty()?;
+            self.expect(&[TokenKind::Semi])?;
+            let start = self.head;
+            let len = self.expr()?;
+            let len = const_eval::eval(&self.arena, &len).map_err(|e| self.invalid(start, e))?;
+            let Ok(len) = usize::try_from(len) else {
+                return Err(self.invalid(start, "array length must not be negative"));
+            };
+            self.expect(&[TokenKind::RBlanket])?;
 
             Type::Array {
                 element: Box::new(ty),
                 len,
             }
-        } else {
-            let id = self.expect_ident();
-            match id.as_str() {
+        } else if self.consume(&[TokenKind::LParen]) {
+            let mut elements = vec![];
+            let mut trailing_comma = false;
+            while !self.consume(&[TokenKind::RParen]) {
+                elements.push(self.ty()?);
+                trailing_comma = self.consume(&[TokenKind::Comma]);
+            }
+
+            match elements.len() {
+                0 => Type::Void, // unit
+                1 if !trailing_comma => elements.pop().unwrap(),
+                _ => Type::Tuple { elements },
+            }
+        } else if self.consume(&[TokenKind::Fn]) {
+            self.expect(&[TokenKind::LParen])?;
+            let mut args = vec![];
+            while !self.consume(&[TokenKind::RParen]) {
+                args.push(self.ty()?);
+                self.consume(&[TokenKind::Comma]);
+            }
+            let ret = if self.consume(&[TokenKind::Arrow]) {
+                self.ty()?
+            } else {
+                Type::Void
+            };
+
+            Type::Fn {
+                args,
+                ret: Box::new(ret),
+            }
+        } else if self.consume(&[TokenKind::Bang]) {
+            Type::Never
+        } else if self.peek(&[TokenKind::Ident]) {
+            let start = self.head;
+            let path = self.path()?;
+            match path.as_str() {
+                "Self" => match &self.self_ty {
+                    Some(ty) => ty.clone(),
+                    None => return Err(self.invalid(start, "`Self` outside of an impl")),
+                },
+                "i8" => Type::I8,
+                "i16" => Type::I16,
+                "i32" => Type::I32,
                 "i64" => Type::I64,
+                "u8" => Type::U8,
+                "u16" => Type::U16,
+                "u32" => Type::U32,
+                "u64" => Type::U64,
+                "f64" => Type::F64,
+                "char" => Type::Char,
+                "str" => Type::Str,
+                "bool" => Type::Bool,
                 "void" => Type::Void,
                 "never" => Type::Never,
-                _ => panic!("found unknown type {}", id),
+                _ => match self.types.get(&self.resolve(&path)) {
+                    Some(ty) => ty.clone(),
+                    None => return Err(self.invalid(start, format!("cannot find type `{}`", path))),
+                },
             }
-        }
+        } else {
+            return Err(self.unexpected(["type"]));
+        };
+        Ok(res)
     }
 
-    fn local(&mut self) -> Local {
-        let name = self.expect_ident();
-        let ty = self.signatures[&name].clone();
-        Local { ty, name }
+    /// the byte of a character literal, which the lexer makes sure is ASCII
+    fn char(&mut self) -> PResult<u8> {
+        let c = self.get().value.clone().unwrap();
+        self.expect(&[TokenKind::Char])?;
+        Ok(c.chars().next().unwrap() as u8)
     }
 
-    fn number(&mut self) -> Number {
-        Number {
-            value: self.expect_number(),
-        }
+    fn number(&mut self) -> PResult<Number> {
+        // checked against the type it ends up with by `typeck`
+        let value = self.expect_number()?;
+        Ok(Number { value })
+    }
+}
+
+/// `noun` followed by `items`, made plural if there are several
+fn plural(noun: &str, items: &[String]) -> String {
+    match items {
+        [one] => format!("{} {}", noun, one),
+        many => format!("{}s {}", noun, many.join(", ")),
+    }
+}
+
+/// name of a type that can have methods, only structs, enums and `str` do
+fn type_name(ty: &Type) -> Option<&str> {
+    match ty {
+        Type::Struct { name, .. } | Type::Enum { name, .. } => Some(name),
+        Type::Str => Some("str"),
+        _ => None,
+    }
+}
+
+/// hidden first argument of a lifted closure, pointing to its environment
+fn env() -> Local {
+    Local {
+        name: "__env".to_string(),
+        ty: Type::Ptr {
+            to: Box::new(Type::I64),
+        },
     }
 }
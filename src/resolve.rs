@@ -0,0 +1,187 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        Arena, Arm, Assign, Ast, Block, Definition, Expr, Field, FnCall, FnDef, Index, Init, Local,
+        NodeId, Static,
+    },
+    ty::Type,
+    visit::{walk_block, walk_expr, walk_static, Visitor},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveError {
+    pub message: String,
+    /// the fn or static the name is in
+    pub item: String,
+}
+
+/// a `let` declaring a name that is already in scope, hiding it until the end of the block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowing {
+    pub name: String,
+    pub item: String,
+    /// the `Init` of the `let`
+    pub init: NodeId,
+}
+
+/// check that every local and fn refers to something in scope where it is used,
+/// locals being visible from their `let` to the end of the enclosing block,
+/// and that only `let mut`s are assigned to after they are initialized
+pub fn resolve(ast: &Ast) -> Result<Vec<Shadowing>, Vec<ResolveError>> {
+    let mut fns = HashSet::new();
+    let mut statics = HashSet::new();
+    for definition in ast.node.definitions.iter() {
+        match definition {
+            Definition::Fn(f) => fns.insert(f.name.clone()),
+            Definition::Extern(e) => fns.insert(e.name.clone()),
+            Definition::Static(s) => statics.insert(s.name.clone()),
+        };
+    }
+
+    let mut resolver = Resolver {
+        fns,
+        statics,
+        scopes: vec![],
+        item: String::new(),
+        errors: vec![],
+        shadowings: vec![],
+    };
+    resolver.visit_ast(ast);
+    if resolver.errors.is_empty() {
+        Ok(resolver.shadowings)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver {
+    fns: HashSet<String>,
+    statics: HashSet<String>,
+    /// names declared in each block entered, innermost last, with whether they are mutable
+    scopes: Vec<HashMap<String, bool>>,
+    /// the fn or static being resolved
+    item: String,
+    errors: Vec<ResolveError>,
+    shadowings: Vec<Shadowing>,
+}
+
+impl Visitor for Resolver {
+    fn visit_fn_def(&mut self, arena: &Arena<Expr>, f: &FnDef) {
+        self.item = f.name.clone();
+        // parameters are the caller's copies, free to change
+        self.scopes = vec![f.args.iter().map(|x| (x.name.clone(), true)).collect()];
+        self.visit_block(arena, &f.body);
+    }
+
+    fn visit_static(&mut self, arena: &Arena<Expr>, s: &Static) {
+        self.item = s.name.clone();
+        self.scopes = vec![];
+        walk_static(self, arena, s);
+    }
+
+    fn visit_block(&mut self, arena: &Arena<Expr>, block: &Block) {
+        self.scopes.push(HashMap::new());
+        walk_block(self, arena, block);
+        self.scopes.pop();
+    }
+
+    fn visit_arm(&mut self, arena: &Arena<Expr>, arm: &Arm) {
+        let mut scope = HashMap::new();
+        if let Some(local) = arm.pat.binding() {
+            scope.insert(local.name.clone(), false);
+        }
+        self.scopes.push(scope);
+        self.visit_expr(arena, arm.body);
+        self.scopes.pop();
+    }
+
+    fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        match &arena[id] {
+            Expr::Init(Init {
+                name,
+                value,
+                mutable,
+            }) => {
+                // the value is evaluated before the name comes into scope
+                if let Some(value) = value {
+                    self.visit_expr(arena, *value);
+                }
+                let Expr::Local(Local { name, .. }) = &arena[*name] else {
+                    panic!("lhs must be addressable")
+                };
+                if self.in_scope(name) {
+                    self.shadowings.push(Shadowing {
+                        name: name.clone(),
+                        item: self.item.clone(),
+                        init: id,
+                    });
+                }
+                // `let x;` is given its value by assigning to it
+                let mutable = *mutable || value.is_none();
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.clone(), mutable);
+            }
+            Expr::Local(Local { name, .. }) => {
+                if !self.in_scope(name) && !self.statics.contains(name) {
+                    self.error(format!("undefined variable `{}`", name));
+                }
+            }
+            Expr::Assign(Assign { lhs, .. }) => {
+                if let Some(name) = place_root(arena, *lhs) {
+                    if self.lookup(name) == Some(false) {
+                        self.error(format!(
+                            "cannot assign to immutable variable `{}`, declare it with `let mut`",
+                            name
+                        ));
+                    }
+                }
+                walk_expr(self, arena, id);
+            }
+            Expr::FnCall(FnCall { name, .. }) => {
+                if !self.fns.contains(name) {
+                    self.error(format!("undefined function `{}`", name));
+                }
+                walk_expr(self, arena, id);
+            }
+            _ => walk_expr(self, arena, id),
+        }
+    }
+}
+
+impl Resolver {
+    fn in_scope(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// whether the local `name` is mutable, if it is in scope
+    fn lookup(&self, name: &str) -> Option<bool> {
+        self.scopes.iter().rev().find_map(|x| x.get(name).copied())
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(ResolveError {
+            message,
+            item: self.item.clone(),
+        });
+    }
+}
+
+/// the local whose own memory the place `id` is part of, if any,
+/// as what a pointer points to is not
+fn place_root(arena: &Arena<Expr>, id: NodeId) -> Option<&str> {
+    match &arena[id] {
+        Expr::Local(Local { name, .. }) => Some(name),
+        Expr::Field(Field { base, .. }) => place_root(arena, *base),
+        Expr::Index(Index { base, .. }) => match &arena[*base] {
+            Expr::Local(Local {
+                ty: Type::Ptr { .. } | Type::Str,
+                ..
+            }) => None,
+            _ => place_root(arena, *base),
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,129 @@
+use std::{fs::File, io, path::Path};
+
+use memmap2::Mmap;
+
+/// files at least this large are memory-mapped rather than read
+const MMAP_THRESHOLD: u64 = 1 << 20;
+
+/// index of a file in `SourceMap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileId(usize);
+
+/// contents of a file, checked to be UTF-8 when loaded
+#[derive(Debug)]
+enum Text {
+    Owned(String),
+    Mapped(Mmap),
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    text: Text,
+    /// global offset of the first byte
+    pub start: usize,
+    /// global offsets of the first byte of each line
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn src(&self) -> &str {
+        match &self.text {
+            Text::Owned(src) => src,
+            // SAFETY: checked to be UTF-8 in `SourceMap::load`, and never written to
+            Text::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map) },
+        }
+    }
+
+    /// global offset one past the last byte
+    pub fn end(&self) -> usize {
+        self.start + self.src().len()
+    }
+
+    /// text of the 1-indexed `line` without its newline
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1] - self.start;
+        let src = self.src();
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(src.len(), |x| x - self.start);
+        src[start..end].trim_end_matches(['\n', '\r'])
+    }
+}
+
+/// resolved location of a global offset
+#[derive(Debug)]
+pub struct Loc<'a> {
+    pub file: &'a SourceFile,
+    /// 1-indexed
+    pub line: usize,
+    /// 1-indexed, in chars
+    pub col: usize,
+}
+
+/// owns every loaded file, laid out one after another in a single global offset space,
+/// so a span alone identifies both the file and the position in it
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, name: impl Into<String>, src: String) -> FileId {
+        self.push(name.into(), Text::Owned(src))
+    }
+
+    /// read the file at `path`, mapping it into memory if it is large
+    pub fn load(&mut self, path: &Path) -> io::Result<FileId> {
+        let file = File::open(path)?;
+        let text = if file.metadata()?.len() >= MMAP_THRESHOLD {
+            // SAFETY: the map is only read, though another process truncating the file
+            // while it is compiled would fault, as it would with any mmap-ing tool
+            let map = unsafe { Mmap::map(&file)? };
+            std::str::from_utf8(&map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Text::Mapped(map)
+        } else {
+            Text::Owned(io::read_to_string(file)?)
+        };
+        Ok(self.push(path.display().to_string(), text))
+    }
+
+    fn push(&mut self, name: String, text: Text) -> FileId {
+        // leave a gap of one so the end of a file is not the start of the next
+        let start = self.files.last().map_or(0, |x| x.end() + 1);
+        let mut file = SourceFile {
+            name,
+            text,
+            start,
+            line_starts: vec![],
+        };
+        file.line_starts = std::iter::once(start)
+            .chain(file.src().match_indices('\n').map(|(i, _)| start + i + 1))
+            .collect();
+
+        self.files.push(file);
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn get(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0]
+    }
+
+    pub fn lookup(&self, pos: usize) -> Loc<'_> {
+        let i = self.files.partition_point(|x| x.start <= pos) - 1;
+        let file = &self.files[i];
+
+        let line = file.line_starts.partition_point(|&x| x <= pos);
+        let line_start = file.line_starts[line - 1] - file.start;
+        let src = file.src();
+        let offset = (pos - file.start).min(src.len());
+        let col = src[line_start..offset].chars().count() + 1;
+
+        Loc { file, line, col }
+    }
+}
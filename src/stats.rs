@@ -0,0 +1,91 @@
+use std::fmt::Write;
+
+#[derive(Debug, Default)]
+pub struct FnStats {
+    pub name: String,
+    pub instructions: usize,
+    pub frame_size: usize,
+    /// temporaries pushed onto the stack
+    pub spills: usize,
+    pub calls: usize,
+}
+
+/// read per-function statistics back from the emitted assembly
+pub fn collect(asm: &str) -> Vec<FnStats> {
+    let mut res: Vec<FnStats> = vec![];
+    // statics live in other sections, between functions
+    let mut in_text = true;
+    for line in asm.lines() {
+        match line {
+            ".text" => in_text = true,
+            ".data" | ".bss" => in_text = false,
+            _ if line.starts_with(".section ") => in_text = false,
+            _ => {}
+        }
+        if !in_text {
+            continue;
+        }
+
+        if let Some(label) = line.strip_suffix(':') {
+            // local labels start with `.`
+            if !label.starts_with('.') && !label.starts_with(' ') {
+                res.push(FnStats {
+                    name: label.to_string(),
+                    ..Default::default()
+                });
+            }
+            continue;
+        }
+
+        // the frame size is set right after the function
+        if let Some((_, size)) = line.strip_prefix(".set ").and_then(|x| x.split_once(", ")) {
+            if let Some(stats) = res.last_mut() {
+                stats.frame_size = size.parse().unwrap_or(0);
+            }
+            continue;
+        }
+
+        let (Some(inst), Some(stats)) = (line.strip_prefix("    "), res.last_mut()) else {
+            continue;
+        };
+        stats.instructions += 1;
+
+        let mut words = inst.split([' ', ',']).filter(|x| !x.is_empty());
+        match (words.next(), words.next(), words.next()) {
+            (Some("push"), Some("rbp"), _) => {}
+            (Some("push"), ..) => stats.spills += 1,
+            (Some("call"), ..) => stats.calls += 1,
+            _ => {}
+        }
+    }
+    res
+}
+
+pub fn to_text(stats: &[FnStats]) -> String {
+    let mut res = format!(
+        "{:<16} {:>12} {:>10} {:>8} {:>6}\n",
+        "function", "instructions", "frame", "spills", "calls"
+    );
+    for x in stats {
+        writeln!(
+            res,
+            "{:<16} {:>12} {:>10} {:>8} {:>6}",
+            x.name, x.instructions, x.frame_size, x.spills, x.calls
+        )
+        .unwrap();
+    }
+    res
+}
+
+pub fn to_json(stats: &[FnStats]) -> String {
+    let fns = stats
+        .iter()
+        .map(|x| {
+            format!(
+                r#"{{"name":"{}","instructions":{},"frame_size":{},"spills":{},"calls":{}}}"#,
+                x.name, x.instructions, x.frame_size, x.spills, x.calls
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", fns.join(","))
+}
@@ -1,6 +1,21 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Type {
+    I8,
+    I16,
+    I32,
     I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F64,
+    /// a byte, converted to and from integers with `as`
+    Char,
+    /// the address of the bytes of a string and their count, fields `ptr` and `len`
+    Str,
     Bool,
     Ptr {
         to: Box<Type>,
@@ -9,28 +24,302 @@ pub enum Type {
         element: Box<Type>,
         len: usize,
     },
+    /// laid out in order, like a struct with fields `0`, `1`, ..
+    Tuple {
+        elements: Vec<Type>,
+    },
+    /// fields are laid out in declaration order
+    Struct {
+        name: String,
+        fields: Vec<(String, Type)>,
+    },
+    /// the index of the variant as a tag, followed by the payload if any variant has one
+    Enum {
+        name: String,
+        variants: Vec<(String, Option<Type>)>,
+    },
     Fn {
         args: Vec<Type>,
         ret: Box<Type>,
     },
     Void,
     Never,
-
-    #[allow(dead_code)]
+    /// type parameter of a generic fn, or `Self` in a trait
+    Param {
+        name: String,
+    },
+    /// not inferred yet, or of an expression already reported as an error
     Unknown,
 }
 
 impl Type {
     pub fn size(&self) -> usize {
-        match self {
-            Type::I64 => 8,
+        self.try_size().unwrap_or_else(|| self.not_known("size"))
+    }
+
+    /// `None` for types that stand in for one not known yet
+    pub fn try_size(&self) -> Option<usize> {
+        let res = match self {
+            Type::I8 | Type::U8 | Type::Char => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 => 4,
+            Type::I64 | Type::U64 | Type::F64 => 8,
             Type::Ptr { .. } => 8,
-            Type::Array { element, len } => element.size() * len,
-            Type::Bool => 8,
-            Type::Fn { .. } => todo!(),
-            Type::Void => todo!(),
-            Type::Never => todo!(),
-            Type::Unknown => todo!(),
+            Type::Str => 16,
+            Type::Array { element, len } => element.try_size()? * len,
+            Type::Tuple { elements } => layout(elements.iter())?.1,
+            Type::Struct { fields, .. } => layout(fields.iter().map(|(_, ty)| ty))?.1,
+            Type::Enum { variants, .. } => {
+                let payload = variants
+                    .iter()
+                    .flat_map(|(_, x)| x)
+                    .map(|x| x.try_size())
+                    .collect::<Option<Vec<_>>>()?;
+                (8 + payload.into_iter().max().unwrap_or(0)).next_multiple_of(8)
+            }
+            Type::Bool => 1,
+            Type::Fn { .. } => 8, // pointer to the code
+            // no value is ever stored
+            Type::Void | Type::Never => 0,
+            Type::Param { .. } | Type::Unknown => return None,
+        };
+        Some(res)
+    }
+
+    /// what the address of a value must be a multiple of, as in the C ABI
+    pub fn align(&self) -> usize {
+        self.try_align()
+            .unwrap_or_else(|| self.not_known("alignment"))
+    }
+
+    /// `None` for types that stand in for one not known yet
+    pub fn try_align(&self) -> Option<usize> {
+        match self {
+            Type::Array { element, .. } => element.try_align(),
+            Type::Tuple { elements } => max_align(elements.iter()),
+            Type::Struct { fields, .. } => max_align(fields.iter().map(|(_, x)| x)),
+            // the tag is a word
+            Type::Enum { .. } | Type::Str => Some(8),
+            Type::Void | Type::Never => Some(1),
+            ty => ty.try_size(),
+        }
+    }
+
+    fn not_known(&self, what: &str) -> ! {
+        match self {
+            Type::Param { name } => {
+                panic!(
+                    "{} of `{}` is not known before it is substituted",
+                    what, name
+                )
+            }
+            ty => panic!("{} of {:?} is not known", what, ty),
+        }
+    }
+
+    /// the environment of a closure, a tuple of the address of its code and the captured values
+    pub fn closure_env(captures: impl Iterator<Item = Type>) -> Type {
+        Type::Tuple {
+            elements: std::iter::once(Type::I64).chain(captures).collect(),
+        }
+    }
+
+    /// offset of the field `name` of a struct, or the element `name` of a tuple, known to exist
+    pub fn offset_of(&self, name: &str) -> usize {
+        self.field(name).unwrap().0
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_signed() || matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::I8 | Type::I16 | Type::I32 | Type::I64)
+    }
+
+    /// whether a constant of `self` can be taken as `ty`,
+    /// integer literals being `I64` until given a type
+    pub fn is_literal_of(&self, ty: &Type) -> bool {
+        self == ty || (*self == Type::I64 && ty.is_integer())
+    }
+
+    /// whether the integer `value` can be represented in `self`
+    pub fn fits(&self, value: i128) -> bool {
+        let bits = self.size() as u32 * 8;
+        if self.is_signed() {
+            (-(1 << (bits - 1))..1 << (bits - 1)).contains(&value)
+        } else {
+            (0..1 << bits).contains(&value)
         }
     }
+
+    /// too large for a register, so passed around by address
+    pub fn is_aggregate(&self) -> bool {
+        match self {
+            Type::Array { .. } | Type::Tuple { .. } | Type::Struct { .. } | Type::Str => true,
+            // C-like enums are just the tag
+            Type::Enum { variants, .. } => variants.iter().any(|(_, x)| x.is_some()),
+            _ => false,
+        }
+    }
+
+    /// bind the parameters in `self` so that it matches `actual`,
+    /// leaving anything else to be checked where the value is used
+    pub fn unify(&self, actual: &Type, bindings: &mut HashMap<String, Type>) {
+        match (self, actual) {
+            (Type::Param { name }, _) => match bindings.get(name) {
+                Some(bound) => assert_eq!(bound, actual, "mismatched types for `{}`", name),
+                None => {
+                    bindings.insert(name.clone(), actual.clone());
+                }
+            },
+            (Type::Ptr { to: a }, Type::Ptr { to: b }) => a.unify(b, bindings),
+            (Type::Array { element: a, .. }, Type::Array { element: b, .. }) => {
+                a.unify(b, bindings)
+            }
+            (Type::Tuple { elements: a }, Type::Tuple { elements: b }) => {
+                for (a, b) in a.iter().zip(b) {
+                    a.unify(b, bindings);
+                }
+            }
+            (Type::Fn { args: a, ret: r }, Type::Fn { args: b, ret: s }) => {
+                for (a, b) in a.iter().zip(b) {
+                    a.unify(b, bindings);
+                }
+                r.unify(s, bindings);
+            }
+            _ => {}
+        }
+    }
+
+    /// replace the parameters bound in `bindings`
+    pub fn subst(&self, bindings: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Param { name } => bindings.get(name).unwrap_or(self).clone(),
+            Type::Ptr { to } => Type::Ptr {
+                to: Box::new(to.subst(bindings)),
+            },
+            Type::Array { element, len } => Type::Array {
+                element: Box::new(element.subst(bindings)),
+                len: *len,
+            },
+            Type::Tuple { elements } => Type::Tuple {
+                elements: elements.iter().map(|x| x.subst(bindings)).collect(),
+            },
+            Type::Fn { args, ret } => Type::Fn {
+                args: args.iter().map(|x| x.subst(bindings)).collect(),
+                ret: Box::new(ret.subst(bindings)),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// index and payload type of the variant `name` of an enum, if it has one
+    pub fn variant(&self, name: &str) -> Option<(usize, Option<Type>)> {
+        let Type::Enum { variants, .. } = self else {
+            return None;
+        };
+
+        let index = variants.iter().position(|(x, _)| x == name)?;
+        Some((index, variants[index].1.clone()))
+    }
+
+    /// offset and type of the field `name` of a struct, or the element `name` of a tuple
+    pub fn field(&self, name: &str) -> Result<(usize, Type), String> {
+        let not_known = || format!("layout of {} is not known", self);
+        if *self == Type::Str {
+            return match name {
+                "ptr" => Ok((
+                    0,
+                    Type::Ptr {
+                        to: Box::new(Type::U8),
+                    },
+                )),
+                "len" => Ok((8, Type::I64)),
+                _ => Err(format!("no field `{}` on str", name)),
+            };
+        }
+
+        if let Type::Tuple { elements } = self {
+            let i = name
+                .parse::<usize>()
+                .ok()
+                .filter(|&i| i < elements.len())
+                .ok_or_else(|| format!("no field `{}` on {}", name, self))?;
+            let (offsets, _) = layout(elements.iter()).ok_or_else(not_known)?;
+            return Ok((offsets[i], elements[i].clone()));
+        }
+
+        let Type::Struct {
+            name: ty_name,
+            fields,
+        } = self
+        else {
+            return Err(format!("{} has no fields", self));
+        };
+
+        let (offsets, _) = layout(fields.iter().map(|(_, ty)| ty)).ok_or_else(not_known)?;
+        for ((field, ty), offset) in fields.iter().zip(offsets) {
+            if field == name {
+                return Ok((offset, ty.clone()));
+            }
+        }
+        Err(format!("no field `{}` on struct {}", name, ty_name))
+    }
+}
+
+/// as written in source, for diagnostics
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |tys: &[Type]| tys.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        match self {
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::F64 => write!(f, "f64"),
+            Type::Char => write!(f, "char"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Ptr { to } => write!(f, "&{}", to),
+            Type::Array { element, len } => write!(f, "[{}; {}]", element, len),
+            Type::Tuple { elements } if elements.len() == 1 => write!(f, "({},)", elements[0]),
+            Type::Tuple { elements } => write!(f, "({})", list(elements).join(", ")),
+            Type::Struct { name, .. } | Type::Enum { name, .. } | Type::Param { name } => {
+                write!(f, "{}", name)
+            }
+            Type::Fn { args, ret } => match **ret {
+                Type::Void => write!(f, "fn({})", list(args).join(", ")),
+                _ => write!(f, "fn({}) -> {}", list(args).join(", "), ret),
+            },
+            Type::Void => write!(f, "()"),
+            Type::Never => write!(f, "!"),
+            Type::Unknown => write!(f, "_"),
+        }
+    }
+}
+
+/// the largest alignment of `tys`, `None` if one of them is not known yet
+fn max_align<'a>(tys: impl Iterator<Item = &'a Type>) -> Option<usize> {
+    tys.map(Type::try_align).try_fold(1, |a, x| Some(a.max(x?)))
+}
+
+/// offsets of fields laid out in order, each padded to its alignment,
+/// and the size of the whole padded to the largest alignment,
+/// `None` if one of them is not known yet
+fn layout<'a>(fields: impl Iterator<Item = &'a Type>) -> Option<(Vec<usize>, usize)> {
+    let mut offsets = vec![];
+    let mut end: usize = 0;
+    let mut align = 1;
+    for ty in fields {
+        let offset = end.next_multiple_of(ty.try_align()?);
+        offsets.push(offset);
+        end = offset + ty.try_size()?;
+        align = align.max(ty.try_align()?);
+    }
+    Some((offsets, end.next_multiple_of(align)))
 }
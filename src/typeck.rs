@@ -0,0 +1,606 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+};
+
+use crate::{
+    ast::{
+        Arena, Array, Assign, Ast, BinOp, BinOpKind, Block, Call, Definition, Enclosed, Expr,
+        FnCall, FnDef, IfElse, Init, Local, Match, NodeId, Number, Return, Static, Stmt, Struct,
+        Tuple, UnOp, UnOpKind, Variant, While,
+    },
+    codegen::{ARG_REGS, FLOAT_ARG_REGS},
+    const_eval,
+    diagnostic::{Diagnostic, Level},
+    source_map::SourceMap,
+    ty::Type,
+    visit::{walk_expr, walk_fn_def, walk_static, Visitor},
+};
+
+/// type of every expression of an `Ast`
+pub type Types = HashMap<NodeId, Type>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub message: String,
+    /// the fn or static the expression is in
+    pub item: String,
+    /// the expression at fault, if it is down to one
+    pub node: Option<NodeId>,
+}
+
+impl TypeError {
+    /// at the span of its expression, or just naming the item it is in if that has none
+    pub fn render(
+        &self,
+        level: Level,
+        spans: &BTreeMap<NodeId, (usize, usize)>,
+        sources: &SourceMap,
+    ) -> String {
+        match self.node.and_then(|x| spans.get(&x)) {
+            Some(&pos) => Diagnostic {
+                level,
+                ..Diagnostic::error(&self.message, pos)
+            }
+            .render(sources),
+            None => format!("{}: {}\n  in `{}`\n", level, self.message, self.item),
+        }
+    }
+}
+
+/// assign a type to every expression, checking them before codegen relies on them
+pub fn check(ast: &Ast) -> Result<Types, Vec<TypeError>> {
+    let externs = ast
+        .node
+        .definitions
+        .iter()
+        .filter_map(|x| match x {
+            Definition::Extern(x) => Some((x.name.clone(), x.variadic)),
+            _ => None,
+        })
+        .collect();
+    let mut checker = Checker {
+        types: Types::new(),
+        errors: vec![],
+        externs,
+        item: String::new(),
+        node: None,
+        ret: Type::Void,
+        inferred: BTreeMap::new(),
+        literals: vec![],
+    };
+    checker.visit_ast(ast);
+    for (id, item) in std::mem::take(&mut checker.literals) {
+        let Expr::Number(Number { value }) = &ast.arena[id] else {
+            unreachable!()
+        };
+        let ty = &checker.types[&id];
+        if ty.is_integer() && !ty.fits(value.parse().unwrap()) {
+            checker.item = item;
+            checker.error_at(id, format!("literal out of range for {}", ty));
+        }
+    }
+    if checker.errors.is_empty() {
+        Ok(checker.types)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/// statements that compute a value only to throw it away with `;`,
+/// which is allowed as every statement is `()` whatever it discards
+pub fn unused_values(ast: &Ast, types: &Types) -> Vec<TypeError> {
+    let mut lint = UnusedValues {
+        types,
+        warnings: vec![],
+        item: String::new(),
+    };
+    lint.visit_ast(ast);
+    lint.warnings
+}
+
+struct UnusedValues<'a> {
+    types: &'a Types,
+    warnings: Vec<TypeError>,
+    item: String,
+}
+
+impl Visitor for UnusedValues<'_> {
+    fn visit_fn_def(&mut self, arena: &Arena<Expr>, f: &FnDef) {
+        self.item = f.name.clone();
+        walk_fn_def(self, arena, f);
+    }
+
+    fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        walk_expr(self, arena, id);
+        let Expr::Stmt(Stmt { expr }) = &arena[id] else {
+            return;
+        };
+        // calls and control flow are there for what they do
+        let pure = matches!(
+            arena[*expr],
+            Expr::BinOp(_)
+                | Expr::UnOp(_)
+                | Expr::Cast(_)
+                | Expr::Index(_)
+                | Expr::Field(_)
+                | Expr::Array(_)
+                | Expr::Tuple(_)
+                | Expr::Struct(_)
+                | Expr::Variant(_)
+                | Expr::Bool(_)
+                | Expr::Local(_)
+                | Expr::Number(_)
+                | Expr::Float(_)
+                | Expr::Char(_)
+                | Expr::Str(_)
+        );
+        let ty = &self.types[expr];
+        if pure && !matches!(ty, Type::Void | Type::Never | Type::Unknown) {
+            self.warnings.push(TypeError {
+                message: format!("unused value of type {:?}", ty),
+                item: self.item.clone(),
+                node: Some(id),
+            });
+        }
+    }
+}
+
+struct Checker {
+    types: Types,
+    errors: Vec<TypeError>,
+    /// extern fns by name, with whether they are variadic
+    externs: HashMap<String, bool>,
+    /// the fn or static being checked
+    item: String,
+    /// the expression being checked, which errors point at
+    node: Option<NodeId>,
+    ret: Type,
+    /// locals declared without a type,
+    /// with their `let` and the type of the first value they are given
+    inferred: BTreeMap<String, (NodeId, Option<Type>)>,
+    /// integer literals with the item they are in,
+    /// checked to fit their type once it can no longer change
+    literals: Vec<(NodeId, String)>,
+}
+
+impl Visitor for Checker {
+    fn visit_fn_def(&mut self, arena: &Arena<Expr>, f: &FnDef) {
+        let Type::Fn { ret, .. } = &f.fn_type else {
+            panic!("function's type must be Fn")
+        };
+        self.item = f.name.clone();
+        self.node = None;
+        self.ret = *ret.clone();
+        let tys: Vec<_> = f.args.iter().map(|x| x.ty.clone()).collect();
+        if !fit_in_registers(&tys) {
+            self.error(format!(
+                "`{}` has more parameters than fit in registers",
+                f.name
+            ));
+        }
+        walk_fn_def(self, arena, f);
+
+        // the tail value is returned like `return`, and as only what diverges is `!`,
+        // a tail of `()` means some path falls off the end without returning
+        let tail = f.body.exprs.last().copied();
+        self.node = tail;
+        let falls_off = tail.map_or(Type::Void, |x| self.ty(x).clone()) == Type::Void;
+        if falls_off && !matches!(self.ret, Type::Void | Type::Unknown) {
+            self.error(format!(
+                "`{}` does not return a value of type {:?} on every path",
+                f.name, self.ret
+            ));
+        } else if let Some(tail) = tail {
+            self.check_return(arena, tail);
+        }
+
+        for (name, (decl, ty)) in std::mem::take(&mut self.inferred) {
+            if ty.is_none() {
+                self.node = Some(decl);
+                self.error(format!("type annotations needed for `{}`", name));
+            }
+        }
+    }
+
+    fn visit_static(&mut self, arena: &Arena<Expr>, s: &Static) {
+        self.item = s.name.clone();
+        walk_static(self, arena, s);
+        self.node = Some(s.init);
+        self.expect_expr(arena, &s.ty, s.init, "mismatched types in `static`");
+        self.check_constant(arena, s.init);
+    }
+
+    fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        walk_expr(self, arena, id);
+        self.node = Some(id);
+
+        // a literal takes the integer type of the other side
+        if let Expr::BinOp(BinOp { op, lhs, rhs }) = &arena[id] {
+            if !matches!(op, BinOpKind::LogAnd | BinOpKind::LogOr) {
+                let (a, b) = (self.ty(*lhs).clone(), self.ty(*rhs).clone());
+                if a.is_integer() && a != Type::I64 {
+                    self.coerce(arena, *rhs, &a);
+                } else if b.is_integer() && b != Type::I64 {
+                    self.coerce(arena, *lhs, &b);
+                }
+            }
+        }
+
+        // errors in the children are not reported again in their parent
+        let unknown = Cell::new(false);
+        let ty_of = |id: &NodeId| {
+            let ty = self.types[id].clone();
+            if ty == Type::Unknown {
+                unknown.set(true);
+            }
+            ty
+        };
+        let ty = match arena[id].try_ty(&ty_of) {
+            Ok(Type::Unknown) => match &arena[id] {
+                Expr::Local(Local { name, .. }) => self.local(name),
+                _ => Type::Unknown,
+            },
+            Ok(ty) => ty,
+            Err(e) => {
+                if !unknown.get() {
+                    self.error(e);
+                }
+                Type::Unknown
+            }
+        };
+        self.types.insert(id, ty);
+        if let Expr::Number(_) = &arena[id] {
+            self.literals.push((id, self.item.clone()));
+        }
+        self.check_expr(arena, id);
+    }
+}
+
+impl Checker {
+    fn error(&mut self, message: String) {
+        self.errors.push(TypeError {
+            message,
+            item: self.item.clone(),
+            node: self.node,
+        });
+    }
+
+    fn error_at(&mut self, id: NodeId, message: String) {
+        self.errors.push(TypeError {
+            message,
+            item: self.item.clone(),
+            node: Some(id),
+        });
+    }
+
+    fn ty(&self, id: NodeId) -> &Type {
+        &self.types[&id]
+    }
+
+    /// statics are laid out at compile time, so every scalar in them must be constant
+    fn check_constant(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        match &arena[id] {
+            Expr::Array(Array { elements }) | Expr::Tuple(Tuple { elements }) => {
+                for x in elements.iter() {
+                    self.check_constant(arena, *x);
+                }
+            }
+            Expr::Struct(Struct { fields, .. }) => {
+                for (_, x) in fields.iter() {
+                    self.check_constant(arena, *x);
+                }
+            }
+            Expr::Variant(Variant { payload, .. }) => {
+                if let Some(x) = payload {
+                    self.check_constant(arena, *x);
+                }
+            }
+            Expr::Enclosed(Enclosed { expr }) => self.check_constant(arena, *expr),
+            // its range is checked against the type by `coerce`
+            Expr::Number(_) => {}
+            expr => {
+                let res = match self.ty(id) {
+                    Type::F64 => const_eval::eval_f64(arena, expr).map(drop),
+                    _ => const_eval::eval(arena, expr).map(drop),
+                };
+                if let Err(e) = res {
+                    self.error_at(id, e);
+                }
+            }
+        }
+    }
+
+    /// type inferred for the local `name` so far
+    fn local(&self, name: &str) -> Type {
+        self.inferred
+            .get(name)
+            .and_then(|(_, ty)| ty.clone())
+            .unwrap_or(Type::Unknown)
+    }
+
+    fn block_ty(&self, block: &Block) -> Type {
+        block
+            .exprs
+            .last()
+            .map_or(Type::Void, |x| self.ty(*x).clone())
+    }
+
+    /// report `message` unless `actual` is `expected`,
+    /// or is unknown due to an error reported already
+    /// `!` is taken as any type, as no value of it is ever made
+    fn expect(&mut self, expected: &Type, actual: &Type, message: &str) {
+        if actual != expected
+            && !matches!(actual, Type::Unknown | Type::Never)
+            && *expected != Type::Unknown
+        {
+            self.error(format!(
+                "{}: expected {:?}, found {:?}",
+                message, expected, actual
+            ));
+        }
+    }
+
+    /// like `expect`, letting an integer literal in `id` take the expected type
+    fn expect_expr(&mut self, arena: &Arena<Expr>, expected: &Type, id: NodeId, message: &str) {
+        self.coerce(arena, id, expected);
+        let actual = self.ty(id).clone();
+        let node = self.node.replace(id);
+        self.expect(expected, &actual, message);
+        self.node = node;
+    }
+
+    /// give the integer literals in `id` the type `ty`, if they fit in it,
+    /// looking into what the value of `id` is made of like the tails of blocks
+    fn coerce(&mut self, arena: &Arena<Expr>, id: NodeId, ty: &Type) {
+        if *self.ty(id) == *ty {
+            return;
+        }
+        match (&arena[id], ty) {
+            (Expr::Number(Number { value }), ty) if ty.is_integer() => {
+                let value = value.parse::<i128>().unwrap();
+                if !ty.fits(value) {
+                    self.error_at(id, format!("literal out of range for {}", ty));
+                    self.types.insert(id, Type::Unknown);
+                    return;
+                }
+                self.types.insert(id, ty.clone());
+                return;
+            }
+            (
+                Expr::UnOp(UnOp {
+                    kind: UnOpKind::Neg,
+                    expr,
+                }),
+                ty,
+            ) if ty.is_signed() => {
+                if let Expr::Number(Number { value }) = &arena[*expr] {
+                    let value = value.parse::<i128>().unwrap();
+                    if !ty.fits(-value) {
+                        self.error_at(id, format!("literal out of range for {}", ty));
+                        self.types.insert(id, Type::Unknown);
+                        return;
+                    }
+                    self.types.insert(*expr, ty.clone());
+                }
+            }
+            // a literal handed to C is just the address of its bytes
+            (Expr::Str(_), Type::Ptr { .. }) => {
+                self.types.insert(id, ty.clone());
+                return;
+            }
+            (Expr::Enclosed(Enclosed { expr }), ty) => self.coerce(arena, *expr, ty),
+            (Expr::Block(block), ty) => self.coerce_block(arena, block, ty),
+            (
+                Expr::IfElse(IfElse {
+                    if_body,
+                    else_body: Some(else_body),
+                    ..
+                }),
+                ty,
+            ) => {
+                self.coerce_block(arena, if_body, ty);
+                self.coerce_block(arena, else_body, ty);
+            }
+            (Expr::Match(Match { arms, .. }), ty) => {
+                for arm in arms.iter() {
+                    self.coerce(arena, arm.body, ty);
+                }
+            }
+            (Expr::Array(Array { elements }), Type::Array { element, .. }) => {
+                for x in elements.iter() {
+                    self.coerce(arena, *x, element);
+                }
+            }
+            (Expr::Tuple(Tuple { elements }), Type::Tuple { elements: tys }) => {
+                for (x, ty) in elements.iter().zip(tys) {
+                    self.coerce(arena, *x, ty);
+                }
+            }
+            _ => return,
+        }
+        if let Ok(ty) = arena[id].try_ty(&|x| self.types[x].clone()) {
+            self.types.insert(id, ty);
+        }
+    }
+
+    fn coerce_block(&mut self, arena: &Arena<Expr>, block: &Block, ty: &Type) {
+        if let Some(&tail) = block.exprs.last() {
+            self.coerce(arena, tail, ty);
+        }
+    }
+
+    fn check_return(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        self.coerce(arena, id, &self.ret.clone());
+        let node = self.node.replace(id);
+        self.check_return_ty(self.ty(id).clone());
+        self.node = node;
+    }
+
+    fn check_return_ty(&mut self, ty: Type) {
+        // functions without `->` default to void and accept any value for now
+        if self.ret != Type::Void && ty != Type::Never {
+            let ret = self.ret.clone();
+            self.expect(&ret, &ty, "mismatched return type");
+        }
+    }
+
+    /// what `try_ty` leaves to the parent, like conditions and arguments
+    fn check_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        match &arena[id] {
+            Expr::Return(Return { expr }) => self.check_return(arena, *expr),
+            Expr::While(While { cond, .. }) => {
+                self.expect_expr(
+                    arena,
+                    &Type::Bool,
+                    *cond,
+                    "condition of `while` must be bool",
+                );
+            }
+            Expr::IfElse(IfElse {
+                cond,
+                if_body,
+                else_body,
+            }) => {
+                self.expect_expr(arena, &Type::Bool, *cond, "condition of `if` must be bool");
+                if else_body.is_none()
+                    && !matches!(
+                        self.block_ty(if_body),
+                        Type::Void | Type::Never | Type::Unknown
+                    )
+                {
+                    self.error("`if` without `else` cannot evaluate to a value".to_string());
+                }
+            }
+            // a callee without a signature is already reported by `try_ty`
+            Expr::FnCall(FnCall {
+                name,
+                args,
+                fn_type: Type::Fn { args: params, .. },
+            }) => {
+                let variadic = self.externs.get(name).copied().unwrap_or(false);
+                self.check_args(arena, name, params, args, variadic);
+            }
+            Expr::Call(Call { callee, args }) => {
+                if let Type::Fn { args: params, .. } = self.ty(*callee).clone() {
+                    self.check_args(arena, "closure", &params, args, false);
+                }
+            }
+            Expr::Init(Init {
+                name: id, value, ..
+            }) => {
+                let Expr::Local(Local { name, ty }) = &arena[*id] else {
+                    panic!("lhs must be addressable")
+                };
+                match (ty, value) {
+                    (Type::Unknown, value) => {
+                        let value = value.map(|x| self.ty(x).clone());
+                        // shadows any local of the same name from here on
+                        self.inferred.insert(name.clone(), (*id, value.clone()));
+                        self.types.insert(*id, value.unwrap_or(Type::Unknown));
+                    }
+                    (ty, Some(value)) => {
+                        self.expect_expr(arena, ty, *value, "mismatched types in `let`")
+                    }
+                    (_, None) => {}
+                }
+            }
+            // stores through a pointer are not checked yet
+            Expr::Assign(Assign { lhs, rhs })
+                if matches!(
+                    &arena[*lhs],
+                    Expr::UnOp(UnOp {
+                        kind: UnOpKind::Deref,
+                        ..
+                    })
+                ) =>
+            {
+                self.coerce(arena, *rhs, &self.ty(*lhs).clone());
+            }
+            Expr::Assign(Assign { lhs, rhs }) => {
+                // `let x;` takes the type of what is first assigned to it
+                if let Expr::Local(Local {
+                    name,
+                    ty: Type::Unknown,
+                }) = &arena[*lhs]
+                {
+                    if let Some(&(decl, None)) = self.inferred.get(name) {
+                        let rhs = self.ty(*rhs).clone();
+                        self.inferred
+                            .insert(name.clone(), (decl, Some(rhs.clone())));
+                        for id in [decl, *lhs, id] {
+                            self.types.insert(id, rhs.clone());
+                        }
+                    }
+                }
+                let lhs = self.ty(*lhs).clone();
+                self.expect_expr(arena, &lhs, *rhs, "mismatched types in assignment");
+            }
+            Expr::Struct(Struct { ty, fields }) => {
+                for (name, value) in fields.iter() {
+                    // the fields are checked by the parser
+                    let (_, field) = ty.field(name).unwrap();
+                    let message = format!("mismatched type of field `{}`", name);
+                    self.expect_expr(arena, &field, *value, &message);
+                }
+            }
+            Expr::Variant(Variant {
+                ty,
+                index,
+                payload: Some(payload),
+            }) => {
+                let Type::Enum { variants, .. } = ty else {
+                    panic!("{:?} has no variants", ty)
+                };
+                if let (name, Some(expected)) = &variants[*index] {
+                    let message = format!("mismatched type of the payload of `{}`", name);
+                    self.expect_expr(arena, expected, *payload, &message);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_args(
+        &mut self,
+        arena: &Arena<Expr>,
+        name: &str,
+        params: &[Type],
+        args: &[NodeId],
+        variadic: bool,
+    ) {
+        if args.len() < params.len() || (args.len() > params.len() && !variadic) {
+            self.error(format!(
+                "`{}` takes {} argument(s) but {} were given",
+                name,
+                params.len(),
+                args.len()
+            ));
+            return;
+        }
+        for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+            let message = format!("mismatched type of argument {} to `{}`", i + 1, name);
+            self.expect_expr(arena, param, *arg, &message);
+        }
+        for arg in args[params.len()..].iter() {
+            let c_string = Type::Ptr {
+                to: Box::new(Type::U8),
+            };
+            self.coerce(arena, *arg, &c_string);
+        }
+
+        let tys: Vec<_> = args.iter().map(|x| self.ty(*x).clone()).collect();
+        if !fit_in_registers(&tys) {
+            self.error(format!(
+                "`{}` is called with more arguments than fit in registers",
+                name
+            ));
+        }
+    }
+}
+
+/// every argument is passed in a register, there are no arguments on the stack yet
+fn fit_in_registers(tys: &[Type]) -> bool {
+    let floats = tys.iter().filter(|x| **x == Type::F64).count();
+    floats <= FLOAT_ARG_REGS.len() && tys.len() - floats <= ARG_REGS.len()
+}
@@ -0,0 +1,193 @@
+use crate::ast::{
+    Arena, Arm, Array, Assign, Ast, BinOp, Block, Break, Call, Cast, Closure, Defer, Definition,
+    Enclosed, Expr, Field, FnCall, FnDef, For, Global, IfElse, Index, Init, Local, Loop, Match,
+    NodeId, Range, Return, Static, Stmt, Struct, Tuple, UnOp, Variant, While,
+};
+
+/// read-only traversal of the AST, every node is visited in evaluation order,
+/// and overriding a method without calling its `walk_*` skips the children,
+/// expressions are looked up in the `arena` passed down
+pub trait Visitor {
+    fn visit_ast(&mut self, ast: &Ast) {
+        walk_ast(self, ast)
+    }
+
+    fn visit_global(&mut self, arena: &Arena<Expr>, global: &Global) {
+        walk_global(self, arena, global)
+    }
+
+    fn visit_definition(&mut self, arena: &Arena<Expr>, definition: &Definition) {
+        walk_definition(self, arena, definition)
+    }
+
+    fn visit_fn_def(&mut self, arena: &Arena<Expr>, f: &FnDef) {
+        walk_fn_def(self, arena, f)
+    }
+
+    fn visit_static(&mut self, arena: &Arena<Expr>, s: &Static) {
+        walk_static(self, arena, s)
+    }
+
+    fn visit_block(&mut self, arena: &Arena<Expr>, block: &Block) {
+        walk_block(self, arena, block)
+    }
+
+    fn visit_expr(&mut self, arena: &Arena<Expr>, id: NodeId) {
+        walk_expr(self, arena, id)
+    }
+
+    fn visit_arm(&mut self, arena: &Arena<Expr>, arm: &Arm) {
+        walk_arm(self, arena, arm)
+    }
+
+    /// every local bound or used, including args and those bound by patterns
+    fn visit_local(&mut self, _local: &Local) {}
+}
+
+pub fn walk_ast<V: Visitor + ?Sized>(v: &mut V, ast: &Ast) {
+    v.visit_global(&ast.arena, &ast.node);
+}
+
+pub fn walk_global<V: Visitor + ?Sized>(v: &mut V, arena: &Arena<Expr>, global: &Global) {
+    for definition in global.definitions.iter() {
+        v.visit_definition(arena, definition);
+    }
+}
+
+pub fn walk_definition<V: Visitor + ?Sized>(
+    v: &mut V,
+    arena: &Arena<Expr>,
+    definition: &Definition,
+) {
+    match definition {
+        Definition::Fn(f) => v.visit_fn_def(arena, f),
+        Definition::Static(s) => v.visit_static(arena, s),
+        Definition::Extern(_) => {}
+    }
+}
+
+pub fn walk_fn_def<V: Visitor + ?Sized>(v: &mut V, arena: &Arena<Expr>, f: &FnDef) {
+    for arg in f.args.iter() {
+        v.visit_local(arg);
+    }
+    v.visit_block(arena, &f.body);
+}
+
+pub fn walk_static<V: Visitor + ?Sized>(v: &mut V, arena: &Arena<Expr>, s: &Static) {
+    v.visit_expr(arena, s.init);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(v: &mut V, arena: &Arena<Expr>, block: &Block) {
+    for expr in block.exprs.iter() {
+        v.visit_expr(arena, *expr);
+    }
+}
+
+pub fn walk_arm<V: Visitor + ?Sized>(v: &mut V, arena: &Arena<Expr>, arm: &Arm) {
+    if let Some(local) = arm.pat.binding() {
+        v.visit_local(local);
+    }
+    v.visit_expr(arena, arm.body);
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, arena: &Arena<Expr>, id: NodeId) {
+    match &arena[id] {
+        Expr::Stmt(Stmt { expr })
+        | Expr::Return(Return { expr })
+        | Expr::UnOp(UnOp { expr, .. })
+        | Expr::Enclosed(Enclosed { expr })
+        | Expr::Cast(Cast { expr, .. })
+        | Expr::Defer(Defer { expr })
+        | Expr::Field(Field { base: expr, .. }) => v.visit_expr(arena, *expr),
+        Expr::Block(block) | Expr::Loop(Loop { body: block, .. }) => v.visit_block(arena, block),
+        Expr::While(While { cond, body, .. }) => {
+            v.visit_expr(arena, *cond);
+            v.visit_block(arena, body);
+        }
+        Expr::For(For {
+            var,
+            range: Range { start, end },
+            body,
+            ..
+        }) => {
+            v.visit_expr(arena, *start);
+            v.visit_expr(arena, *end);
+            v.visit_local(var);
+            v.visit_block(arena, body);
+        }
+        Expr::Break(Break { expr, .. }) => {
+            if let Some(expr) = expr {
+                v.visit_expr(arena, *expr);
+            }
+        }
+        Expr::IfElse(IfElse {
+            cond,
+            if_body,
+            else_body,
+        }) => {
+            v.visit_expr(arena, *cond);
+            v.visit_block(arena, if_body);
+            if let Some(else_body) = else_body {
+                v.visit_block(arena, else_body);
+            }
+        }
+        Expr::Match(Match { scrutinee, arms }) => {
+            v.visit_expr(arena, *scrutinee);
+            for arm in arms.iter() {
+                v.visit_arm(arena, arm);
+            }
+        }
+        Expr::FnCall(FnCall { args, .. }) => {
+            for arg in args.iter() {
+                v.visit_expr(arena, *arg);
+            }
+        }
+        Expr::Call(Call { callee, args }) => {
+            v.visit_expr(arena, *callee);
+            for arg in args.iter() {
+                v.visit_expr(arena, *arg);
+            }
+        }
+        Expr::Closure(Closure { captures, .. }) => {
+            for capture in captures.iter() {
+                v.visit_expr(arena, *capture);
+            }
+        }
+        Expr::Init(Init { name, value, .. }) => {
+            if let Some(value) = value {
+                v.visit_expr(arena, *value);
+            }
+            v.visit_expr(arena, *name);
+        }
+        Expr::Assign(Assign { lhs, rhs }) | Expr::BinOp(BinOp { lhs, rhs, .. }) => {
+            v.visit_expr(arena, *lhs);
+            v.visit_expr(arena, *rhs);
+        }
+        Expr::Index(Index { base, index }) => {
+            v.visit_expr(arena, *base);
+            v.visit_expr(arena, *index);
+        }
+        Expr::Array(Array { elements }) | Expr::Tuple(Tuple { elements }) => {
+            for element in elements.iter() {
+                v.visit_expr(arena, *element);
+            }
+        }
+        Expr::Struct(Struct { fields, .. }) => {
+            for (_, value) in fields.iter() {
+                v.visit_expr(arena, *value);
+            }
+        }
+        Expr::Variant(Variant { payload, .. }) => {
+            if let Some(payload) = payload {
+                v.visit_expr(arena, *payload);
+            }
+        }
+        Expr::Local(local) => v.visit_local(local),
+        Expr::Continue(_)
+        | Expr::Bool(_)
+        | Expr::Number(_)
+        | Expr::Float(_)
+        | Expr::Char(_)
+        | Expr::Str(_) => {}
+    }
+}
@@ -1,4 +1,6 @@
-use tools::assert_exit_code;
+use tools::{
+    assert_compile_error, assert_compile_warning, assert_exit_code, assert_exit_code_files,
+};
 
 mod tools;
 
@@ -101,7 +103,7 @@ fn fib_array() {
         a[0] = 0;
         a[1] = 1;
 
-        let i = 2;
+        let mut i = 2;
         loop {
             if i == len {
                 return a[len - 1];
@@ -113,6 +115,7 @@ fn fib_array() {
     }
     ";
 
+    #[allow(clippy::useless_vec)]
     let mut fib = vec![0; 10];
     fib[1] = 1;
     for i in 2..10 {
@@ -145,3 +148,2293 @@ fn short() {
 
     assert_exit_code(s, 1);
 }
+
+#[test]
+fn return_type_annotation() {
+    let s = r"
+    fn add(a: i64, b: i64) -> i64 {
+        return a + b;
+    }
+
+    fn main() -> i64 {
+        return add(40, 2);
+    }
+    ";
+
+    assert_exit_code(s, 42);
+
+    let s = r"
+    fn main() -> i64 {
+        return true;
+    }
+    ";
+
+    assert_compile_error(s, "mismatched return type: expected I64, found Bool");
+}
+
+#[test]
+fn unknown_character() {
+    let s = r"
+    fn main() -> i64 {
+        return 0；
+    }
+    ";
+
+    assert_compile_error(s, "did you mean `;`?");
+}
+
+#[test]
+fn negative_literal() {
+    let s = r"
+    fn main() -> i64 {
+        let min = -9223372036854775808;
+        let a = min + 9223372036854775807;
+        let b = -5 * 2;
+        return b + 13 + a;
+    }
+    ";
+
+    assert_exit_code(s, 2);
+}
+
+#[test]
+fn negative_literal_const() {
+    let s = r"
+    const MIN: i64 = -9223372036854775808;
+    static DELTA: [i64; 2] = [-4, -1];
+
+    fn main() -> i64 {
+        if -9223372036854775808 != MIN {
+            return 100;
+        }
+        return 7 + DELTA[0] - DELTA[1] * 2;
+    }
+    ";
+
+    assert_exit_code(s, 5);
+}
+
+#[test]
+fn literal_out_of_range() {
+    let s = r"
+    fn main() -> i64 {
+        return 99999999999999999999;
+    }
+    ";
+
+    assert_compile_error(s, "integer literal is too large");
+
+    // only negated is the magnitude of i64::MIN in range
+    let s = r"
+    fn main() -> i64 {
+        return 9223372036854775808;
+    }
+    ";
+
+    assert_compile_error(s, "literal out of range for i64");
+}
+
+#[test]
+fn u64_literals() {
+    let s = r"
+    static S: u64 = 18446744073709551615;
+    const C: u64 = 18446744073709551614;
+    fn main() -> i64 {
+        let a: u64 = 18446744073709551615;
+        let c: u64 = C;
+        let mut n = 0;
+        if a == S {
+            n = n + 1;
+        }
+        if a - c == 1 {
+            n = n + 2;
+        }
+        match a {
+            18446744073709551615 => n + 4,
+            _ => n,
+        }
+    }
+    ";
+
+    assert_exit_code(s, 7);
+
+    let s = r"
+    fn main() -> i64 {
+        let a = 9223372036854775808;
+        0
+    }
+    ";
+
+    assert_compile_error(s, "literal out of range for i64");
+
+    let s = r"
+    const C: u8 = 256;
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "literal out of range for u8");
+}
+
+#[test]
+fn operand_locations() {
+    let s = r"
+    fn sub(a: i64, b: i64) -> i64 {
+        return a - b;
+    }
+
+    fn main() -> i64 {
+        let x = 10;
+        let y = sub(sub(x, 1), sub(5, 3));
+        if y >= 7 {
+            return y / 2;
+        } else {
+            return 0;
+        }
+    }
+    ";
+
+    assert_exit_code(s, 3);
+}
+
+#[test]
+fn block_comment() {
+    let s = r"
+    /* outer /* nested */ still a comment */
+    fn main() -> i64 {
+        let a = 1 /* inline */ + 2;
+        /*
+        a = 0;
+        */
+        return a;
+    }
+    ";
+
+    assert_exit_code(s, 3);
+}
+
+#[test]
+fn unterminated_block_comment() {
+    let s = r"
+    fn main() -> i64 {
+        return 0;
+    }
+    /* /* */
+    ";
+
+    assert_compile_error(s, "unterminated block comment");
+}
+
+#[test]
+fn radix_literals() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 0x1F;
+        let b = 0o17;
+        let c = 0b101;
+        return a + b + c;
+    }
+    ";
+
+    assert_exit_code(s, 0x1F + 0o17 + 0b101);
+}
+
+#[test]
+fn numeric_separators() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 1_000_000;
+        let b = 0b_1010_1010;
+        let c = a - 999_900;
+        return c + b;
+    }
+    ";
+
+    assert_exit_code(s, (100 + 0b1010_1010) % 256);
+}
+
+#[test]
+fn unknown_escape() {
+    let s = r#"
+    fn main() -> i64 {
+        let s = "tab\t quote\" \q";
+        return 0;
+    }
+    "#;
+
+    assert_compile_error(s, r"unknown character escape `q`");
+}
+
+#[test]
+fn str_type() {
+    let s = r#"
+    impl str {
+        fn count(self, c: char) -> i64 {
+            let mut n = 0;
+            let mut i = 0;
+            while i < self.len() {
+                if self[i] == c {
+                    n = n + 1;
+                }
+                i = i + 1;
+            }
+            n
+        }
+    }
+
+    fn first(s: str) -> char {
+        s[0]
+    }
+
+    fn main() -> i64 {
+        let s = "hello, world\n";
+        let t: str = "sofa";
+        if first(t) != 's' {
+            return 1;
+        }
+        // 13 + 2 + 4
+        s.len() + s.count('o') + t.len
+    }
+    "#;
+
+    assert_exit_code(s, 19);
+}
+
+#[test]
+fn char_type() {
+    let s = r"
+    fn digit(c: char) -> i64 {
+        if c >= '0' && c <= '9' {
+            return c as i64 - '0' as i64;
+        }
+        return match c {
+            'x' => -2,
+            _ => -1,
+        };
+    }
+
+    fn main() -> i64 {
+        let src: [char; 5] = ['4', '2', ' ', 'x', '7'];
+        let mut sum = 0;
+        let mut i = 0;
+        while i < 5 {
+            let d = digit(src[i]);
+            if d >= 0 {
+                sum = sum * 10 + d;
+            }
+            i = i + 1;
+        }
+        let up = ('a' as i64 - 32) as char;
+        if up == 'A' {
+            return sum - 400;
+        }
+        return 0;
+    }
+    ";
+
+    assert_exit_code(s, 27);
+}
+
+#[test]
+fn char_literal() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 'a';
+        let nl = '\n';
+        return a as i64 + nl as i64;
+    }
+    ";
+
+    assert_exit_code(s, 'a' as i32 + '\n' as i32);
+}
+
+#[test]
+fn two_char_operators() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 5;
+        if (a != 4) && (a >= 5) && (a <= 5) {
+            return a;
+        }
+        return 0;
+    }
+    ";
+
+    assert_exit_code(s, 5);
+}
+
+#[test]
+fn doc_comment() {
+    let s = r"
+    /// adds one
+    ///
+    /// really
+    fn inc(a: i64) -> i64 {
+        /// not attached to anything yet
+        return a + 1;
+    }
+
+    //// just a comment
+    fn main() -> i64 {
+        return inc(1);
+    }
+    ";
+
+    assert_exit_code(s, 2);
+}
+
+#[test]
+fn shebang() {
+    let s = "#!/usr/bin/env sofa
+    fn main() -> i64 {
+        return 7;
+    }
+    ";
+
+    assert_exit_code(s, 7);
+}
+
+#[test]
+fn contextual_keyword() {
+    let s = r"
+    fn main() -> i64 {
+        let in = 4;
+        return in + 1;
+    }
+    ";
+
+    assert_exit_code(s, 5);
+}
+
+#[test]
+fn while_loop() {
+    let s = r"
+    fn main() -> i64 {
+        let mut i = 0;
+        let mut sum = 0;
+        while i < 10 {
+            i = i + 1;
+            sum = sum + i;
+        }
+        return sum;
+    }
+    ";
+
+    assert_exit_code(s, 55);
+}
+
+#[test]
+fn for_range() {
+    let s = r"
+    fn main() -> i64 {
+        let n = 5;
+        let mut sum = 0;
+        for i in 0..n + 1 {
+            sum = sum + i;
+        }
+        for i in 3..3 {
+            sum = sum + 100;
+        }
+        return sum;
+    }
+    ";
+
+    assert_exit_code(s, 15);
+}
+
+#[test]
+fn break_continue() {
+    let s = r"
+    fn main() -> i64 {
+        let mut sum = 0;
+        for i in 0..10 {
+            if i == 3 {
+                continue;
+            }
+            let mut j = 0;
+            loop {
+                if j == i {
+                    break;
+                }
+                j = j + 1;
+                sum = sum + 1;
+            }
+        }
+        let mut k = 0;
+        while true {
+            k = k + 1;
+            if k == 5 {
+                break;
+            }
+        }
+        return sum + k;
+    }
+    ";
+
+    // 0 + 1 + 2 + 4 + 5 + 6 + 7 + 8 + 9 = 42
+    assert_exit_code(s, 47);
+}
+
+#[test]
+fn break_value() {
+    let s = r"
+    fn main() -> i64 {
+        let mut i = 1;
+        let x = loop {
+            i = i * 2;
+            if i > 20 {
+                break i + 1;
+            }
+        };
+        return x;
+    }
+    ";
+
+    assert_exit_code(s, 33);
+}
+
+#[test]
+fn precedence() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 10 - 3 - 2 + 2 * 3 % 4;
+        let b = -a * 2 + 20;
+        if a == 7 && b == 6 || false && 1 / 0 == 0 {
+            return a + b * 2;
+        }
+        return 1;
+    }
+    ";
+
+    assert_exit_code(s, 19);
+}
+
+#[test]
+fn chained_comparison() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 1;
+        let b = a = 2 < 3 == true;
+        0
+    }
+    ";
+
+    assert_compile_error(s, "comparison operators cannot be chained");
+}
+
+#[test]
+fn bitwise() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 12 & 10 | 1 ^ 3;
+        let b = 6 ^ 3 & 1;
+        if true & (a == 10) | false ^ false {
+            return a + b;
+        }
+        return 0;
+    }
+    ";
+
+    // 8 | 2 = 10, 6 ^ 1 = 7
+    assert_exit_code(s, 17);
+}
+
+#[test]
+fn shift() {
+    let s = r"
+    fn main() -> i64 {
+        let n = 3;
+        let a = 1 << n + 1;
+        let b = -64 >> 2;
+        let c = a >> n;
+        return a + b + (c << 2);
+    }
+    ";
+
+    // 16 - 16 + 8
+    assert_exit_code(s, 8);
+}
+
+#[test]
+fn remainder() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 47;
+        let b = -7 % 3;
+        return a % 10 * 3 + b + 100 % a;
+    }
+    ";
+
+    // 21 - 1 + 6, the sign follows the dividend
+    assert_exit_code(s, 26);
+}
+
+#[test]
+fn compound_assign() {
+    let s = r"
+    fn main() -> i64 {
+        let mut a = 5;
+        a += 3;
+        a *= 4;
+        a -= 2;
+        a /= 3;
+        a %= 7;
+        a <<= 3;
+        a >>= 1;
+        a |= 1;
+        a ^= 3;
+        a &= 14;
+        let p = &a;
+        *p += 1;
+        return a;
+    }
+    ";
+
+    // 8, 32, 30, 10, 3, 24, 12, 13, 14, 14, 15
+    assert_exit_code(s, 15);
+}
+
+#[test]
+fn logical_not() {
+    let s = r"
+    fn main() -> i64 {
+        let mut n = 0;
+        while !(n == 5) {
+            n += 1;
+        }
+        if !!true && !false {
+            return n;
+        }
+        return 0;
+    }
+    ";
+
+    assert_exit_code(s, 5);
+}
+
+#[test]
+fn bitwise_not() {
+    let s = r"
+    fn main() -> i64 {
+        let a = ~0;
+        let b = ~a + 7;
+        return ~-8 + b & ~(1 << 1);
+    }
+    ";
+
+    // 7 + 7 = 14, with bit 1 cleared
+    assert_exit_code(s, 12);
+}
+
+#[test]
+fn if_else_value() {
+    let s = r"
+    fn sign(a: i64) -> i64 {
+        let s = if a < 0 { -1 } else { if a == 0 { 0 } else { 1 } };
+        return s;
+    }
+
+    fn main() -> i64 {
+        let mut x = if sign(-5) == -1 { 20 } else { return 1; };
+        if x == 20 {
+            x = x + sign(0) + sign(3);
+        }
+        return x;
+    }
+    ";
+
+    assert_exit_code(s, 21);
+}
+
+#[test]
+fn block_value() {
+    let s = r"
+    fn double(a: i64) -> i64 {
+        a * 2
+    }
+
+    fn main() -> i64 {
+        let mut a = {
+            let b = 3;
+            { b + 1 }
+        };
+        {
+            a = a + 1;
+        };
+        double(a) + 4
+    }
+    ";
+
+    assert_exit_code(s, 14);
+}
+
+#[test]
+fn forward_call() {
+    let s = r"
+    fn main() -> i64 {
+        g(1)
+    }
+
+    fn g(a: i64) -> i64 {
+        a
+    }
+    ";
+
+    assert_exit_code(s, 1);
+}
+
+#[test]
+fn array_literal() {
+    let s = r"
+    fn sum(p: &i64, n: i64) -> i64 {
+        let mut res = 0;
+        for i in 0..n {
+            res += p[i];
+        }
+        res
+    }
+
+    fn main() -> i64 {
+        let big: [i64; 512];
+        big[511] = 2;
+        let mut a = [1, 2, 3];
+        let mut m = [[4, 5], [6, 7]];
+        a[1] *= 10;
+        m[0] = [a[0], big[511]];
+        a = [a[2], a[1], 0];
+        sum(&a[0], 3) + m[0][1] * m[1][0] + [8, 9][1]
+    }
+    ";
+
+    // 3 + 20 + 2 * 6 + 9
+    assert_exit_code(s, 44);
+}
+
+#[test]
+fn struct_literal() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    struct Line {
+        from: Point,
+        to: Point,
+    }
+
+    fn main() -> i64 {
+        let p = Point { y: 2, x: 1 };
+        let mut l = Line { from: p, to: Point { x: 10, y: 20 } };
+        l.from.x += 4;
+        let q = &l.to;
+        (*q).y = 30;
+        l.from = Point { x: l.from.x, y: p.y * 3 };
+        l.from.x * l.from.y + l.to.y + p.x
+    }
+    ";
+
+    assert_exit_code(s, 61);
+}
+
+#[test]
+fn c_like_enum() {
+    let s = r"
+    enum Color {
+        Red,
+        Green,
+        Blue,
+    }
+
+    fn next(c: Color) -> Color {
+        if c == Color::Red {
+            Color::Green
+        } else {
+            if c == Color::Green { Color::Blue } else { Color::Red }
+        }
+    }
+
+    fn main() -> i64 {
+        let mut c: Color = Color::Red;
+        let mut n = 0;
+        while n == 0 || c != Color::Red {
+            c = next(c);
+            n += 1;
+        }
+        n
+    }
+    ";
+
+    assert_exit_code(s, 3);
+}
+
+#[test]
+fn payload_enum() {
+    let s = r"
+    struct Pair {
+        a: i64,
+        b: i64,
+    }
+
+    enum Shape {
+        Square(i64),
+        Rect(Pair),
+        Empty,
+    }
+
+    enum Opt {
+        Some(i64),
+        None,
+    }
+
+    fn main() -> i64 {
+        let shapes = [
+            Shape::Square(3),
+            Shape::Rect(Pair { a: 2, b: 5 }),
+            Shape::Empty,
+        ];
+        let mut total = 0;
+        for i in 0..3 {
+            total += match shapes[i] {
+                Shape::Square(n) => n * n,
+                Shape::Rect(p) => p.a * p.b,
+                Shape::Empty => 0,
+            };
+        }
+        let x = Opt::None;
+        let y = match x {
+            Opt::Some(v) => v,
+            _ => 1,
+        };
+        total + y
+    }
+    ";
+
+    assert_exit_code(s, 20);
+}
+
+#[test]
+fn match_patterns() {
+    let s = r"
+    fn classify(n: i64) -> i64 {
+        match n {
+            0 => 1,
+            -1 => 2,
+            9_000_000_000 => 3,
+            other => other * 10,
+        }
+    }
+
+    fn main() -> i64 {
+        let flag = match classify(5) == 50 {
+            true => 7,
+            false => 0,
+        };
+        flag + classify(0) + classify(-1) + classify(9_000_000_000) + match 4 { _ => 100 }
+    }
+    ";
+
+    assert_exit_code(s, 113);
+}
+
+#[test]
+fn tuple() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    fn div_rem(a: i64, b: i64) -> (i64, i64) {
+        (a / b, a % b)
+    }
+
+    fn flip(p: &Point) -> Point {
+        return Point { x: (*p).y, y: (*p).x };
+    }
+
+    fn main() -> i64 {
+        let t: ((i64, bool), i64) = ((3, true), 4);
+        let q = div_rem(17, 5);
+        let p = flip(&Point { x: 1, y: 2 });
+        if t.0.1 {
+            t.0.0 * t.1 + q.0 * 10 + q.1 + p.x * 100
+        } else {
+            0
+        }
+    }
+    ";
+
+    // 12 + 30 + 2 + 200 = 244
+    assert_exit_code(s, 244);
+}
+
+#[test]
+fn type_annotations() {
+    let s = r"
+    fn nothing() -> () {}
+
+    fn never() -> ! {
+        loop {}
+    }
+
+    fn main() -> i64 {
+        let a = 5;
+        let p: &i64 = &a;
+        let pp: &&i64 = &p;
+        let arr: [(i64, bool); 2] = [(1, true), (2, false)];
+        let one: (i64,) = (3,);
+        let paren: (i64) = 4;
+        let f: fn(i64, &i64) -> (i64, i64);
+        nothing();
+        **pp + arr[1].0 + one.0 + paren
+    }
+    ";
+
+    assert_exit_code(s, 14);
+}
+
+#[test]
+fn return_type() {
+    let s = r"
+    fn add(a: i64, b: i64) -> i64 {
+        return a + b;
+    }
+
+    fn early(a: &i64) {
+        *a = *a + 1;
+        if *a > 10 {
+            return;
+        }
+    }
+
+    fn unit() -> () {
+        return
+    }
+
+    fn main() -> i64 {
+        let a = 10;
+        early(&a);
+        early(&a);
+        unit();
+        add(a, 1)
+    }
+    ";
+
+    assert_exit_code(s, 13);
+}
+
+#[test]
+fn statics() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    enum Shape {
+        Dot,
+        Square(i64),
+    }
+
+    static COUNT: i64 = 0;
+    static mut STEP: i64 = 3;
+    static ORIGIN: Point = Point { y: 2, x: -1 };
+    static TABLE: [i64; 3] = [10, 20, 30];
+    static SHAPE: Shape = Shape::Square(4);
+    static FLAG: bool = true;
+
+    fn bump() {
+        COUNT = COUNT + STEP;
+    }
+
+    fn main() -> i64 {
+        bump();
+        bump();
+        let p = &COUNT;
+        *p = *p + 1;
+        let side = match SHAPE {
+            Shape::Dot => 0,
+            Shape::Square(n) => n,
+        };
+        if FLAG {
+            COUNT + ORIGIN.x + ORIGIN.y + TABLE[2] + side
+        } else {
+            0
+        }
+    }
+    ";
+
+    assert_exit_code(s, 42);
+}
+
+#[test]
+fn consts() {
+    let s = r"
+    const N: i64 = 3 * 8;
+    const HALF: i64 = N / 2 - (1 << 2);
+    const BIG: bool = N > 20 && !false;
+
+    static TOTAL: i64 = N + HALF;
+
+    fn main() -> i64 {
+        let arr: [i64; N / 8] = [1, 2, N];
+        let table: [bool; HALF] = [true, true, true, true, true, true, true, true];
+        if BIG && table[7] {
+            arr[2] + HALF + TOTAL - N
+        } else {
+            0
+        }
+    }
+    ";
+
+    assert_exit_code(s, 40);
+}
+
+#[test]
+fn modules() {
+    let main = r"
+    mod geometry;
+    mod util;
+
+    use geometry::Point;
+    use util::double;
+
+    fn main() -> i64 {
+        let p = Point { x: 3, y: 4 };
+        let shape = geometry::shapes::Shape::Square(5);
+        let area = match shape {
+            geometry::shapes::Shape::Dot => 0,
+            geometry::shapes::Shape::Square(n) => n * n,
+        };
+        double(geometry::manhattan(&p)) + area + util::LIMIT + util::COUNT
+    }
+    ";
+    let geometry = r"
+    pub mod shapes;
+
+    pub struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    fn add(a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    pub fn manhattan(p: &Point) -> i64 {
+        // resolved within the module first
+        add((*p).x, (*p).y)
+    }
+    ";
+    let shapes = r"
+    pub enum Shape {
+        Dot,
+        Square(i64),
+    }
+    ";
+    let util = r"
+    pub const LIMIT: i64 = 2;
+    pub static COUNT: i64 = 1;
+
+    pub fn double(x: i64) -> i64 {
+        x * LIMIT
+    }
+    ";
+
+    assert_exit_code_files(
+        &[
+            ("main.sofa", main),
+            ("geometry.sofa", geometry),
+            ("geometry/shapes.sofa", shapes),
+            ("util.sofa", util),
+        ],
+        42,
+    );
+}
+
+#[test]
+fn parse_error() {
+    let s = r"
+    fn main() -> i64 {
+        let a = (1 + 2;
+        a
+    }
+    ";
+
+    assert_compile_error(s, "expected `)`, found `;`");
+}
+
+#[test]
+fn parse_error_recovery() {
+    let s = r"
+    fn first() -> i64 {
+        let a = (1 + 2;
+        if true {
+            let b = 3 +;
+        }
+        0
+    }
+
+    fn (x: i64) -> i64 {
+        x
+    }
+
+    fn main() -> i64 {
+        let c = [1, 2;
+        0
+    ";
+
+    assert_compile_error(s, "aborting due to 5 previous error(s)");
+}
+
+#[test]
+fn else_if() {
+    let s = r"
+    fn classify(n: i64) -> i64 {
+        if n < 0 {
+            1
+        } else if n == 0 {
+            2
+        } else if n < 10 {
+            3
+        } else {
+            4
+        }
+    }
+
+    fn main() -> i64 {
+        let mut a = 0;
+        if a == 1 {
+            a = 100;
+        } else if a == 0 {
+            a = 10;
+        }
+        a + classify(-5) + classify(0) * 2 + classify(5) * 4 + classify(50) * 8
+    }
+    ";
+
+    // 10 + 1 + 4 + 12 + 32
+    assert_exit_code(s, 59);
+}
+
+#[test]
+fn cast() {
+    let s = r"
+    enum Color {
+        Red,
+        Green,
+        Blue,
+    }
+
+    const ONE: i64 = true as i64;
+
+    fn main() -> i64 {
+        let a = 5;
+        let p = &a;
+        let addr = p as i64;
+        let q = (addr + 8 - 8) as &i64;
+        let flag = a > 3;
+        *q + flag as i64 * 10 + Color::Blue as i64 * 100 + -ONE as i64
+    }
+    ";
+
+    assert_exit_code(s, 214);
+}
+
+#[test]
+fn invalid_cast() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 1 as bool;
+        0
+    }
+    ";
+
+    assert_compile_error(s, "cannot cast I64 as Bool");
+}
+
+#[test]
+fn type_errors() {
+    let s = r"
+    fn f(a: bool) -> i64 {
+        if a { 1 } else { 2 }
+    }
+    fn main() -> i64 {
+        if 1 { return 0; }
+        f(1) + true
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "condition of `if` must be bool: expected Bool, found I64",
+    );
+    assert_compile_error(s, "mismatched type of argument 1 to `f`");
+    assert_compile_error(s, "aborting due to 3 previous error(s)");
+}
+
+#[test]
+fn let_inference() {
+    let s = r"
+    fn main() -> i64 {
+        let x;
+        let y = 2;
+        x = y * 3;
+        let z = x + 1;
+        z
+    }
+    ";
+
+    assert_exit_code(s, 7);
+
+    let s = r"
+    fn main() -> i64 {
+        let x;
+        0
+    }
+    ";
+
+    assert_compile_error(s, "type annotations needed for `x`");
+}
+
+#[test]
+fn sized_integers() {
+    let s = r"
+    struct Pixel {
+        r: u8,
+        g: u8,
+        b: u8,
+    }
+
+    static TABLE: [i16; 3] = [-1, 2, -300];
+
+    fn inc(x: u8) -> u8 {
+        x + 1
+    }
+
+    fn main() -> i64 {
+        let a: u8 = 250;
+        let wrapped = a + 10;
+        let c: i8 = -128;
+        let big: u32 = 4000000000;
+        let p = Pixel { r: 255, g: 1, b: 2 };
+        let mut buf: [u8; 4] = [1, 2, 3, 4];
+        buf[1] = 200;
+
+        if inc(255) == 0 && c - 1 == 127 && big / 3 == 1333333333 && -1 as u64 > 1 {
+            (wrapped as i64) + (p.r as i64) + (buf[1] as i64) + (TABLE[2] as i64)
+                + size_of::<Pixel>() + size_of::<[u8; 4]>()
+        } else {
+            0
+        }
+    }
+    ";
+
+    // 4 + 255 + 200 - 300 + 3 + 4
+    assert_exit_code(s, 166);
+
+    let s = r"
+    fn main() -> i64 {
+        let x: u8 = 256;
+        0
+    }
+    ";
+
+    assert_compile_error(s, "literal out of range for u8");
+}
+
+#[test]
+fn all_paths_return() {
+    let s = r"
+    fn sign(x: i64) -> i64 {
+        if x < 0 {
+            return -1;
+        } else if x == 0 {
+            return 0;
+        }
+        1
+    }
+
+    fn find(x: i64) -> i64 {
+        let mut i = 0;
+        loop {
+            if i * i >= x {
+                return i;
+            }
+            i += 1;
+        }
+    }
+
+    fn main() -> i64 {
+        match sign(-3) {
+            -1 => return find(50) + 34,
+            _ => return 0,
+        }
+    }
+    ";
+
+    assert_exit_code(s, 42);
+
+    let s = r"
+    fn first_positive(xs: [i64; 3]) -> i64 {
+        for i in 0..3 {
+            if xs[i] > 0 {
+                return xs[i];
+            }
+        }
+    }
+
+    fn main() -> i64 {
+        first_positive([0, 2, 3])
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "`first_positive` does not return a value of type I64 on every path",
+    );
+}
+
+#[test]
+fn call_arity() {
+    let s = r"
+    fn add(a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    fn main() -> i64 {
+        add(1, 2, 3)
+    }
+    ";
+
+    assert_compile_error(s, "`add` takes 2 argument(s) but 3 were given");
+
+    let s = r#"
+    extern "C" fn printf(format: &u8, ...) -> i32;
+
+    fn main() -> i64 {
+        printf("%d %d %d %d %d %d", 1, 2, 3, 4, 5, 6);
+        0
+    }
+    "#;
+
+    assert_compile_error(
+        s,
+        "`printf` is called with more arguments than fit in registers",
+    );
+
+    let s = r"
+    fn seven(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64) -> i64 {
+        g
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "`seven` has more parameters than fit in registers");
+}
+
+#[test]
+fn let_mut() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    fn bump(p: &Point, n: i64) -> i64 {
+        p.x += 1;
+        n = n + 1;
+        n
+    }
+
+    fn main() -> i64 {
+        let mut p = Point { x: 1, y: 2 };
+        p.y = 10;
+        let q = &p;
+        let n = bump(q, 20);
+        let (mut a, b) = (5, 6);
+        a *= b;
+        let later;
+        later = 3;
+        p.x + p.y + n + a - later - 18
+    }
+    ";
+
+    assert_exit_code(s, 42);
+
+    let s = r"
+    fn main() -> i64 {
+        let xs = [1, 2, 3];
+        xs[0] = 4;
+        xs[0]
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "cannot assign to immutable variable `xs`, declare it with `let mut`",
+    );
+}
+
+#[test]
+fn block_scopes() {
+    let s = r"
+    fn main() -> i64 {
+        let x = 1;
+        let mut sum = 0;
+        {
+            let x = 10;
+            let y = 100;
+            sum += x + y;
+        }
+        {
+            let y = 20;
+            let x = y + x;
+            sum += x;
+        }
+        for i in 0..3 {
+            let t = i * 2;
+            sum += t;
+        }
+        sum + x - 96
+    }
+    ";
+
+    assert_exit_code(s, 42);
+}
+
+#[test]
+fn name_resolution() {
+    let s = r"
+    fn main() -> i64 {
+        {
+            let inner = 1;
+        }
+        inner
+    }
+    ";
+
+    assert_compile_error(s, "undefined variable `inner`");
+
+    let s = r"
+    fn main() -> i64 {
+        y
+    }
+    ";
+
+    assert_compile_error(s, "undefined variable `y`");
+
+    let s = r"
+    fn main() -> i64 {
+        missing(1)
+    }
+    ";
+
+    assert_compile_error(s, "undefined function `missing`");
+}
+
+#[test]
+fn unused_value() {
+    let s = r"
+    fn main() -> i64 {
+        let x = 1;
+        x + 1;
+        x
+    }
+    ";
+
+    assert_exit_code(s, 1);
+    assert_compile_warning(s, "warning: unused value of type I64");
+}
+
+#[test]
+fn never_type() {
+    let s = r"
+    fn first_even(xs: [i64; 4]) -> i64 {
+        let mut i = 0;
+        let found: i64 = loop {
+            if i == 4 {
+                return -1;
+            }
+            if xs[i] % 2 == 0 {
+                break xs[i];
+            }
+            i += 1;
+        };
+        let doubled = if found > 0 { found * 2 } else { return 0; };
+        let checked: i64 = match doubled {
+            0 => return 0,
+            n => n,
+        };
+        checked
+    }
+
+    fn main() -> i64 {
+        let a: i64 = first_even([1, 3, 20, 5]);
+        let b = first_even([3, 21, 7, 9]);
+        a + b + 3
+    }
+    ";
+
+    assert_exit_code(s, 42);
+}
+
+#[test]
+fn type_alias() {
+    let s = r"
+    type Byte = u8;
+    type Bytes = &Byte;
+    type Callback = fn(Bytes, i64) -> Byte;
+
+    fn at(p: Bytes, i: i64) -> Byte {
+        *((p as i64 + i) as Bytes)
+    }
+
+    fn main() -> i64 {
+        let buf: [Byte; 3] = [1, 20, 21];
+        let f: Callback = |p: Bytes, i: i64| -> Byte { at(p, i) };
+        let type = 1;
+        (f(&buf[0], 1) + f(&buf[0], 2)) as i64 + type
+    }
+    ";
+
+    assert_exit_code(s, 42);
+}
+
+#[test]
+fn aligned_captures() {
+    let s = r"
+    fn main() -> i64 {
+        let small: u8 = 2;
+        let big: i64 = 40;
+        let half: i32 = -10;
+        let f = |x: i64| small as i64 + big + half as i64 + x;
+        f(10)
+    }
+    ";
+
+    assert_exit_code(s, 42);
+}
+
+#[test]
+fn zero_sized() {
+    let s = r"
+    fn nothing() {}
+
+    fn main() -> i64 {
+        let unit = nothing();
+        size_of::<()>() + size_of::<!>() + size_of::<(i64, ())>() + size_of::<fn(i64) -> i64>()
+    }
+    ";
+
+    assert_exit_code(s, 16);
+}
+
+#[test]
+fn struct_padding() {
+    let s = r"
+    struct Mixed {
+        a: u8,
+        b: i64,
+        c: i16,
+        d: u8,
+    }
+
+    static M: Mixed = Mixed { a: 1, b: 2, c: 3, d: 4 };
+    static T: (u8, i32) = (5, 6);
+
+    fn main() -> i64 {
+        let m = Mixed { a: 10, b: 20, c: 30, d: 40 };
+        let p = &m;
+        // 24 + 8 + 100 + 10 + 11
+        size_of::<Mixed>() + size_of::<(u8, i32)>() + p.a as i64 + p.b + p.c as i64 + p.d as i64
+            + M.a as i64 + M.b + M.c as i64 + M.d as i64 + T.0 as i64 + T.1 as i64
+    }
+    ";
+
+    assert_exit_code(s, 153);
+}
+
+#[test]
+fn bool_byte() {
+    let s = r"
+    struct Flags {
+        a: bool,
+        b: bool,
+        n: i64,
+    }
+
+    fn main() -> i64 {
+        let mut seen: [bool; 3] = [false, 1 < 2, false];
+        seen[2] = seen[1] == true;
+        let f = Flags { a: seen[2], b: !seen[0], n: 40 };
+        if f.a && f.b {
+            f.n + size_of::<Flags>() - size_of::<[bool; 3]>() - 11
+        } else {
+            0
+        }
+    }
+    ";
+
+    assert_exit_code(s, 42);
+
+    let s = r"
+    fn main() -> i64 {
+        let x: i64 = 1 < 2;
+        x
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types in `let`: expected I64, found Bool");
+}
+
+#[test]
+fn floats() {
+    let s = r#"
+    extern "C" fn snprintf(buf: &i64, n: i64, format: &i64, ...) -> i64;
+    extern "C" fn atof(s: &i64) -> f64;
+
+    const HALF: f64 = 1.0 / 2.0;
+    static S: [f64; 2] = [0.5, -1.5];
+
+    fn scale(x: f64, k: i64, y: f64) -> f64 {
+        x * (k as f64) + y
+    }
+
+    fn main() -> i64 {
+        let a = scale(1.5, 4, HALF);
+        let b = atof("2.25");
+        let buf: [i64; 4];
+        let n = snprintf(buf + 0, 32, "%.2f", a + b);
+        let c = atof(buf + 0);
+        let nan = 0.0 / 0.0;
+        if nan == nan || !(nan != nan) || nan < 1.0 || c < b || -c > 0.0 {
+            return 1;
+        }
+        (c * 4.0) as i64 + n + ((S[0] - S[1]) as i64)
+    }
+    "#;
+
+    // 8.75 * 4 + the 4 chars of "8.75" + 2
+    assert_exit_code(s, 41);
+}
+
+#[test]
+fn size_of() {
+    let s = r"
+    struct Pair {
+        a: i64,
+        b: [i64; 3],
+    }
+
+    enum Shape {
+        Dot,
+        Rect((i64, i64)),
+    }
+
+    const WORDS: i64 = size_of::<Pair>() / size_of::<i64>();
+
+    fn main() -> i64 {
+        let arr: [i64; size_of::<(i64, bool)>() / 8];
+        arr[1] = 2;
+        arr[1] + size_of::<&Pair>() + WORDS + size_of::<Shape>()
+    }
+    ";
+
+    assert_exit_code(s, 2 + 8 + 4 + 24);
+}
+
+#[test]
+fn closures() {
+    let s = r"
+    fn apply(f: fn(i64) -> i64, x: i64) -> i64 {
+        f(x)
+    }
+
+    fn main() -> i64 {
+        let mut y = 10;
+        let add = |x| x + y;
+        // captured by value
+        y = 100;
+
+        let pair = (1, 2);
+        let scale = |x: i64| -> i64 {
+            let inner = |z| z * pair.1 + y;
+            inner(x)
+        };
+        let three = || 3;
+        apply(add, 5) + apply(scale, 4) + three()
+    }
+    ";
+
+    assert_exit_code(s, 15 + 108 + 3);
+}
+
+#[test]
+fn default_params() {
+    let s = r"
+    const BASE: i64 = 100;
+
+    fn f(a: i64, b: i64 = 10, c: i64 = BASE * 2) -> i64 {
+        a + b + c
+    }
+
+    fn main() -> i64 {
+        f(1) - f(1, 2) + f(1, 2, 3) - 6
+    }
+    ";
+
+    assert_exit_code(s, 211 - 203 + 6 - 6);
+}
+
+#[test]
+fn default_params_trailing() {
+    let s = r"
+    fn f(a: i64 = 1, b: i64) -> i64 {
+        a + b
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "parameter `b` after a defaulted one needs a default too");
+}
+
+#[test]
+fn extern_variadic() {
+    let s = r#"
+    extern fn labs(x: i64) -> i64;
+    extern "C" fn syscall(n: i64, ...) -> i64;
+
+    fn main() -> i64 {
+        let a = 1 + labs(-4);
+        // exit
+        syscall(60, a * 10 + labs(-2));
+        0
+    }
+    "#;
+
+    assert_exit_code(s, 52);
+}
+
+#[test]
+fn methods() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    impl Point {
+        fn new(x: i64, y: i64) -> Self {
+            Self { x: x, y: y }
+        }
+
+        fn norm(self) -> i64 {
+            self.x * self.x + self.y * self.y
+        }
+
+        fn scale(&mut self, k: i64) {
+            self.x = self.x * k;
+            self.y = self.y * k;
+        }
+
+        fn doubled(self) -> Point {
+            let p = self;
+            p.scale(2);
+            p
+        }
+    }
+
+    enum Sign {
+        Neg,
+        Pos,
+    }
+
+    impl Sign {
+        fn apply(self, x: i64) -> i64 {
+            match self {
+                Sign::Neg => -x,
+                Sign::Pos => x,
+            }
+        }
+    }
+
+    fn main() -> i64 {
+        let p = Point::new(1, 2);
+        p.scale(3);
+        let q = &p;
+        // 45 + 20
+        Sign::Neg.apply(-q.norm()) + Point::new(1, 2).doubled().norm()
+    }
+    ";
+
+    assert_exit_code(s, 65);
+}
+
+#[test]
+fn traits() {
+    let s = r"
+    trait Shape {
+        fn area(&self) -> i64;
+        fn scaled(self, k: i64) -> Self;
+    }
+
+    struct Rect {
+        w: i64,
+        h: i64,
+    }
+
+    struct Square {
+        side: i64,
+    }
+
+    impl Shape for Rect {
+        fn area(&self) -> i64 {
+            self.w * self.h
+        }
+
+        fn scaled(self, k: i64) -> Rect {
+            Rect { w: self.w * k, h: self.h * k }
+        }
+    }
+
+    impl Shape for Square {
+        fn area(&self) -> i64 {
+            self.side * self.side
+        }
+
+        fn scaled(self, k: i64) -> Square {
+            Square { side: self.side * k }
+        }
+    }
+
+    fn double_area<T: Shape>(x: T) -> i64 {
+        let y = x.scaled(2);
+        area_of(&y) / 2
+    }
+
+    fn area_of<T: Shape>(x: &T) -> i64 {
+        x.area()
+    }
+
+    fn main() -> i64 {
+        let r = Rect { w: 2, h: 3 };
+        let s = Square { side: 4 };
+        // 12 + 32 + 16
+        double_area(r) + double_area(s) + area_of(&s)
+    }
+    ";
+
+    assert_exit_code(s, 60);
+}
+
+#[test]
+fn trait_not_implemented() {
+    let s = r"
+    trait Shape {
+        fn area(&self) -> i64;
+    }
+
+    struct Point {
+        x: i64,
+    }
+
+    fn area_of<T: Shape>(x: &T) -> i64 {
+        x.area()
+    }
+
+    fn main() -> i64 {
+        let p = Point { x: 1 };
+        area_of(&p)
+    }
+    ";
+
+    assert_compile_error(s, "does not implement `Shape`");
+}
+
+#[test]
+fn if_let() {
+    let s = r"
+    enum Opt {
+        None,
+        Some(i64),
+    }
+
+    fn get(o: Opt) -> i64 {
+        if let Opt::Some(x) = o {
+            x
+        } else if let 0 = 1 {
+            100
+        } else {
+            7
+        }
+    }
+
+    fn main() -> i64 {
+        let mut a = 0;
+        if let Opt::Some(x) = Opt::Some(3) {
+            a = a + x;
+        }
+        if let Opt::Some(x) = Opt::None {
+            a = a + 100;
+        }
+        a + get(Opt::Some(20)) + get(Opt::None)
+    }
+    ";
+
+    assert_exit_code(s, 3 + 20 + 7);
+}
+
+#[test]
+fn destructuring_let() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    struct Line {
+        from: Point,
+        to: Point,
+    }
+
+    fn pair() -> (i64, i64) {
+        (3, 4)
+    }
+
+    fn main() -> i64 {
+        let (a, b) = pair();
+        let Point { x, y: c } = Point { x: 10, y: 20 };
+        let line = Line { from: Point { x: 1, y: 2 }, to: Point { x: 5, y: 6 } };
+        let Line { from: Point { x: x1, .. }, to: Point { y: y2, .. } } = line;
+        let ((d, _), e) = ((30, 0), 40);
+        a * b + x + c + x1 + y2 + d + e
+    }
+    ";
+
+    assert_exit_code(s, 12 + 10 + 20 + 1 + 6 + 30 + 40);
+}
+
+#[test]
+fn labeled_loops() {
+    let s = r"
+    fn main() -> i64 {
+        let mut count = 0;
+        'rows: for i in 0..10 {
+            for j in 0..10 {
+                if j > i {
+                    continue 'rows;
+                }
+                if i == 5 {
+                    break 'rows;
+                }
+                count += 1;
+            }
+        }
+
+        let found = 'outer: loop {
+            let mut k = 0;
+            while true {
+                k += 1;
+                if k == 7 {
+                    break 'outer k * 2;
+                }
+            }
+        };
+        // 1 + 2 + 3 + 4 + 5 + 14
+        count + found + 'a' as i64 - 97
+    }
+    ";
+
+    assert_exit_code(s, 29);
+}
+
+#[test]
+fn undeclared_label() {
+    let s = r"
+    fn main() -> i64 {
+        loop {
+            break 'outer;
+        }
+        0
+    }
+    ";
+
+    assert_compile_error(s, "use of undeclared label `'outer`");
+}
+
+#[test]
+fn defer() {
+    let s = r"
+    static LOG: i64 = 0;
+
+    fn push(digit: i64) {
+        LOG = LOG * 10 + digit;
+    }
+
+    fn early(flag: bool) -> i64 {
+        defer push(1);
+        if flag {
+            defer push(2);
+            return LOG;
+        }
+        defer push(3);
+        LOG + 100
+    }
+
+    fn main() -> i64 {
+        // read before the deferred run
+        let a = early(true);
+        let b = early(false);
+        for i in 0..3 {
+            defer push(4);
+            if i == 1 {
+                continue;
+            }
+            if i == 2 {
+                break;
+            }
+        }
+        // LOG is 2131444
+        a * 100 + b - 100 + LOG % 100
+    }
+    ";
+
+    assert_exit_code(s, 21 + 44);
+}
+
+#[test]
+fn attributes() {
+    let s = r#"
+    extern fn forty() -> i64;
+
+    #[export_name("forty")]
+    #[inline]
+    fn answer() -> i64 {
+        40
+    }
+
+    #[no_mangle]
+    pub fn two() -> i64 {
+        2
+    }
+
+    fn main() -> i64 {
+        forty() + two()
+    }
+    "#;
+
+    assert_exit_code(s, 42);
+}
+
+#[test]
+fn assign_chain() {
+    let s = r"
+    struct Pair {
+        a: i64,
+        b: i64,
+    }
+
+    fn main() -> i64 {
+        let mut x = 0;
+        let mut y = 0;
+        let z = x = y = 4;
+        x += y -= 1;
+
+        let mut p = Pair { a: 0, b: 0 };
+        let q = Pair { a: 1, b: 2 };
+        let r = p = q;
+        x * 10 + y + z + r.b + p.a
+    }
+    ";
+
+    assert_exit_code(s, 80);
+}
+
+#[test]
+fn ternary() {
+    let s = r"
+    fn sign(x: i64) -> i64 {
+        return x < 0 ? -1 : x == 0 ? 0 : 1;
+    }
+
+    fn main() -> i64 {
+        let mut a = 0;
+        a = sign(-5) == -1 && sign(0) == 0 ? 10 : 20;
+        a + sign(7)
+    }
+    ";
+
+    assert_exit_code(s, 11);
+}
+
+#[test]
+fn string_literals() {
+    let s = r#"
+    extern "C" fn printf(format: &i64, ...) -> i64;
+    extern "C" fn strlen(s: &i64) -> i64;
+
+    fn main() -> i64 {
+        let n = printf("%s=%d\n", "answer", 42);
+        n + strlen("a\"b\\c\t")
+    }
+    "#;
+
+    assert_exit_code(s, 16);
+}
+
+#[test]
+fn for_scope() {
+    let s = r"
+    fn main() -> i64 {
+        let i = 100;
+        let mut n = 0;
+        for i in 0..4 {
+            // a copy of the counter, so the loop still runs 4 times
+            i = i * 10;
+            n = n + 1;
+        }
+        for j in 0..3 {
+            let f = || j;
+            n = n + f();
+        }
+        i + n
+    }
+    ";
+
+    assert_exit_code(s, 107);
+}
+
+#[test]
+fn semantic_errors() {
+    let s = r"
+    fn main() -> Foo {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "cannot find type `Foo`");
+
+    let s = r"
+    fn main() -> i64 {
+        loop {
+            break 'a;
+        }
+        0
+    }
+    ";
+
+    assert_compile_error(s, "use of undeclared label `'a`");
+
+    let s = r"
+    fn main() -> i64 {
+        break;
+        0
+    }
+    ";
+
+    assert_compile_error(s, "`break` or `continue` outside of a loop");
+
+    let s = r"
+    struct P {
+        x: i64,
+        y: i64,
+    }
+    fn main() -> i64 {
+        let p = P { x: 1 };
+        p.x
+    }
+    ";
+
+    assert_compile_error(s, "missing field `y` in initializer of P");
+
+    let s = r"
+    const A: bool = 1;
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types in const `A`: expected bool, found i64");
+
+    let s = r"
+    fn main() -> i64 {
+        match 1 {
+            true => 1,
+            _ => 0,
+        }
+    }
+    ";
+
+    assert_compile_error(s, "expected a pattern of i64, found a bool");
+
+    let s = r"
+    trait T {
+        fn f(&self) -> i64;
+    }
+    struct P {
+        x: i64,
+    }
+    impl T for P {}
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "missing method `P::f` of trait `T`");
+}
+
+#[test]
+fn unknown_field() {
+    let s = r"
+    struct P {
+        x: i64,
+    }
+    fn main() -> i64 {
+        let p = P { x: 1 };
+        p.y
+    }
+    ";
+
+    assert_compile_error(s, "no field `y` on struct P");
+
+    let s = r"
+    fn main() -> i64 {
+        let p = (1, 2);
+        p.2
+    }
+    ";
+
+    assert_compile_error(s, "no field `2` on (i64, i64)");
+
+    let s = r"
+    fn main() -> i64 {
+        let p = 1;
+        p.x
+    }
+    ";
+
+    assert_compile_error(s, "i64 has no fields");
+
+    let s = r"
+    struct P {
+        x: i64,
+        x: i64,
+    }
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "field `x` is already declared on struct P");
+}
+
+#[test]
+fn non_exhaustive_match() {
+    let s = r"
+    enum E {
+        A,
+        B(i64),
+        C,
+    }
+    fn main() -> i64 {
+        let e = E::A;
+        match e {
+            E::A => 1,
+        }
+    }
+    ";
+
+    assert_compile_error(s, "`E::B(_)`, `E::C` not covered");
+
+    let s = r"
+    fn main() -> i64 {
+        match 3 {
+            1 => 1,
+        }
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "non-exhaustive patterns in `match` on i64: `_` not covered",
+    );
+}
+
+#[test]
+fn no_method() {
+    let s = r"
+    fn main() -> i64 {
+        let x = 1;
+        x.foo()
+    }
+    ";
+
+    assert_compile_error(s, "no method `foo` on i64");
+
+    let s = r"
+    struct P {
+        x: i64,
+    }
+    fn main() -> i64 {
+        let p = P { x: 1 };
+        p.foo()
+    }
+    ";
+
+    assert_compile_error(s, "no method `foo` on P");
+
+    let s = r"
+    impl i64 {
+        fn f(&self) -> i64 {
+            0
+        }
+    }
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "cannot define methods on i64");
+}
+
+#[test]
+fn type_error_spans() {
+    let s = r"
+    fn main() -> i64 {
+        let a: i64 = true;
+        0
+    }
+    ";
+
+    assert_compile_error(s, " --> <console>:3:22");
+
+    let s = r"
+    fn main() -> i64 {
+        let x = 1;
+        x + true
+    }
+    ";
+
+    assert_compile_error(s, "4 |         x + true\n  |         ^^^^^^^^");
+}
+
+#[test]
+fn debug_info() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 1;
+        a + 2
+    }
+    ";
+
+    tools::assert_exit_code_of(&["-g", "-c", s], 3);
+}
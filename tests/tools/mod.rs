@@ -1,12 +1,33 @@
 use std::process::Command;
 
 pub(crate) fn assert_exit_code(s: &str, expected: i32) {
+    assert_exit_code_of(&["-c", s], expected);
+}
+
+/// `files` are written into a fresh directory and the first one is compiled
+pub(crate) fn assert_exit_code_files(files: &[(&str, &str)], expected: i32) {
+    let testcase_id: u32 = rand::random();
+    let dir = format!("./target/tmp/testcase{}", testcase_id);
+    for (name, s) in files.iter() {
+        let path = format!("{}/{}", dir, name);
+        std::fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+        std::fs::write(path, s).unwrap();
+    }
+
+    let root = format!("{}/{}", dir, files[0].0);
+    assert_exit_code_of(&["-f", root.as_str()], expected);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+pub(crate) fn assert_exit_code_of(input: &[&str], expected: i32) {
     let testcase_id: u32 = rand::random();
     let test_asm_name = format!("./target/tmp/testcase{}.s", testcase_id);
     let test_bin_name = format!("./target/tmp/testcase{}", testcase_id);
 
     let _ = Command::new("cargo")
-        .args(["run", "-q", "--", "-c", s, "-o", test_asm_name.as_str()])
+        .args(["run", "-q", "--"])
+        .args(input)
+        .args(["-o", test_asm_name.as_str()])
         .spawn()
         .expect("failed to execute sofac")
         .wait()
@@ -32,3 +53,27 @@ pub(crate) fn assert_exit_code(s: &str, expected: i32) {
     std::fs::remove_file(test_asm_name).unwrap();
     std::fs::remove_file(test_bin_name).unwrap();
 }
+
+pub(crate) fn assert_compile_error(s: &str, expected: &str) {
+    let output = Command::new("cargo")
+        .args(["run", "-q", "--", "-c", s, "-s"])
+        .output()
+        .expect("failed to execute sofac");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "compiled successfully");
+    // reported as a diagnostic rather than a crash
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(stderr.contains(expected), "{}", stderr);
+}
+
+pub(crate) fn assert_compile_warning(s: &str, expected: &str) {
+    let output = Command::new("cargo")
+        .args(["run", "-q", "--", "-c", s, "-s"])
+        .output()
+        .expect("failed to execute sofac");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains(expected), "{}", stderr);
+}